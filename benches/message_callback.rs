@@ -0,0 +1,138 @@
+//! Allocations and time spent by the tracker on each candles message, along with the
+//! allocations of flattening the updates by cloning them compared to moving them. Run
+//! with `cargo bench --bench message_callback`.
+
+use candle_watcher::sink::NullSink;
+use candle_watcher::TaskTracker;
+
+use cbadv::product::CandleUpdate;
+use cbadv::websocket::{CandlesEvent, Message, MessageCallback};
+use serde_json::json;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Messages passed to the tracker.
+const MESSAGES: u64 = 2_000;
+/// Products updated by each message.
+const PRODUCTS: usize = 50;
+/// Updates of each candle before the next one starts.
+const UPDATES_PER_CANDLE: u64 = 4;
+/// Start of the first candle, 2023-11-14 22:10 UTC.
+const FIRST_START: u64 = 1_700_000_000 / 300 * 300;
+
+/// Allocations made since the program started.
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator that counts the allocations made through it.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Candles message updating the candle starting at `start` of every product.
+fn message(start: u64, close: f64) -> Message {
+    let updates: Vec<serde_json::Value> = (0..PRODUCTS)
+        .map(|product| {
+            json!({
+                "product_id": format!("P{}-USD", product),
+                "start": start.to_string(),
+                "open": close.to_string(),
+                "high": close.to_string(),
+                "low": close.to_string(),
+                "close": close.to_string(),
+                "volume": "1",
+            })
+        })
+        .collect();
+
+    serde_json::from_value(json!({
+        "channel": "candles",
+        "client_id": "",
+        "timestamp": "2023-11-14T22:13:20Z",
+        "sequence_num": 0,
+        "events": [{ "type": "update", "candles": updates }],
+    }))
+    .expect("candles message")
+}
+
+/// Messages of the benchmark, the same each time they are created.
+fn messages() -> Vec<Message> {
+    (0..MESSAGES)
+        .map(|i| message(FIRST_START + i / UPDATES_PER_CANDLE * 300, 100.0 + i as f64))
+        .collect()
+}
+
+/// Allocations made by `flatten` combining the updates of each message's events.
+fn flatten_allocations<F>(messages: Vec<Message>, flatten: F) -> usize
+where
+    F: Fn(Vec<CandlesEvent>) -> Vec<CandleUpdate>,
+{
+    let events: Vec<Vec<CandlesEvent>> = messages
+        .into_iter()
+        .map(|message| match message {
+            Message::Candles(value) => value.events,
+            _ => vec![],
+        })
+        .collect();
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    for ev in events {
+        black_box(flatten(ev));
+    }
+    ALLOCATIONS.load(Ordering::Relaxed) - allocations
+}
+
+fn main() {
+    // Borrowing the events and cloning each update, as messages used to be flattened.
+    let cloned = flatten_allocations(messages(), |ev| {
+        ev.iter().flat_map(|c| c.candles.clone()).collect()
+    });
+    // Moving the updates out of the events, as `message_callback` does now.
+    let moved = flatten_allocations(messages(), |ev| {
+        ev.into_iter().flat_map(|c| c.candles).collect()
+    });
+    println!(
+        "flattening {} updates per message: {:.1} allocations cloned, {:.1} moved",
+        PRODUCTS,
+        cloned as f64 / MESSAGES as f64,
+        moved as f64 / MESSAGES as f64
+    );
+
+    let messages = messages();
+
+    let mut tracker = TaskTracker::with_sink(NullSink);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for message in messages {
+        tracker.message_callback(Ok(message));
+    }
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!(
+        "{} messages of {} updates: {:?} total, {:.2?} per message, {:.1} allocations per message",
+        MESSAGES,
+        PRODUCTS,
+        elapsed,
+        elapsed / MESSAGES as u32,
+        allocations as f64 / MESSAGES as f64
+    );
+    println!(
+        "processed {} updates, completed {} candles",
+        tracker.processed(),
+        tracker.completed()
+    );
+}
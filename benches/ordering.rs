@@ -0,0 +1,101 @@
+//! Time spent by the tracker on 10k candle updates arriving in order and out of order,
+//! run with `cargo bench --bench ordering`. Updates in order are not sorted.
+
+use candle_watcher::sink::NullSink;
+use candle_watcher::TaskTracker;
+
+use cbadv::websocket::{Message, MessageCallback};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Candle updates passed to the tracker.
+const UPDATES: u64 = 10_000;
+/// Updates within each message.
+const UPDATES_PER_MESSAGE: u64 = 20;
+/// Products the updates are spread over.
+const PRODUCTS: u64 = 5;
+/// Start of the first candle, 2023-11-14 22:10 UTC.
+const FIRST_START: u64 = 1_700_000_000 / 300 * 300;
+/// Times each ordering is run, the fastest is reported.
+const RUNS: usize = 10;
+
+/// Candles message holding the updates numbered `updates`, each product receiving a
+/// newer candle every few updates.
+fn message(updates: impl Iterator<Item = u64>) -> Message {
+    let candles: Vec<serde_json::Value> = updates
+        .map(|i| {
+            let close = (100 + i % 7).to_string();
+            json!({
+                "product_id": format!("P{}-USD", i % PRODUCTS),
+                "start": (FIRST_START + i / PRODUCTS / 2 * 300).to_string(),
+                "open": close,
+                "high": close,
+                "low": close,
+                "close": close,
+                "volume": "1",
+            })
+        })
+        .collect();
+
+    serde_json::from_value(json!({
+        "channel": "candles",
+        "client_id": "",
+        "timestamp": "2023-11-14T22:13:20Z",
+        "sequence_num": 0,
+        "events": [{ "type": "update", "candles": candles }],
+    }))
+    .expect("candles message")
+}
+
+/// Messages holding every update, each message in reverse when `reversed`.
+fn messages(reversed: bool) -> Vec<Message> {
+    (0..UPDATES / UPDATES_PER_MESSAGE)
+        .map(|n| {
+            let updates = n * UPDATES_PER_MESSAGE..(n + 1) * UPDATES_PER_MESSAGE;
+            match reversed {
+                true => message(updates.rev()),
+                false => message(updates),
+            }
+        })
+        .collect()
+}
+
+/// Fastest time the tracker took for the messages, along with the candles completed.
+fn run(reversed: bool) -> (Duration, usize) {
+    let mut fastest = Duration::MAX;
+    let mut completed = 0;
+    for _ in 0..RUNS {
+        let messages = messages(reversed);
+        let mut tracker = TaskTracker::with_sink(NullSink);
+        let started = Instant::now();
+        for message in messages {
+            tracker.message_callback(Ok(message));
+        }
+        fastest = fastest.min(started.elapsed());
+        completed = tracker.completed();
+    }
+    (fastest, completed)
+}
+
+fn main() {
+    let (in_order, completed) = run(false);
+    let (out_of_order, sorted_completed) = run(true);
+    assert_eq!(
+        completed, sorted_completed,
+        "ordering changed the candles completed"
+    );
+
+    println!(
+        "{} updates in order: {:?}, {:.0} ns per update",
+        UPDATES,
+        in_order,
+        in_order.as_nanos() as f64 / UPDATES as f64
+    );
+    println!(
+        "{} updates out of order: {:?}, {:.0} ns per update",
+        UPDATES,
+        out_of_order,
+        out_of_order.as_nanos() as f64 / UPDATES as f64
+    );
+    println!("completed {} candles", completed);
+}
@@ -0,0 +1,172 @@
+//! Rolls completed candles up into higher timeframes.
+
+use crate::calendar::Calendar;
+
+use cbadv::product::Candle;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Timeframes that completed candles can be aggregated into.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Timeframe {
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "30m")]
+    ThirtyMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "2h")]
+    TwoHours,
+    #[serde(rename = "6h")]
+    SixHours,
+    /// Starts at midnight of the configured timezone, UTC by default.
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl Timeframe {
+    /// Length of the timeframe in seconds.
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Timeframe::FiveMinutes => 300,
+            Timeframe::FifteenMinutes => 900,
+            Timeframe::ThirtyMinutes => 1_800,
+            Timeframe::OneHour => 3_600,
+            Timeframe::TwoHours => 7_200,
+            Timeframe::SixHours => 21_600,
+            Timeframe::OneDay => 86_400,
+        }
+    }
+
+    /// Start of the timeframe period that contains `timestamp`. Days start at the
+    /// local midnight of the calendar.
+    pub fn boundary(&self, timestamp: u64, calendar: &Calendar) -> u64 {
+        match self {
+            Timeframe::OneDay => calendar.day_start(timestamp),
+            _ => timestamp - timestamp % self.seconds(),
+        }
+    }
+
+    /// End of the period starting at `boundary`, days vary in length across daylight
+    /// saving changes.
+    pub fn end(&self, boundary: u64, calendar: &Calendar) -> u64 {
+        match self {
+            Timeframe::OneDay => calendar.next_day_start(boundary),
+            _ => boundary + self.seconds(),
+        }
+    }
+}
+
+impl fmt::Display for Timeframe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Timeframe::FiveMinutes => "5m",
+            Timeframe::FifteenMinutes => "15m",
+            Timeframe::ThirtyMinutes => "30m",
+            Timeframe::OneHour => "1h",
+            Timeframe::TwoHours => "2h",
+            Timeframe::SixHours => "6h",
+            Timeframe::OneDay => "1d",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Aggregates completed candles of a single product into a higher timeframe.
+pub struct Aggregator {
+    /// Timeframe being produced.
+    timeframe: Timeframe,
+    /// Granularity of the candles being consumed, in seconds.
+    interval: u64,
+    /// Decides where days start.
+    calendar: Calendar,
+    /// Aggregated candle currently being built.
+    current: Option<Candle>,
+}
+
+impl Aggregator {
+    /// Creates an aggregator consuming candles of `interval` seconds, days start at
+    /// UTC midnight.
+    pub fn new(timeframe: Timeframe, interval: u64) -> Self {
+        Self::with_calendar(timeframe, interval, Calendar::default())
+    }
+
+    /// Creates an aggregator whose days start at the midnight of `calendar`.
+    pub fn with_calendar(timeframe: Timeframe, interval: u64, calendar: Calendar) -> Self {
+        Self {
+            timeframe,
+            interval,
+            calendar,
+            current: None,
+        }
+    }
+
+    /// Timeframe being produced.
+    pub fn timeframe(&self) -> Timeframe {
+        self.timeframe
+    }
+
+    /// Partially built candle, if there is one.
+    pub fn partial(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    /// Continues building `candle`, such as one restored after a restart.
+    pub fn resume(&mut self, candle: Candle) {
+        self.current = Some(candle);
+    }
+
+    /// Removes the partially built candle, if there is one.
+    pub fn flush(&mut self) -> Option<Candle> {
+        self.current.take()
+    }
+
+    /// Adds a completed candle, returning any aggregated candle that completed.
+    pub fn update(&mut self, candle: &Candle) -> Vec<Candle> {
+        let mut completed: Vec<Candle> = vec![];
+        let boundary = self.timeframe.boundary(candle.start, &self.calendar);
+
+        match self.current.as_ref().map(|c| c.start.cmp(&boundary)) {
+            Some(Ordering::Equal) => {
+                // Same period, extend the running candle.
+                if let Some(current) = self.current.as_mut() {
+                    current.high = current.high.max(candle.high);
+                    current.low = current.low.min(candle.low);
+                    current.close = candle.close;
+                    current.volume += candle.volume;
+                }
+            }
+            Some(Ordering::Greater) => {
+                // Older than the period being built, nothing to update.
+                return completed;
+            }
+            _ => {
+                // New period, the previous one rolled over without its final candle.
+                if let Some(old) = self.current.take() {
+                    completed.push(old);
+                }
+
+                self.current = Some(Candle {
+                    start: boundary,
+                    low: candle.low,
+                    high: candle.high,
+                    open: candle.open,
+                    close: candle.close,
+                    volume: candle.volume,
+                });
+            }
+        }
+
+        // Eject once the final candle of the period has been consumed.
+        if candle.start + self.interval >= self.timeframe.end(boundary, &self.calendar) {
+            if let Some(done) = self.current.take() {
+                completed.push(done);
+            }
+        }
+
+        completed
+    }
+}
@@ -0,0 +1,135 @@
+//! Alerts for completed closes crossing configured price levels.
+
+use cbadv::product::Candle;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::warn;
+
+/// Price levels that trigger an alert when crossed.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    /// Alert when the close rises above this price.
+    pub above: Option<f64>,
+    /// Alert when the close falls below this price.
+    pub below: Option<f64>,
+}
+
+/// Direction of a crossing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    /// Close rose above the `above` threshold.
+    Above,
+    /// Close fell below the `below` threshold.
+    Below,
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertKind::Above => write!(f, "above"),
+            AlertKind::Below => write!(f, "below"),
+        }
+    }
+}
+
+/// A completed close crossed a threshold.
+#[derive(Debug, Clone)]
+pub struct Alert {
+    /// Product that crossed the threshold.
+    pub product_id: String,
+    /// Direction of the crossing.
+    pub kind: AlertKind,
+    /// Close of the candle that crossed.
+    pub price: f64,
+    /// Start of the candle that crossed.
+    pub candle_start: u64,
+}
+
+/// Receives alerts as they are triggered.
+pub trait AlertSink {
+    /// Called once for each crossing.
+    fn on_alert(&mut self, alert: &Alert);
+}
+
+/// Prints each alert.
+pub struct LogAlertSink;
+
+impl AlertSink for LogAlertSink {
+    fn on_alert(&mut self, alert: &Alert) {
+        warn!(
+            product_id = %alert.product_id,
+            start = alert.candle_start,
+            close = alert.price,
+            "ALERT close crossed {}.",
+            alert.kind
+        );
+    }
+}
+
+/// Checks completed closes against the thresholds of each product.
+pub struct AlertTracker {
+    /// Thresholds for each product.
+    thresholds: HashMap<String, Thresholds>,
+    /// Previous completed close for each product, determines which side it was on.
+    closes: HashMap<String, f64>,
+    /// Receives triggered alerts.
+    sink: Box<dyn AlertSink + Send>,
+}
+
+impl AlertTracker {
+    /// Creates a tracker passing alerts to `sink`.
+    pub fn new(thresholds: HashMap<String, Thresholds>, sink: Box<dyn AlertSink + Send>) -> Self {
+        Self {
+            thresholds,
+            closes: HashMap::new(),
+            sink,
+        }
+    }
+
+    /// Checks a completed candle, alerting once when its close crosses a threshold.
+    /// Closes that remain beyond a threshold do not alert again.
+    pub fn check(&mut self, product_id: &str, candle: &Candle) {
+        let thresholds = match self.thresholds.get(product_id) {
+            Some(thresholds) => *thresholds,
+            None => return,
+        };
+
+        let prev = match self.closes.insert(product_id.to_string(), candle.close) {
+            Some(prev) => prev,
+            // Nothing to compare against yet.
+            None => return,
+        };
+
+        let mut alert = |kind: AlertKind| {
+            self.sink.on_alert(&Alert {
+                product_id: product_id.to_string(),
+                kind,
+                price: candle.close,
+                candle_start: candle.start,
+            })
+        };
+
+        if let Some(above) = thresholds.above {
+            if prev <= above && candle.close > above {
+                alert(AlertKind::Above);
+            }
+        }
+        if let Some(below) = thresholds.below {
+            if prev >= below && candle.close < below {
+                alert(AlertKind::Below);
+            }
+        }
+    }
+
+    /// Forgets the previous close of a product.
+    pub fn reset(&mut self, product_id: &str) {
+        self.closes.remove(product_id);
+    }
+}
+
+impl Default for AlertTracker {
+    fn default() -> Self {
+        Self::new(HashMap::new(), Box::new(LogAlertSink))
+    }
+}
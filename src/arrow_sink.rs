@@ -0,0 +1,262 @@
+//! Batches completed candles into Arrow record batches for in-process analytics.
+
+use crate::sink::{CandleInfo, CandleSink};
+
+use arrow::array::{ArrayBuilder, ArrayRef, Float64Builder, Int64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use cbadv::product::Candle;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self as tokio_mpsc, UnboundedReceiver};
+use tracing::error;
+
+/// Columns of a batch of candles, the same as each Parquet file.
+pub fn candle_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("start", DataType::Int64, false),
+        Field::new("open", DataType::Float64, false),
+        Field::new("high", DataType::Float64, false),
+        Field::new("low", DataType::Float64, false),
+        Field::new("close", DataType::Float64, false),
+        Field::new("volume", DataType::Float64, false),
+    ]))
+}
+
+/// Builds a batch of candles with the [`candle_schema`].
+pub fn candle_batch(candles: &[Candle]) -> Result<RecordBatch, ArrowError> {
+    let mut columns = Columns::with_capacity(candles.len());
+    for candle in candles {
+        columns.append(candle);
+    }
+    columns.finish(&candle_schema())
+}
+
+/// Column builders of a batch being filled.
+struct Columns {
+    start: Int64Builder,
+    open: Float64Builder,
+    high: Float64Builder,
+    low: Float64Builder,
+    close: Float64Builder,
+    volume: Float64Builder,
+}
+
+impl Columns {
+    /// Creates empty columns with room for `capacity` candles.
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            start: Int64Builder::with_capacity(capacity),
+            open: Float64Builder::with_capacity(capacity),
+            high: Float64Builder::with_capacity(capacity),
+            low: Float64Builder::with_capacity(capacity),
+            close: Float64Builder::with_capacity(capacity),
+            volume: Float64Builder::with_capacity(capacity),
+        }
+    }
+
+    /// Appends a row for the candle.
+    fn append(&mut self, candle: &Candle) {
+        self.start.append_value(candle.start as i64);
+        self.open.append_value(candle.open);
+        self.high.append_value(candle.high);
+        self.low.append_value(candle.low);
+        self.close.append_value(candle.close);
+        self.volume.append_value(candle.volume);
+    }
+
+    /// Rows appended since the last batch.
+    fn len(&self) -> usize {
+        self.start.len()
+    }
+
+    /// Takes the appended rows as a batch, leaving the columns empty.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.start.finish()),
+            Arc::new(self.open.finish()),
+            Arc::new(self.high.finish()),
+            Arc::new(self.low.finish()),
+            Arc::new(self.close.finish()),
+            Arc::new(self.volume.finish()),
+        ];
+        RecordBatch::try_new(Arc::clone(schema), columns)
+    }
+}
+
+/// Work sent to the batching thread.
+enum Command {
+    /// Appends a completed candle of a product.
+    Candle(String, Candle),
+    /// Hands over every partial batch, signaling once complete.
+    Flush(Sender<()>),
+}
+
+/// Accumulates completed candles of each product into column builders, handing each
+/// product a batch once it reaches `batch_size` rows or `interval` after its first
+/// row. Batches hold a single product and share the schema of the Parquet files.
+pub struct ArrowSink {
+    /// Queues work for the batching thread, `None` once shutting down.
+    commands: Option<Sender<Command>>,
+    /// Thread building the batches.
+    batcher: Option<JoinHandle<()>>,
+}
+
+impl ArrowSink {
+    /// Creates a sink passing each batch, with its product, to `on_batch`. The
+    /// callback is run on the batching thread.
+    pub fn new<F>(batch_size: usize, interval: Duration, on_batch: F) -> Self
+    where
+        F: FnMut(&str, RecordBatch) + Send + 'static,
+    {
+        let (commands, receiver) = mpsc::channel();
+        let batch_size = batch_size.max(1);
+        let batcher = thread::spawn(move || batch_loop(batch_size, interval, receiver, on_batch));
+        Self {
+            commands: Some(commands),
+            batcher: Some(batcher),
+        }
+    }
+
+    /// Creates a sink along with a receiver of each batch and its product.
+    pub fn with_channel(
+        batch_size: usize,
+        interval: Duration,
+    ) -> (Self, UnboundedReceiver<(String, RecordBatch)>) {
+        let (sender, receiver) = tokio_mpsc::unbounded_channel();
+        let sink = Self::new(batch_size, interval, move |product_id, batch| {
+            // The receiver being dropped only means nobody wants the batches.
+            let _ = sender.send((product_id.to_string(), batch));
+        });
+        (sink, receiver)
+    }
+
+    /// Sends work to the batching thread.
+    fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            if commands.send(command).is_err() {
+                error!("Arrow batcher stopped, candle dropped.");
+            }
+        }
+    }
+}
+
+impl CandleSink for ArrowSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        // Only native candles that completed, batches would otherwise mix granularities.
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        self.send(Command::Candle(product_id.to_string(), candle.clone()));
+    }
+
+    fn flush(&mut self) {
+        let (done, wait) = mpsc::channel();
+        self.send(Command::Flush(done));
+        let _ = wait.recv();
+    }
+}
+
+impl Drop for ArrowSink {
+    fn drop(&mut self) {
+        // Closing the channel hands over the partial batches and stops the thread.
+        self.commands.take();
+        if let Some(batcher) = self.batcher.take() {
+            let _ = batcher.join();
+        }
+    }
+}
+
+/// Columns of a product along with when its first row was appended.
+struct Pending {
+    columns: Columns,
+    since: Instant,
+}
+
+/// Builds batches from queued candles until the sink is dropped.
+fn batch_loop<F>(
+    batch_size: usize,
+    interval: Duration,
+    commands: Receiver<Command>,
+    mut on_batch: F,
+) where
+    F: FnMut(&str, RecordBatch),
+{
+    let schema = candle_schema();
+    let mut pending: HashMap<String, Pending> = HashMap::new();
+
+    loop {
+        // Wake for the product that has waited the longest.
+        let wait = pending
+            .values()
+            .filter(|p| p.columns.len() > 0)
+            .map(|p| (p.since + interval).saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(interval);
+
+        match commands.recv_timeout(wait) {
+            Ok(Command::Candle(product_id, candle)) => {
+                let product = pending
+                    .entry(product_id.clone())
+                    .or_insert_with(|| Pending {
+                        columns: Columns::with_capacity(batch_size),
+                        since: Instant::now(),
+                    });
+                if product.columns.len() == 0 {
+                    product.since = Instant::now();
+                }
+
+                product.columns.append(&candle);
+                if product.columns.len() >= batch_size {
+                    emit(&schema, &product_id, product, &mut on_batch);
+                }
+            }
+            Ok(Command::Flush(done)) => {
+                emit_all(&schema, &mut pending, &mut on_batch, |_| true);
+                let _ = done.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                emit_all(&schema, &mut pending, &mut on_batch, |p| {
+                    now.duration_since(p.since) >= interval
+                });
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                emit_all(&schema, &mut pending, &mut on_batch, |_| true);
+                return;
+            }
+        }
+    }
+}
+
+/// Hands over the batches of every product with rows that is `due`.
+fn emit_all<F>(
+    schema: &SchemaRef,
+    pending: &mut HashMap<String, Pending>,
+    on_batch: &mut F,
+    due: impl Fn(&Pending) -> bool,
+) where
+    F: FnMut(&str, RecordBatch),
+{
+    for (product_id, product) in pending.iter_mut() {
+        if product.columns.len() > 0 && due(product) {
+            emit(schema, product_id, product, on_batch);
+        }
+    }
+}
+
+/// Hands over the rows of a product as a batch.
+fn emit<F>(schema: &SchemaRef, product_id: &str, product: &mut Pending, on_batch: &mut F)
+where
+    F: FnMut(&str, RecordBatch),
+{
+    match product.columns.finish(schema) {
+        Ok(batch) => on_batch(product_id, batch),
+        Err(err) => error!(product_id, "unable to build Arrow batch: {}", err),
+    }
+}
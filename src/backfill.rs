@@ -0,0 +1,220 @@
+//! Backfills missing candles using the REST API.
+
+use crate::observer::{CandleObserver, LogObserver};
+use crate::ratelimit::RateLimiter;
+use crate::sink::{CandleSink, StdoutSink};
+use crate::TrackerHandle;
+
+use cbadv::product::{Candle, ProductCandleQuery};
+use cbadv::rest::Client as RestClient;
+use futures::stream::{self, StreamExt};
+use std::sync::Arc;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{error, info, warn};
+
+/// Maximum amount of candles Coinbase returns for a single request.
+const MAX_CANDLES_PER_REQUEST: u64 = 300;
+/// Maximum products fetched at once during warmup and refreshes, the limiter paces
+/// the requests.
+const WARMUP_CONCURRENCY: usize = 5;
+
+/// Range of candles missing from a products series.
+#[derive(Debug, Clone)]
+pub struct Gap {
+    /// Product the candles are missing for.
+    pub product_id: String,
+    /// Start of the first missing candle.
+    pub start: u64,
+    /// Start of the first candle received after the gap.
+    pub end: u64,
+}
+
+/// Observer that logs gaps and queues them to be backfilled.
+pub struct BackfillObserver {
+    gaps: UnboundedSender<Gap>,
+}
+
+impl BackfillObserver {
+    /// Creates an observer that sends gaps to the backfiller over `gaps`.
+    pub fn new(gaps: UnboundedSender<Gap>) -> Self {
+        Self { gaps }
+    }
+}
+
+impl CandleObserver for BackfillObserver {
+    fn on_gap(&mut self, product_id: &str, expected_start: u64, actual_start: u64) {
+        LogObserver.on_gap(product_id, expected_start, actual_start);
+
+        let gap = Gap {
+            product_id: product_id.to_string(),
+            start: expected_start,
+            end: actual_start,
+        };
+        if self.gaps.send(gap).is_err() {
+            warn!(product_id, "backfill is not running, gap ignored.");
+        }
+    }
+
+    fn on_first_candle(&mut self, product_id: &str, candle: &Candle) {
+        LogObserver.on_first_candle(product_id, candle);
+    }
+
+    fn on_candle_open(&mut self, product_id: &str, start: u64, open: f64) {
+        LogObserver.on_candle_open(product_id, start, open);
+    }
+
+    fn on_late_arrival(&mut self, product_id: &str, current_start: u64, late: &Candle) {
+        LogObserver.on_late_arrival(product_id, current_start, late);
+    }
+
+    fn on_late_candle(&mut self, product_id: &str, candle: &Candle) {
+        LogObserver.on_late_candle(product_id, candle);
+    }
+}
+
+/// Fetches historic candles and replays them into the tracker.
+pub struct Backfiller<S: CandleSink = StdoutSink> {
+    /// Client used to obtain candles.
+    client: Arc<RestClient>,
+    /// Budget of requests shared with every other user of the API.
+    limiter: RateLimiter,
+    /// Tracker the candles are replayed into.
+    tracker: TrackerHandle<S>,
+}
+
+impl<S: CandleSink> Clone for Backfiller<S> {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            limiter: self.limiter.clone(),
+            tracker: self.tracker.clone(),
+        }
+    }
+}
+
+impl<S: CandleSink + Send + 'static> Backfiller<S> {
+    /// Creates a backfiller replaying candles into `tracker`, requests draw from the
+    /// shared `limiter`.
+    pub fn new(client: RestClient, limiter: RateLimiter, tracker: TrackerHandle<S>) -> Self {
+        Self {
+            client: Arc::new(client),
+            limiter,
+            tracker,
+        }
+    }
+
+    /// Seeds the tracker with the last `lookback_minutes` of candles for each product.
+    /// Seeded candles update the aggregators but are not recorded as finished.
+    pub async fn warmup(&self, products: &[String], lookback_minutes: u64) {
+        let now = self.tracker.now();
+        let start = now.saturating_sub(lookback_minutes * 60);
+        let end = now + self.tracker.granularity().seconds();
+
+        info!(
+            "Warming up {} products with {} minutes of candles.",
+            products.len(),
+            lookback_minutes
+        );
+
+        let mut fetches = stream::iter(products)
+            .map(|product_id| async move {
+                let candles = self.fetch(product_id, start, end).await;
+                (product_id, candles)
+            })
+            .buffer_unordered(WARMUP_CONCURRENCY);
+
+        let mut seeded: usize = 0;
+        while let Some((product_id, candles)) = fetches.next().await {
+            seeded += self.tracker.seed(product_id, candles);
+        }
+        info!("Warmup complete, seeded {} candles.", seeded);
+    }
+
+    /// Refreshes the in-progress candle of each product from the REST API after the
+    /// connection was lost, along with any candles that started since.
+    pub async fn refresh(&self, products: &[String]) {
+        let now = self.tracker.now();
+        let end = now + self.tracker.granularity().seconds();
+        let mut fetches = stream::iter(products)
+            .map(|product_id| async move {
+                let candles = match self.tracker.in_progress_start(product_id) {
+                    Some(start) => self.fetch(product_id, start, end).await,
+                    None => vec![],
+                };
+                (product_id, candles)
+            })
+            .buffer_unordered(WARMUP_CONCURRENCY);
+
+        let mut refreshed: usize = 0;
+        while let Some((product_id, candles)) = fetches.next().await {
+            if !candles.is_empty() {
+                refreshed += self.tracker.refresh(product_id, candles);
+            }
+        }
+        info!("Refreshed {} candles missed while disconnected.", refreshed);
+    }
+
+    /// Backfills each gap as it is received, runs until the sender is dropped.
+    pub async fn run(self, mut gaps: UnboundedReceiver<Gap>) {
+        while let Some(gap) = gaps.recv().await {
+            self.backfill(&gap.product_id, gap.start, gap.end).await;
+        }
+    }
+
+    /// Fetches the candles starting within `start..end` and passes them through the
+    /// trackers completion path in chronological order.
+    pub async fn backfill(&self, product_id: &str, start: u64, end: u64) {
+        let candles = self.fetch(product_id, start, end).await;
+        if candles.is_empty() {
+            return;
+        }
+
+        let replayed = self.tracker.replay(product_id, candles);
+        info!(
+            product_id,
+            "backfilled {} candles from {} to {}.", replayed, start, end
+        );
+    }
+
+    /// Obtains the candles starting within `start..end`, oldest first. The range is
+    /// split into multiple requests to respect the per-request candle limit.
+    pub async fn fetch(&self, product_id: &str, start: u64, end: u64) -> Vec<Candle> {
+        let mut candles: Vec<Candle> = vec![];
+        let granularity = self.tracker.granularity();
+        let step = MAX_CANDLES_PER_REQUEST * granularity.seconds();
+
+        let mut chunk_start = start;
+        let mut throttled: u32 = 1;
+        while chunk_start < end {
+            let chunk_end = (chunk_start + step).min(end);
+            let query = ProductCandleQuery {
+                start: chunk_start,
+                end: chunk_end,
+                granularity: granularity.to_string(),
+            };
+
+            self.limiter.acquire().await;
+            match self.client.product.candles(product_id, &query).await {
+                Ok(mut chunk) => candles.append(&mut chunk),
+                Err(err) => {
+                    let err = err.to_string();
+                    if self.limiter.throttled(&err, throttled) {
+                        throttled += 1;
+                        continue;
+                    }
+                    error!(product_id, "unable to obtain candles: {}", err);
+                    break;
+                }
+            }
+
+            chunk_start = chunk_end;
+            throttled = 1;
+        }
+
+        // Only keep candles within the range, sorted and without overlaps between chunks.
+        candles.retain(|c| c.start >= start && c.start < end);
+        candles.sort_by(|a, b| a.start.cmp(&b.start));
+        candles.dedup_by_key(|c| c.start);
+        candles
+    }
+}
@@ -0,0 +1,139 @@
+//! Day boundaries and displayed times in a configurable timezone. Timestamps are
+//! always stored as UTC, only days and formatting depend on the zone.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Seconds within a UTC day.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Measures days in a timezone, UTC unless one is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Calendar {
+    /// Zone days start in, `None` for UTC.
+    timezone: Option<Tz>,
+}
+
+impl Calendar {
+    /// Creates a calendar for `timezone`, `None` uses UTC.
+    pub fn new(timezone: Option<Tz>) -> Self {
+        Self { timezone }
+    }
+
+    /// Days since the Unix epoch of the local date containing `timestamp`.
+    pub fn day(&self, timestamp: u64) -> i64 {
+        match self.timezone {
+            None => (timestamp / SECONDS_PER_DAY) as i64,
+            Some(tz) => {
+                let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+                (local(tz, timestamp).date_naive() - epoch).num_days()
+            }
+        }
+    }
+
+    /// Unix time of the local midnight starting the day that contains `timestamp`.
+    /// Where midnight is skipped by a daylight saving change, the day starts at the
+    /// first valid local time.
+    pub fn day_start(&self, timestamp: u64) -> u64 {
+        let tz = match self.timezone {
+            None => return timestamp - timestamp % SECONDS_PER_DAY,
+            Some(tz) => tz,
+        };
+
+        let midnight = local(tz, timestamp)
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let mut time = midnight;
+        loop {
+            match tz.from_local_datetime(&time) {
+                LocalResult::Single(start) | LocalResult::Ambiguous(start, _) => {
+                    return start.timestamp().max(0) as u64;
+                }
+                // Within a gap, no zone skips more than a few hours.
+                LocalResult::None => time += Duration::minutes(15),
+            }
+        }
+    }
+
+    /// Unix time the local day after the one containing `timestamp` starts. Days are
+    /// 23 or 25 hours long across daylight saving changes.
+    pub fn next_day_start(&self, timestamp: u64) -> u64 {
+        let start = self.day_start(timestamp);
+        // Past the end of even a 25 hour day, but before the end of the next.
+        self.day_start(start + SECONDS_PER_DAY + SECONDS_PER_DAY / 4)
+    }
+
+    /// Formats `timestamp` for display. Without a timezone the Unix time is shown.
+    pub fn format(&self, timestamp: u64) -> String {
+        match self.timezone {
+            None => timestamp.to_string(),
+            Some(tz) => local(tz, timestamp)
+                .format("%Y-%m-%d %H:%M:%S %Z")
+                .to_string(),
+        }
+    }
+}
+
+/// Local time of a Unix timestamp within `tz`.
+fn local(tz: Tz, timestamp: u64) -> DateTime<Tz> {
+    let utc = DateTime::<Utc>::from_timestamp(timestamp as i64, 0).unwrap_or_default();
+    utc.with_timezone(&tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::{New_York, Sao_Paulo};
+
+    /// 2024-01-15 00:00 in New York, 05:00 UTC.
+    const JAN_15: u64 = 1_705_294_800;
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        let calendar = Calendar::new(Some(New_York));
+        assert_eq!(calendar.day_start(JAN_15 + 3_600), JAN_15);
+        assert_eq!(calendar.next_day_start(JAN_15), JAN_15 + 86_400);
+        assert_eq!(calendar.day(JAN_15), calendar.day(JAN_15 + 86_399));
+        assert_eq!(calendar.day(JAN_15 + 86_400), calendar.day(JAN_15) + 1);
+
+        // Midnight UTC is still the evening before in New York.
+        assert_eq!(calendar.day(1_705_276_800), calendar.day(JAN_15 - 1));
+        assert_eq!(calendar.day(JAN_15 - 1) + 1, calendar.day(JAN_15));
+    }
+
+    #[test]
+    fn days_across_daylight_saving_changes() {
+        let calendar = Calendar::new(Some(New_York));
+
+        // 2024-03-10 springs forward at 02:00, the day lasts 23 hours.
+        let spring = 1_710_046_800;
+        assert_eq!(calendar.next_day_start(spring), spring + 23 * 3_600);
+        assert_eq!(calendar.day_start(spring + 23 * 3_600 - 1), spring);
+        assert_eq!(calendar.day(spring + 23 * 3_600), calendar.day(spring) + 1);
+
+        // 2024-11-03 falls back at 02:00, the day lasts 25 hours.
+        let fall = 1_730_606_400;
+        assert_eq!(calendar.next_day_start(fall), fall + 25 * 3_600);
+        assert_eq!(calendar.day(fall + 24 * 3_600), calendar.day(fall));
+    }
+
+    #[test]
+    fn days_start_at_the_first_time_after_a_skipped_midnight() {
+        // 2018-11-04 in São Paulo skipped from 00:00 to 01:00, 03:00 UTC.
+        let calendar = Calendar::new(Some(Sao_Paulo));
+        let start = 1_541_300_400;
+        assert_eq!(calendar.day_start(start + 3_600), start);
+        assert_eq!(calendar.next_day_start(start - 86_400), start);
+        assert_eq!(calendar.next_day_start(start), start + 23 * 3_600);
+    }
+
+    #[test]
+    fn days_without_a_timezone_are_utc() {
+        let calendar = Calendar::default();
+        assert_eq!(calendar.day_start(86_400 + 3_600), 86_400);
+        assert_eq!(calendar.next_day_start(86_400), 172_800);
+        assert_eq!(calendar.day(86_399), 0);
+        assert_eq!(calendar.day(86_400), 1);
+    }
+}
@@ -0,0 +1,117 @@
+//! Command-line arguments, these override the configuration file.
+
+use candle_watcher::settings::{OutputFormat, WatcherSettings};
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Watches Coinbase candles over the WebSocket and records them as they complete.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file to load, created with defaults if missing.
+    #[arg(long, default_value = "config.toml")]
+    pub config: String,
+
+    /// Named profile from the configuration whose credentials are used instead of the
+    /// `[coinbase]` section.
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Products to watch, skips product discovery entirely.
+    #[arg(long, value_delimiter = ',')]
+    pub products: Option<Vec<String>>,
+
+    /// Quote currencies of the products to discover, replaces `quote_currencies`.
+    #[arg(long, value_delimiter = ',')]
+    pub quote: Option<Vec<String>>,
+
+    /// How completed candles are printed to stdout, replaces `output_format`.
+    #[arg(long)]
+    pub format: Option<OutputFormat>,
+
+    /// Directory to append candles to as one CSV file per product, replaces `csv_dir`.
+    #[arg(long)]
+    pub csv_dir: Option<PathBuf>,
+
+    /// Never color the output, replaces `color`.
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// Discovers, subscribes, and receives candles as usual, but discards them instead of
+    /// recording them to any sink. Only the periodic summary is logged.
+    #[arg(long, conflicts_with = "replay")]
+    pub dry_run: bool,
+
+    /// Replays candles recorded as CSV or JSON lines instead of connecting.
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Replay speed as a multiple of real-time, 0 replays as fast as possible.
+    #[arg(long, default_value_t = 0.0, requires = "replay")]
+    pub speed: f64,
+}
+
+/// Tasks run instead of watching.
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Prints the products that would be watched, one per line, and exits.
+    Products,
+}
+
+impl Args {
+    /// Overrides the settings with any arguments that were provided.
+    pub fn apply(&self, settings: &mut WatcherSettings) {
+        if let Some(quote) = &self.quote {
+            settings.quote_currencies = quote.clone();
+        }
+        if let Some(format) = self.format {
+            settings.output_format = format;
+        }
+        if let Some(dir) = &self.csv_dir {
+            settings.csv_dir = Some(dir.clone());
+        }
+        if self.no_color {
+            settings.color = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn arguments_override_variables_which_override_the_file() {
+        // Deserialized as the `[watcher]` section of the file is.
+        let mut settings: WatcherSettings = serde_json::from_value(json!({
+            "quote_currencies": ["EUR"],
+            "output_format": "text",
+            "csv_dir": "file",
+            "summary_interval": 30,
+        }))
+        .unwrap();
+
+        let vars = [
+            ("CW_QUOTE_CURRENCIES", "USD,USDC"),
+            ("CW_OUTPUT_FORMAT", "json"),
+            ("CW_CSV_DIR", "env"),
+        ];
+        settings
+            .apply_env(vars.map(|(name, value)| (name.to_string(), value.to_string())))
+            .unwrap();
+
+        let args =
+            Args::try_parse_from(["candle_watcher", "--quote", "BTC", "--csv-dir", "cli"]).unwrap();
+        args.apply(&mut settings);
+
+        assert_eq!(settings.quote_currencies, vec!["BTC".to_string()]);
+        assert_eq!(settings.csv_dir, Some(PathBuf::from("cli")));
+        assert_eq!(settings.output_format, OutputFormat::Json);
+        assert_eq!(settings.summary_interval, 30);
+    }
+}
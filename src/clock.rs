@@ -0,0 +1,63 @@
+//! Source of the current time for the tracker and its timer tasks, so the logic that
+//! depends on it can be driven by a clock that only moves when told to.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Provides the current wall-clock time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Time elapsed since the Unix epoch.
+    fn now(&self) -> Duration;
+
+    /// Unix time in seconds.
+    fn unix_now(&self) -> u64 {
+        self.now().as_secs()
+    }
+}
+
+/// Clock reading the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+/// Clock that stands still until it is set or advanced, clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    /// Milliseconds since the Unix epoch.
+    millis: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// Creates a clock reading `unix_secs` seconds since the Unix epoch.
+    pub fn new(unix_secs: u64) -> Self {
+        let clock = Self::default();
+        clock.set(Duration::from_secs(unix_secs));
+        clock
+    }
+
+    /// Sets the time elapsed since the Unix epoch.
+    pub fn set(&self, now: Duration) {
+        self.millis.store(now.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        self.millis
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.millis.load(Ordering::SeqCst))
+    }
+}
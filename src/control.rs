@@ -0,0 +1,133 @@
+//! Answers debugging commands over a Unix domain socket while the watcher runs.
+//!
+//! Each line sent is a command, each is answered with a single line of JSON:
+//!
+//! - `dump` returns the in-progress candle and indicator state of every product.
+//! - `stats` returns the counters, in total and for each product.
+//!
+//! ```sh
+//! echo dump | nc -U /tmp/candle-watcher.sock
+//! ```
+
+use crate::sink::CandleSink;
+use crate::stats::Stats;
+use crate::TrackerHandle;
+
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Listens on `path` until the returned task is aborted, a socket left behind by a
+/// previous run is replaced. Commands only copy the state while the trackers are
+/// locked, so they never hold up the message path for long.
+pub fn serve_control<S: CandleSink + Send + 'static>(
+    path: PathBuf,
+    trackers: Vec<TrackerHandle<S>>,
+) -> io::Result<JoinHandle<()>> {
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => (),
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    let trackers = Arc::new(trackers);
+    info!("Serving commands on '{}'.", path.display());
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let trackers = Arc::clone(&trackers);
+                    tokio::spawn(async move {
+                        if let Err(err) = handle_client(stream, &trackers).await {
+                            debug!("Command client failed: {}", err);
+                        }
+                    });
+                }
+                Err(err) => warn!("Unable to accept command client: {}", err),
+            }
+        }
+    }))
+}
+
+/// Answers commands from a single client until it disconnects.
+async fn handle_client<S: CandleSink + Send + 'static>(
+    stream: UnixStream,
+    trackers: &[TrackerHandle<S>],
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match line.trim() {
+            "" => continue,
+            "dump" => dump(trackers),
+            "stats" => stats(trackers),
+            other => json!({ "error": format!("unknown command '{}'", other) }),
+        };
+
+        let mut text = response.to_string();
+        text.push('\n');
+        writer.write_all(text.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// State of every partition combined, each product belongs to a single partition.
+fn dump<S: CandleSink + Send + 'static>(trackers: &[TrackerHandle<S>]) -> Value {
+    let mut combined = Map::new();
+    for tracker in trackers {
+        let state = match tracker.dump() {
+            Ok(Value::Object(state)) => state,
+            Ok(_) => continue,
+            Err(err) => return json!({ "error": err }),
+        };
+
+        // Maps keyed by product are merged, the rest are the same for every partition.
+        for (key, value) in state {
+            match (combined.get_mut(&key), value) {
+                (Some(Value::Object(existing)), Value::Object(products)) => {
+                    existing.extend(products);
+                }
+                (_, value) => {
+                    combined.insert(key, value);
+                }
+            }
+        }
+    }
+    Value::Object(combined)
+}
+
+/// Counters of every partition summed, along with those of each product.
+fn stats<S: CandleSink + Send + 'static>(trackers: &[TrackerHandle<S>]) -> Value {
+    let mut products = Map::new();
+    for tracker in trackers {
+        for (product_id, product) in tracker.product_stats() {
+            products.insert(product_id, json!(product));
+        }
+    }
+
+    let totals: Vec<Arc<Stats>> = trackers.iter().map(|tracker| tracker.stats()).collect();
+    json!({
+        "processed": total(&totals, Stats::processed),
+        "completed": total(&totals, Stats::completed),
+        "products": total(&totals, Stats::products),
+        "connections": total(&totals, Stats::connections),
+        "reconnects": total(&totals, Stats::reconnects),
+        "rejected": total(&totals, Stats::rejected),
+        "duplicate_ejections": total(&totals, Stats::duplicate_ejections),
+        "last_message": totals.iter().map(|s| s.last_message()).max().unwrap_or(0),
+        "product_stats": products,
+    })
+}
+
+/// Sums a counter over every partition.
+fn total(stats: &[Arc<Stats>], count: impl Fn(&Stats) -> usize) -> usize {
+    stats.iter().map(|stats| count(stats)).sum()
+}
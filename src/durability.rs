@@ -0,0 +1,52 @@
+//! How often file sinks write buffered candles and whether they wait for the disk.
+
+use serde::Deserialize;
+use std::fs::File;
+use std::io;
+
+/// What a file sink does with its buffered candles on each interval. Candles are
+/// always written and synced to disk on a graceful shutdown.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncPolicy {
+    /// Candles are only written once a buffer fills, a Parquet day rolls over, or on
+    /// shutdown. A crash loses everything still buffered.
+    None,
+    /// Candles are written to the file every interval. A crash of the process loses
+    /// at most one interval, a crash of the system also what the OS had not written.
+    #[default]
+    Flush,
+    /// Candles are written and synced to the disk every interval, a crash of the
+    /// system loses at most one interval.
+    Fsync,
+}
+
+/// When a file sink writes its buffered candles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilePolicy {
+    /// What happens to the buffered candles on each interval.
+    pub sync: SyncPolicy,
+    /// Seconds between writes, 0 writes every candle as it is recorded.
+    pub interval: u64,
+}
+
+impl FilePolicy {
+    /// Whether buffered candles are due to be written, `elapsed` seconds since the
+    /// last write.
+    pub fn is_due(&self, elapsed: u64) -> bool {
+        self.sync != SyncPolicy::None && elapsed >= self.interval
+    }
+
+    /// Whether writes wait for the file to reach the disk.
+    pub fn fsync(&self) -> bool {
+        self.sync == SyncPolicy::Fsync
+    }
+}
+
+/// Waits for the written contents of a file to reach the disk when `fsync` is set.
+pub fn sync(file: &File, fsync: bool) -> io::Result<()> {
+    match fsync {
+        true => file.sync_data(),
+        false => Ok(()),
+    }
+}
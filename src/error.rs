@@ -0,0 +1,46 @@
+//! Errors that stop the watcher, grouped by what failed.
+
+use std::error::Error;
+use std::fmt;
+
+/// Reason the watcher could not start or had to stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatcherError {
+    /// Configuration file, settings, or arguments are invalid.
+    Config(String),
+    /// Coinbase rejected the credentials.
+    Auth(String),
+    /// WebSocket connection could not be kept open.
+    WebSocket(String),
+    /// REST API request failed.
+    Rest(String),
+    /// Sink that candles are recorded to could not be created.
+    Sink(String),
+}
+
+impl WatcherError {
+    /// Process exit code for the error, distinct for each kind.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WatcherError::Config(_) => 2,
+            WatcherError::Auth(_) => 3,
+            WatcherError::WebSocket(_) => 4,
+            WatcherError::Rest(_) => 5,
+            WatcherError::Sink(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for WatcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatcherError::Config(err) => write!(f, "invalid configuration: {}", err),
+            WatcherError::Auth(err) => write!(f, "authentication failed: {}", err),
+            WatcherError::WebSocket(err) => write!(f, "WebSocket failed: {}", err),
+            WatcherError::Rest(err) => write!(f, "REST request failed: {}", err),
+            WatcherError::Sink(err) => write!(f, "unable to record candles: {}", err),
+        }
+    }
+}
+
+impl Error for WatcherError {}
@@ -0,0 +1,107 @@
+//! Detects completed candles that set a new high or low over the previous candles.
+
+use cbadv::product::Candle;
+use tracing::info;
+
+/// Which extreme a candle set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtremeKind {
+    /// High above every previous high of the period.
+    NewHigh,
+    /// Low below every previous low of the period.
+    NewLow,
+}
+
+/// Completed candle that set a new extreme.
+#[derive(Debug, Clone)]
+pub struct Extreme {
+    /// Product the candle belongs to.
+    pub product_id: String,
+    /// Start of the candle.
+    pub candle_start: u64,
+    /// Which extreme was set.
+    pub kind: ExtremeKind,
+    /// High or low of the candle.
+    pub price: f64,
+    /// Extreme of the previous candles that was exceeded.
+    pub previous: f64,
+    /// Previous candles compared against.
+    pub period: usize,
+}
+
+/// Receives extremes as they are detected.
+pub trait ExtremeSink {
+    /// Called for each new high or low, a candle may set both.
+    fn on_extreme(&mut self, extreme: &Extreme);
+}
+
+/// Prints each extreme.
+pub struct LogExtremeSink;
+
+impl ExtremeSink for LogExtremeSink {
+    fn on_extreme(&mut self, extreme: &Extreme) {
+        let kind = match extreme.kind {
+            ExtremeKind::NewHigh => "high",
+            ExtremeKind::NewLow => "low",
+        };
+        info!(
+            product_id = %extreme.product_id,
+            start = extreme.candle_start,
+            price = extreme.price,
+            previous = extreme.previous,
+            "New {}-candle {}.",
+            extreme.period,
+            kind
+        );
+    }
+}
+
+/// Compares the high and low of completed candles against the previous candles of
+/// the product.
+pub struct ExtremeDetector {
+    /// Previous candles compared against, nothing is reported until this many completed.
+    period: usize,
+    /// Receives detected extremes.
+    sink: Box<dyn ExtremeSink + Send>,
+}
+
+impl ExtremeDetector {
+    /// Creates a detector over `period` candles passing extremes to `sink`.
+    pub fn new(period: usize, sink: Box<dyn ExtremeSink + Send>) -> Self {
+        Self { period, sink }
+    }
+
+    /// Previous candles compared against.
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    /// Checks a completed candle against the `previous` completed candles, oldest
+    /// first, before it is added to them.
+    pub fn check(&mut self, product_id: &str, candle: &Candle, previous: &[Candle]) {
+        if self.period == 0 || previous.len() < self.period {
+            return;
+        }
+
+        let window = &previous[previous.len() - self.period..];
+        let high = window.iter().map(|c| c.high).fold(f64::MIN, f64::max);
+        let low = window.iter().map(|c| c.low).fold(f64::MAX, f64::min);
+
+        let mut report = |kind: ExtremeKind, price: f64, previous: f64| {
+            self.sink.on_extreme(&Extreme {
+                product_id: product_id.to_string(),
+                candle_start: candle.start,
+                kind,
+                price,
+                previous,
+                period: self.period,
+            });
+        };
+        if candle.high > high {
+            report(ExtremeKind::NewHigh, candle.high, high);
+        }
+        if candle.low < low {
+            report(ExtremeKind::NewLow, candle.low, low);
+        }
+    }
+}
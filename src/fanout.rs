@@ -0,0 +1,171 @@
+//! Passes candles to several sinks at once, each isolated on its own thread.
+
+use crate::sink::{CandleInfo, CandleSink};
+
+use cbadv::product::Candle;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// Candles waiting for each sink by default, candles are dropped beyond this.
+const DEFAULT_CAPACITY: usize = 1_024;
+/// Maximum time to wait for each sink to flush on shutdown.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Work sent to the thread of a sink.
+enum Command {
+    /// Passes a candle to the sink.
+    Candle(String, Candle, CandleInfo),
+    /// Flushes the sink, signaling once complete.
+    Flush(Sender<()>),
+}
+
+/// Sink running on its own thread.
+struct Worker {
+    /// Name used when reporting the sink.
+    name: String,
+    /// Queues work for the thread, `None` once shutting down.
+    commands: Option<SyncSender<Command>>,
+    /// Thread calling the sink.
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Collects the sinks of a [`FanOutSink`].
+pub struct FanOutBuilder {
+    sinks: Vec<(String, Box<dyn CandleSink + Send>)>,
+    capacity: usize,
+}
+
+impl FanOutBuilder {
+    /// Adds a sink, `name` identifies it when it falls behind or fails.
+    pub fn sink(mut self, name: &str, sink: Box<dyn CandleSink + Send>) -> Self {
+        self.sinks.push((name.to_string(), sink));
+        self
+    }
+
+    /// Sets the candles that can wait for each sink, at least 1.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Whether no sinks were added.
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Starts a thread for each sink.
+    pub fn build(self) -> FanOutSink {
+        let capacity = self.capacity;
+        let workers = self
+            .sinks
+            .into_iter()
+            .map(|(name, sink)| {
+                let (commands, receiver) = mpsc::sync_channel(capacity);
+                let thread_name = name.clone();
+                let thread = thread::spawn(move || run(&thread_name, sink, receiver));
+                Worker {
+                    name,
+                    commands: Some(commands),
+                    thread: Some(thread),
+                }
+            })
+            .collect();
+        FanOutSink { workers }
+    }
+}
+
+/// Dispatches each candle to every sink, each on its own thread behind a bounded
+/// queue. A sink that falls behind has candles dropped rather than delaying the
+/// others, and a sink that panics is stopped while the others continue.
+pub struct FanOutSink {
+    workers: Vec<Worker>,
+}
+
+impl FanOutSink {
+    /// Starts collecting the sinks to dispatch to.
+    pub fn builder() -> FanOutBuilder {
+        FanOutBuilder {
+            sinks: vec![],
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+impl CandleSink for FanOutSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        for worker in &self.workers {
+            let commands = match &worker.commands {
+                Some(commands) => commands,
+                None => continue,
+            };
+
+            let command = Command::Candle(product_id.to_string(), candle.clone(), info.clone());
+            match commands.try_send(command) {
+                Ok(()) => (),
+                Err(TrySendError::Full(_)) => warn!(
+                    product_id,
+                    "'{}' sink is behind, candle dropped.", worker.name
+                ),
+                // The sink panicked and was stopped, already reported.
+                Err(TrySendError::Disconnected(_)) => (),
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        // Flush every sink at once, then wait on each.
+        let mut pending = vec![];
+        for worker in &self.workers {
+            if let Some(commands) = &worker.commands {
+                let (done, wait) = mpsc::channel();
+                if commands.send(Command::Flush(done)).is_ok() {
+                    pending.push((&worker.name, wait));
+                }
+            }
+        }
+
+        for (name, wait) in pending {
+            if wait.recv_timeout(FLUSH_TIMEOUT).is_err() {
+                error!("'{}' sink did not flush in time.", name);
+            }
+        }
+    }
+}
+
+impl Drop for FanOutSink {
+    fn drop(&mut self) {
+        // Closing the queues lets each thread finish the queued candles and stop.
+        for worker in &mut self.workers {
+            worker.commands.take();
+        }
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Calls the sink for each command until the queue is closed or the sink panics.
+fn run(name: &str, mut sink: Box<dyn CandleSink + Send>, commands: Receiver<Command>) {
+    while let Ok(command) = commands.recv() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| match command {
+            Command::Candle(product_id, candle, info) => {
+                sink.on_candle(&product_id, &candle, &info);
+            }
+            Command::Flush(done) => {
+                sink.flush();
+                let _ = done.send(());
+            }
+        }));
+
+        if result.is_err() {
+            // The state of the sink is unknown, stop using it.
+            error!("'{}' sink panicked and was stopped.", name);
+            return;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Selects the fields of each candle written by the CSV and JSON output, the candles
+//! passed to other sinks keep every field.
+
+use cbadv::product::Candle;
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Field of a candle that can be written.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputField {
+    Start,
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+    ProductId,
+}
+
+impl OutputField {
+    /// Columns of CSV files when no fields are selected.
+    pub const CSV_DEFAULT: [OutputField; 6] = [
+        OutputField::Start,
+        OutputField::Open,
+        OutputField::High,
+        OutputField::Low,
+        OutputField::Close,
+        OutputField::Volume,
+    ];
+
+    /// Name of the field in the configuration, CSV headers, and JSON keys.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputField::Start => "start",
+            OutputField::Open => "open",
+            OutputField::High => "high",
+            OutputField::Low => "low",
+            OutputField::Close => "close",
+            OutputField::Volume => "volume",
+            OutputField::ProductId => "product_id",
+        }
+    }
+
+    /// Value of the field for a candle of a product, as text.
+    fn text(self, product_id: &str, candle: &Candle) -> String {
+        match self {
+            OutputField::Start => candle.start.to_string(),
+            OutputField::Open => candle.open.to_string(),
+            OutputField::High => candle.high.to_string(),
+            OutputField::Low => candle.low.to_string(),
+            OutputField::Close => candle.close.to_string(),
+            OutputField::Volume => candle.volume.to_string(),
+            OutputField::ProductId => product_id.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for OutputField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Header line of a CSV file with the `fields` columns, without the newline.
+pub fn csv_header(fields: &[OutputField]) -> String {
+    let names: Vec<&str> = fields.iter().map(|field| field.name()).collect();
+    names.join(",")
+}
+
+/// Row of a candle with the `fields` columns, without the newline.
+pub fn csv_row(fields: &[OutputField], product_id: &str, candle: &Candle) -> String {
+    let values: Vec<String> = fields
+        .iter()
+        .map(|field| field.text(product_id, candle))
+        .collect();
+    values.join(",")
+}
+
+/// Serializes only the selected fields of a candle, in the order they were selected.
+pub struct Selected<'a> {
+    pub fields: &'a [OutputField],
+    pub product_id: &'a str,
+    pub candle: &'a Candle,
+}
+
+impl Serialize for Selected<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len()))?;
+        for field in self.fields {
+            match field {
+                OutputField::Start => map.serialize_entry(field.name(), &self.candle.start)?,
+                OutputField::Open => map.serialize_entry(field.name(), &self.candle.open)?,
+                OutputField::High => map.serialize_entry(field.name(), &self.candle.high)?,
+                OutputField::Low => map.serialize_entry(field.name(), &self.candle.low)?,
+                OutputField::Close => map.serialize_entry(field.name(), &self.candle.close)?,
+                OutputField::Volume => map.serialize_entry(field.name(), &self.candle.volume)?,
+                OutputField::ProductId => map.serialize_entry(field.name(), self.product_id)?,
+            }
+        }
+        map.end()
+    }
+}
@@ -0,0 +1,26 @@
+//! Filters messages before the tracker processes them.
+
+use cbadv::websocket::Message;
+
+/// Decides which messages reach the tracker, such as ignoring some products, sampling
+/// or dropping messages during maintenance windows.
+pub trait MessageFilter {
+    /// Whether the message is processed, dropped messages still count towards the
+    /// connection being alive.
+    fn keep(&self, msg: &Message) -> bool;
+}
+
+/// Filter that keeps every message.
+pub struct PassThrough;
+
+impl MessageFilter for PassThrough {
+    fn keep(&self, _msg: &Message) -> bool {
+        true
+    }
+}
+
+impl<F: Fn(&Message) -> bool> MessageFilter for F {
+    fn keep(&self, msg: &Message) -> bool {
+        self(msg)
+    }
+}
@@ -0,0 +1,78 @@
+//! Candle granularities supported by Coinbase.
+
+use crate::aggregator::Timeframe;
+
+use serde::Deserialize;
+use std::fmt;
+
+/// Granularity of the candles provided by Coinbase.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Granularity {
+    OneMinute,
+    #[default]
+    FiveMinute,
+    FifteenMinute,
+    ThirtyMinute,
+    OneHour,
+    TwoHour,
+    SixHour,
+    OneDay,
+}
+
+impl Granularity {
+    /// Length of a candle in seconds.
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Granularity::OneMinute => 60,
+            Granularity::FiveMinute => 300,
+            Granularity::FifteenMinute => 900,
+            Granularity::ThirtyMinute => 1_800,
+            Granularity::OneHour => 3_600,
+            Granularity::TwoHour => 7_200,
+            Granularity::SixHour => 21_600,
+            Granularity::OneDay => 86_400,
+        }
+    }
+
+    /// Name used by the Coinbase API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::OneMinute => "ONE_MINUTE",
+            Granularity::FiveMinute => "FIVE_MINUTE",
+            Granularity::FifteenMinute => "FIFTEEN_MINUTE",
+            Granularity::ThirtyMinute => "THIRTY_MINUTE",
+            Granularity::OneHour => "ONE_HOUR",
+            Granularity::TwoHour => "TWO_HOUR",
+            Granularity::SixHour => "SIX_HOUR",
+            Granularity::OneDay => "ONE_DAY",
+        }
+    }
+
+    /// Whether the WebSocket CANDLES channel provides this granularity, it currently
+    /// only sends five minute candles.
+    pub fn websocket_supported(&self) -> bool {
+        *self == Granularity::FiveMinute
+    }
+
+    /// Timeframe that five minute candles are aggregated into to build candles of this
+    /// granularity, `None` for those shorter than five minutes.
+    pub fn timeframe(&self) -> Option<Timeframe> {
+        match self {
+            Granularity::OneMinute => None,
+            Granularity::FiveMinute => Some(Timeframe::FiveMinutes),
+            Granularity::FifteenMinute => Some(Timeframe::FifteenMinutes),
+            Granularity::ThirtyMinute => Some(Timeframe::ThirtyMinutes),
+            Granularity::OneHour => Some(Timeframe::OneHour),
+            Granularity::TwoHour => Some(Timeframe::TwoHours),
+            Granularity::SixHour => Some(Timeframe::SixHours),
+            Granularity::OneDay => Some(Timeframe::OneDay),
+        }
+    }
+}
+
+impl fmt::Display for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,117 @@
+//! Liveness and readiness probes served over HTTP, such as for Kubernetes.
+//!
+//! - `/healthz` responds `200 OK` while the process is running.
+//! - `/readyz` responds `200 OK` once every partition is connected and a candle update
+//!   was received within the staleness window, otherwise `503 Service Unavailable`.
+
+use crate::sink::CandleSink;
+use crate::TrackerHandle;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+/// Largest request read, probes only need the request line.
+const MAX_REQUEST: usize = 4096;
+/// Maximum time a client has to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `/healthz` and `/readyz` on `addr` until the returned task is aborted. The
+/// watcher is ready when every tracker is connected and any product received an
+/// update within the last `stale_after` seconds, 0 only requires that one was received.
+pub async fn serve_health<S: CandleSink + Send + 'static>(
+    addr: SocketAddr,
+    trackers: Vec<TrackerHandle<S>>,
+    stale_after: u64,
+) -> std::io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    let trackers = Arc::new(trackers);
+    info!("Serving health checks on http://{}/healthz.", addr);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let trackers = Arc::clone(&trackers);
+                    tokio::spawn(async move {
+                        if let Err(err) = respond(stream, &trackers, stale_after).await {
+                            debug!("Health check request failed: {}", err);
+                        }
+                    });
+                }
+                Err(err) => warn!("Unable to accept health check client: {}", err),
+            }
+        }
+    }))
+}
+
+/// Responds to a single request and closes the connection.
+async fn respond<S: CandleSink + Send + 'static>(
+    mut stream: TcpStream,
+    trackers: &[TrackerHandle<S>],
+    stale_after: u64,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; MAX_REQUEST];
+    let mut len = 0;
+    // Only the request line is needed, stop once it has been read.
+    while !buffer[..len].contains(&b'\n') && len < buffer.len() {
+        match timeout(READ_TIMEOUT, stream.read(&mut buffer[len..])).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(read)) => len += read,
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Ok(()),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&buffer[..len]);
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+
+    let (status, body) = match (method, path) {
+        (Some("GET" | "HEAD"), Some("/healthz")) => ("200 OK", "ok"),
+        (Some("GET" | "HEAD"), Some("/readyz")) => match readiness(trackers, stale_after) {
+            Ok(()) => ("200 OK", "ready"),
+            Err(reason) => ("503 Service Unavailable", reason),
+        },
+        (Some(_), Some("/healthz" | "/readyz")) => ("405 Method Not Allowed", "GET only"),
+        _ => ("404 Not Found", "not found"),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}\n",
+        status,
+        body.len() + 1,
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Whether the watcher is ready, otherwise why not.
+fn readiness<S: CandleSink + Send + 'static>(
+    trackers: &[TrackerHandle<S>],
+    stale_after: u64,
+) -> Result<(), &'static str> {
+    if !trackers.iter().all(|tracker| tracker.stats().connected()) {
+        return Err("websocket not connected");
+    }
+
+    // Heartbeats prove the connection, only candle updates prove data is flowing.
+    let fresh = trackers.iter().any(|tracker| {
+        let now = tracker.now();
+        tracker.product_stats().values().any(|product| {
+            product.last_update > 0
+                && (stale_after == 0 || now.saturating_sub(product.last_update) <= stale_after)
+        })
+    });
+    if fresh {
+        Ok(())
+    } else {
+        Err("no recent candles")
+    }
+}
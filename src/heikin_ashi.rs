@@ -0,0 +1,104 @@
+//! Heikin-Ashi candles built from the completed candles of each product.
+
+use cbadv::product::Candle;
+use std::collections::HashMap;
+
+/// Transforms completed candles into Heikin-Ashi candles, keeping the prior
+/// Heikin-Ashi candle of each product.
+#[derive(Debug, Clone, Default)]
+pub struct HeikinAshi {
+    /// Open and close of the last Heikin-Ashi candle of each product.
+    previous: HashMap<String, (f64, f64)>,
+}
+
+impl HeikinAshi {
+    /// Creates a transform without any prior candles.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Heikin-Ashi candle of the next completed candle of a product. The close is the
+    /// mean of the OHLC and the open the midpoint of the prior open and close, seeded
+    /// by the raw open for the first candle. The high and low extend to cover both.
+    pub fn update(&mut self, product_id: &str, candle: &Candle) -> Candle {
+        let close = (candle.open + candle.high + candle.low + candle.close) / 4.0;
+        let open = match self.previous.get(product_id) {
+            Some((open, close)) => (open + close) / 2.0,
+            None => candle.open,
+        };
+        self.previous.insert(product_id.to_string(), (open, close));
+
+        Candle {
+            start: candle.start,
+            open,
+            high: candle.high.max(open).max(close),
+            low: candle.low.min(open).min(close),
+            close,
+            volume: candle.volume,
+        }
+    }
+
+    /// Forgets the prior candle of a product, the next one is seeded again.
+    pub fn reset(&mut self, product_id: &str) {
+        self.previous.remove(product_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Candle with the given prices and a volume of 1.
+    fn ohlc(start: u64, open: f64, high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            start,
+            open,
+            high,
+            low,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    /// Open, high, low, and close of a candle.
+    fn prices(candle: &Candle) -> (f64, f64, f64, f64) {
+        (candle.open, candle.high, candle.low, candle.close)
+    }
+
+    #[test]
+    fn transforms_a_worked_example() {
+        let mut heikin_ashi = HeikinAshi::new();
+
+        // Seeded by the raw open, the close is (10 + 12 + 9 + 11) / 4.
+        let first = heikin_ashi.update("BTC-USD", &ohlc(0, 10.0, 12.0, 9.0, 11.0));
+        assert_eq!(prices(&first), (10.0, 12.0, 9.0, 10.5));
+
+        // Open (10 + 10.5) / 2, close (11 + 13 + 10 + 12.5) / 4.
+        let second = heikin_ashi.update("BTC-USD", &ohlc(300, 11.0, 13.0, 10.0, 12.5));
+        assert_eq!(prices(&second), (10.25, 13.0, 10.0, 11.625));
+
+        // Open (10.25 + 11.625) / 2 is below the raw low, which extends to cover it.
+        let third = heikin_ashi.update("BTC-USD", &ohlc(600, 12.5, 12.75, 11.25, 11.5));
+        assert_eq!(prices(&third), (10.9375, 12.75, 10.9375, 12.0));
+        assert_eq!(third.start, 600);
+        assert_eq!(third.volume, 1.0);
+    }
+
+    #[test]
+    fn seeds_each_product_and_reset_separately() {
+        let mut heikin_ashi = HeikinAshi::new();
+        heikin_ashi.update("BTC-USD", &ohlc(0, 10.0, 12.0, 9.0, 11.0));
+
+        // Another product starts from its own raw open.
+        let other = heikin_ashi.update("ETH-USD", &ohlc(0, 20.0, 22.0, 18.0, 20.0));
+        assert_eq!(other.open, 20.0);
+
+        heikin_ashi.reset("BTC-USD");
+        let reseeded = heikin_ashi.update("BTC-USD", &ohlc(300, 11.0, 13.0, 10.0, 12.5));
+        assert_eq!(reseeded.open, 11.0);
+
+        // ETH-USD still continues from its prior candle, (20 + 20) / 2.
+        let next = heikin_ashi.update("ETH-USD", &ohlc(300, 21.0, 23.0, 19.0, 22.0));
+        assert_eq!(next.open, 20.0);
+    }
+}
@@ -0,0 +1,675 @@
+//! Indicators calculated from completed candles.
+
+use crate::calendar::Calendar;
+
+use cbadv::product::Candle;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Simple moving average over a fixed window of values.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sma {
+    /// Amount of values averaged.
+    period: usize,
+    /// Most recent values, oldest first.
+    window: VecDeque<f64>,
+    /// Sum of the values within the window.
+    sum: f64,
+}
+
+impl Sma {
+    /// Creates an average over the last `period` values.
+    pub fn new(period: usize) -> Self {
+        Self {
+            period,
+            window: VecDeque::with_capacity(period),
+            sum: 0.0,
+        }
+    }
+
+    /// Amount of values averaged.
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    /// Adds a value, returning the new average once the window is full.
+    pub fn update(&mut self, value: f64) -> Option<f64> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.period {
+            if let Some(old) = self.window.pop_front() {
+                self.sum -= old;
+            }
+        }
+
+        self.value()
+    }
+
+    /// Current average, `None` until `period` values have been added.
+    pub fn value(&self) -> Option<f64> {
+        if self.period == 0 || self.window.len() < self.period {
+            return None;
+        }
+        Some(self.sum / self.period as f64)
+    }
+
+    /// Removes all values.
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.sum = 0.0;
+    }
+}
+
+/// Moving average attached to a recorded candle.
+#[derive(Debug, Clone, Copy)]
+pub struct SmaReading {
+    /// Amount of closes averaged.
+    pub period: usize,
+    /// Average of the closes, `None` until enough candles have completed.
+    pub value: Option<f64>,
+}
+
+/// Exponential moving average, seeded with the simple moving average of the first
+/// `period` values.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ema {
+    /// Weight given to each new value.
+    multiplier: f64,
+    /// Average used to seed the first value.
+    seed: Sma,
+    /// Current average, `None` until seeded.
+    value: Option<f64>,
+}
+
+impl Ema {
+    /// Creates an exponential average over `period` values.
+    pub fn new(period: usize) -> Self {
+        Self {
+            multiplier: 2.0 / (period as f64 + 1.0),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    /// Adds a value, returning the new average once seeded.
+    pub fn update(&mut self, value: f64) -> Option<f64> {
+        self.value = match self.value {
+            Some(prev) => Some((value - prev) * self.multiplier + prev),
+            None => self.seed.update(value),
+        };
+        self.value
+    }
+
+    /// Current average, `None` until `period` values have been added.
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Moving Average Convergence Divergence using the typical 12/26/9 setup.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Macd {
+    /// Fast moving average of the values.
+    fast: Ema,
+    /// Slow moving average of the values.
+    slow: Ema,
+    /// Moving average of the MACD line.
+    signal: Ema,
+    /// Difference between the fast and slow averages.
+    value: Option<f64>,
+}
+
+impl Macd {
+    /// Creates a MACD with 12 and 26 period averages and a 9 period signal.
+    pub fn new() -> Self {
+        Self::with_periods(12, 26, 9)
+    }
+
+    /// Creates a MACD with custom periods.
+    pub fn with_periods(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+            value: None,
+        }
+    }
+
+    /// Adds a value, updating the MACD and signal lines.
+    pub fn update(&mut self, value: f64) -> MacdReading {
+        let fast = self.fast.update(value);
+        let slow = self.slow.update(value);
+
+        if let (Some(fast), Some(slow)) = (fast, slow) {
+            let macd = fast - slow;
+            self.value = Some(macd);
+            self.signal.update(macd);
+        }
+
+        self.reading()
+    }
+
+    /// MACD line, `None` until the slow average is seeded.
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    /// Signal line, `None` until enough MACD values exist to seed it.
+    pub fn signal(&self) -> Option<f64> {
+        self.signal.value()
+    }
+
+    /// Difference between the MACD and signal lines.
+    pub fn histogram(&self) -> Option<f64> {
+        Some(self.value? - self.signal()?)
+    }
+
+    /// Current values of the lines.
+    pub fn reading(&self) -> MacdReading {
+        MacdReading {
+            value: self.value(),
+            signal: self.signal(),
+            histogram: self.histogram(),
+        }
+    }
+}
+
+impl Default for Macd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// MACD values attached to a recorded candle.
+#[derive(Debug, Clone, Copy)]
+pub struct MacdReading {
+    /// MACD line.
+    pub value: Option<f64>,
+    /// Signal line.
+    pub signal: Option<f64>,
+    /// Difference between the MACD and signal lines.
+    pub histogram: Option<f64>,
+}
+
+/// Relative Strength Index using Wilder's smoothing of the average gains and losses.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rsi {
+    /// Amount of changes averaged.
+    period: usize,
+    /// Previous value, used to calculate the change.
+    prev: Option<f64>,
+    /// Changes seen while seeding the averages.
+    seeded: usize,
+    /// Average gain, a running sum until seeded.
+    avg_gain: f64,
+    /// Average loss, a running sum until seeded.
+    avg_loss: f64,
+}
+
+impl Rsi {
+    /// Creates an RSI over `period` changes, typically 14.
+    pub fn new(period: usize) -> Self {
+        Self {
+            period,
+            prev: None,
+            seeded: 0,
+            avg_gain: 0.0,
+            avg_loss: 0.0,
+        }
+    }
+
+    /// Adds a value, returning the new RSI once enough changes have been seen.
+    pub fn update(&mut self, value: f64) -> Option<f64> {
+        let prev = match self.prev.replace(value) {
+            Some(prev) => prev,
+            None => return None,
+        };
+
+        let change = value - prev;
+        let gain = change.max(0.0);
+        let loss = (-change).max(0.0);
+
+        if self.seeded < self.period {
+            // First averages are the simple average of the first `period` changes.
+            self.avg_gain += gain;
+            self.avg_loss += loss;
+            self.seeded += 1;
+            if self.seeded == self.period {
+                self.avg_gain /= self.period as f64;
+                self.avg_loss /= self.period as f64;
+            }
+        } else {
+            let period = self.period as f64;
+            self.avg_gain = (self.avg_gain * (period - 1.0) + gain) / period;
+            self.avg_loss = (self.avg_loss * (period - 1.0) + loss) / period;
+        }
+
+        self.value()
+    }
+
+    /// Current RSI between 0 and 100, `None` until `period` changes have been seen.
+    pub fn value(&self) -> Option<f64> {
+        if self.period == 0 || self.seeded < self.period {
+            return None;
+        }
+
+        // Avoid dividing by zero when there were only gains, or no changes at all.
+        if self.avg_loss == 0.0 {
+            let rsi = if self.avg_gain == 0.0 { 50.0 } else { 100.0 };
+            return Some(rsi);
+        }
+
+        let rs = self.avg_gain / self.avg_loss;
+        Some(100.0 - 100.0 / (1.0 + rs))
+    }
+}
+
+/// Average True Range using Wilder's smoothing of the true range of each candle.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Atr {
+    /// Amount of true ranges averaged.
+    period: usize,
+    /// Close of the previous candle, gaps between candles count towards the range.
+    prev_close: Option<f64>,
+    /// True ranges seen while seeding the average.
+    seeded: usize,
+    /// Average true range, a running sum until seeded.
+    average: f64,
+}
+
+impl Atr {
+    /// Creates an ATR over `period` candles, typically 14.
+    pub fn new(period: usize) -> Self {
+        Self {
+            period,
+            prev_close: None,
+            seeded: 0,
+            average: 0.0,
+        }
+    }
+
+    /// Adds a candle, returning the new ATR once enough candles have been seen. The
+    /// first candle only has its own range to go by.
+    pub fn update(&mut self, candle: &Candle) -> Option<f64> {
+        let range = candle.high - candle.low;
+        let true_range = match self.prev_close.replace(candle.close) {
+            Some(prev) => range
+                .max((candle.high - prev).abs())
+                .max((candle.low - prev).abs()),
+            None => range,
+        };
+
+        if self.seeded < self.period {
+            // First average is the simple average of the first `period` true ranges.
+            self.average += true_range;
+            self.seeded += 1;
+            if self.seeded == self.period {
+                self.average /= self.period as f64;
+            }
+        } else {
+            let period = self.period as f64;
+            self.average = (self.average * (period - 1.0) + true_range) / period;
+        }
+
+        self.value()
+    }
+
+    /// Current ATR, `None` until `period` candles have been seen.
+    pub fn value(&self) -> Option<f64> {
+        if self.period == 0 || self.seeded < self.period {
+            return None;
+        }
+        Some(self.average)
+    }
+}
+
+/// Volume-weighted average price of the candles within the current day.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Vwap {
+    /// Local days since the Unix epoch of the candles accumulated, `None` before the
+    /// first.
+    day: Option<i64>,
+    /// Sum of the typical prices multiplied by their volume.
+    price_volume: f64,
+    /// Sum of the volumes.
+    volume: f64,
+}
+
+impl Vwap {
+    /// Creates an empty VWAP.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a candle using its typical price, starting over once the candle begins a
+    /// new day of the calendar. Returns the new VWAP.
+    pub fn update(&mut self, candle: &Candle, calendar: &Calendar) -> Option<f64> {
+        let day = calendar.day(candle.start);
+        if self.day != Some(day) {
+            self.day = Some(day);
+            self.price_volume = 0.0;
+            self.volume = 0.0;
+        }
+
+        let typical = (candle.high + candle.low + candle.close) / 3.0;
+        self.price_volume += typical * candle.volume;
+        self.volume += candle.volume;
+        self.value()
+    }
+
+    /// Current VWAP, `None` until volume has been traded within the day.
+    pub fn value(&self) -> Option<f64> {
+        if self.volume > 0.0 {
+            Some(self.price_volume / self.volume)
+        } else {
+            None
+        }
+    }
+}
+
+/// Bollinger Bands over the closes of a window of completed candles.
+#[derive(Debug, Clone, Copy)]
+pub struct BollingerBands {
+    /// Amount of closes the bands are calculated over.
+    period: usize,
+    /// Standard deviations between the middle band and the outer bands.
+    k: f64,
+}
+
+impl BollingerBands {
+    /// Creates bands over `period` closes, `k` standard deviations wide, typically
+    /// 20 and 2.
+    pub fn new(period: usize, k: f64) -> Self {
+        Self { period, k }
+    }
+
+    /// Amount of closes the bands are calculated over.
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    /// Calculates the bands over the last `period` candles of `history`, oldest first.
+    /// `None` until the history holds enough candles.
+    pub fn calculate(&self, history: &[Candle]) -> Option<Bands> {
+        if self.period == 0 || history.len() < self.period {
+            return None;
+        }
+
+        let window = &history[history.len() - self.period..];
+        let mean = window.iter().map(|c| c.close).sum::<f64>() / self.period as f64;
+        let variance =
+            window.iter().map(|c| (c.close - mean).powi(2)).sum::<f64>() / self.period as f64;
+        let width = self.k * variance.sqrt();
+
+        Some(Bands {
+            lower: mean - width,
+            middle: mean,
+            upper: mean + width,
+        })
+    }
+}
+
+/// Prices derived from a single candle, commonly used as the input of indicators.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct DerivedPrices {
+    /// Typical price, `(high + low + close) / 3`.
+    pub typical_price: f64,
+    /// Median price, `(high + low) / 2`.
+    pub median_price: f64,
+    /// Weighted close, `(high + low + 2 * close) / 4`.
+    pub weighted_close: f64,
+}
+
+impl DerivedPrices {
+    /// Derives the prices of a candle.
+    pub fn of(candle: &Candle) -> Self {
+        Self {
+            typical_price: (candle.high + candle.low + candle.close) / 3.0,
+            median_price: (candle.high + candle.low) / 2.0,
+            weighted_close: (candle.high + candle.low + 2.0 * candle.close) / 4.0,
+        }
+    }
+}
+
+/// Bollinger Bands attached to a recorded candle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bands {
+    /// Middle band less `k` standard deviations.
+    pub lower: f64,
+    /// Simple moving average of the closes.
+    pub middle: f64,
+    /// Middle band plus `k` standard deviations.
+    pub upper: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Closes of a reference series, commonly used to illustrate the indicators.
+    const CLOSES: [f64; 40] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03, 45.61,
+        46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45, 45.78, 45.35,
+        44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13, 43.55, 44.02, 44.70, 45.11, 44.86, 45.34,
+        45.90,
+    ];
+
+    /// Asserts that a value is within rounding of the expected value.
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("value");
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "expected {}, found {}",
+            expected,
+            value
+        );
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_sma() {
+        let mut ema = Ema::new(3);
+        assert_eq!(ema.update(1.0), None);
+        assert_eq!(ema.update(2.0), None);
+        assert_close(ema.update(3.0), 2.0);
+        assert_close(ema.update(4.0), 3.0);
+        assert_close(ema.update(8.0), 5.5);
+    }
+
+    #[test]
+    fn macd_matches_the_reference_series() {
+        let mut macd = Macd::new();
+        let readings: Vec<MacdReading> = CLOSES.iter().map(|close| macd.update(*close)).collect();
+
+        // The MACD line starts once the slow average is seeded, the signal line 9 later.
+        assert!(readings[..25].iter().all(|reading| reading.value.is_none()));
+        assert_close(readings[25].value, 0.306688848132886);
+        assert!(readings[..33]
+            .iter()
+            .all(|reading| reading.signal.is_none()));
+        assert_close(readings[33].value, -0.49809439476452155);
+        assert_close(readings[33].signal, -0.14799738222242967);
+        assert_close(readings[33].histogram, -0.3500970125420919);
+
+        assert_close(readings[39].value, -0.029130859813882637);
+        assert_close(readings[39].signal, -0.19128539708209477);
+        assert_close(readings[39].histogram, 0.16215453726821213);
+    }
+
+    #[test]
+    fn rsi_matches_the_reference_series() {
+        let mut rsi = Rsi::new(14);
+        let values: Vec<Option<f64>> = CLOSES.iter().map(|close| rsi.update(*close)).collect();
+
+        // The first close has no change, the averages are seeded by the next 14.
+        assert!(values[..14].iter().all(|value| value.is_none()));
+        assert_close(values[14], 70.46413502109705);
+        assert_close(values[15], 66.24961855355505);
+        assert_close(values[20], 62.880718309962404);
+        assert_close(values[39], 59.38188088629188);
+    }
+
+    #[test]
+    fn rsi_without_losses_or_gains_does_not_divide_by_zero() {
+        let mut gains = Rsi::new(14);
+        let mut losses = Rsi::new(14);
+        let mut flat = Rsi::new(14);
+        for i in 0..30 {
+            gains.update(100.0 + i as f64);
+            losses.update(100.0 - i as f64);
+            flat.update(100.0);
+        }
+
+        assert_eq!(gains.value(), Some(100.0));
+        assert_eq!(losses.value(), Some(0.0));
+        assert_eq!(flat.value(), Some(50.0));
+    }
+
+    /// Candle that trades `volume` at a single `price`.
+    fn trade(start: u64, price: f64, volume: f64) -> Candle {
+        Candle {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume,
+        }
+    }
+
+    #[test]
+    fn vwap_resets_at_midnight() {
+        let calendar = Calendar::default();
+        let mut vwap = Vwap::new();
+
+        // 23:50 and 23:55 UTC of the first day.
+        assert_close(vwap.update(&trade(85_800, 10.0, 1.0), &calendar), 10.0);
+        assert_close(vwap.update(&trade(86_100, 20.0, 3.0), &calendar), 17.5);
+
+        // Midnight starts the next day over.
+        assert_close(vwap.update(&trade(86_400, 30.0, 1.0), &calendar), 30.0);
+        assert_close(vwap.update(&trade(86_700, 40.0, 1.0), &calendar), 35.0);
+
+        // Nothing traded yet in the day after.
+        assert_eq!(vwap.update(&trade(172_800, 50.0, 0.0), &calendar), None);
+    }
+
+    #[test]
+    fn bollinger_bands_match_the_reference_series() {
+        let history: Vec<Candle> = CLOSES.iter().map(|close| trade(0, *close, 1.0)).collect();
+        let bands = BollingerBands::new(20, 2.0);
+        assert_eq!(bands.calculate(&history[..19]), None);
+
+        let first = bands.calculate(&history[..20]).expect("bands");
+        assert_close(Some(first.lower), 43.70267177834978);
+        assert_close(Some(first.middle), 45.409);
+        assert_close(Some(first.upper), 47.115328221650216);
+
+        // Only the last 20 closes count.
+        let last = bands.calculate(&history).expect("bands");
+        assert_close(Some(last.lower), 42.612827936453414);
+        assert_close(Some(last.middle), 44.772);
+        assert_close(Some(last.upper), 46.93117206354658);
+    }
+
+    #[test]
+    fn bollinger_bands_of_identical_closes_collapse_to_the_mean() {
+        let history: Vec<Candle> = (0..20).map(|_| trade(0, 42.5, 1.0)).collect();
+        let bands = BollingerBands::new(20, 2.0)
+            .calculate(&history)
+            .expect("bands");
+
+        assert_eq!(
+            bands,
+            Bands {
+                lower: 42.5,
+                middle: 42.5,
+                upper: 42.5,
+            }
+        );
+    }
+
+    /// Candle with the given high, low, and close.
+    fn hlc(high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            start: 0,
+            open: close,
+            high,
+            low,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    #[test]
+    fn atr_includes_gaps_from_the_previous_close() {
+        let mut atr = Atr::new(3);
+        // True ranges of 2, seeding an average of 2.
+        assert_eq!(atr.update(&hlc(10.0, 8.0, 9.0)), None);
+        assert_eq!(atr.update(&hlc(11.0, 9.0, 10.5)), None);
+        assert_close(atr.update(&hlc(12.0, 10.0, 11.0)), 2.0);
+
+        // Gaps up from a close of 11, the range of 1 is exceeded by the high minus it.
+        assert_close(atr.update(&hlc(16.0, 15.0, 15.5)), 3.0);
+        // Gaps down from a close of 15.5, the previous close minus the low is 4.5.
+        assert_close(atr.update(&hlc(12.0, 11.0, 11.5)), 3.5);
+    }
+
+    #[test]
+    fn vwap_resets_at_local_midnight() {
+        let calendar = Calendar::new(Some(chrono_tz::America::New_York));
+        // 2024-03-10 00:00 in New York, a day shortened by daylight saving.
+        let midnight = 1_710_046_800;
+
+        let mut vwap = Vwap::new();
+        assert_close(
+            vwap.update(&trade(midnight - 300, 10.0, 1.0), &calendar),
+            10.0,
+        );
+        assert_close(vwap.update(&trade(midnight, 20.0, 1.0), &calendar), 20.0);
+
+        // Midnight UTC, 20:00 in New York, does not start the day over.
+        let utc_midnight = 1_710_115_200;
+        assert_close(
+            vwap.update(&trade(utc_midnight, 30.0, 1.0), &calendar),
+            25.0,
+        );
+
+        // 23 hours after midnight the next local day starts.
+        assert_close(
+            vwap.update(&trade(midnight + 23 * 3_600, 40.0, 1.0), &calendar),
+            40.0,
+        );
+    }
+
+    #[test]
+    fn derives_typical_median_and_weighted_prices() {
+        let candle = Candle {
+            start: 0,
+            open: 9.0,
+            high: 12.0,
+            low: 8.0,
+            close: 11.0,
+            volume: 1.0,
+        };
+        let derived = DerivedPrices::of(&candle);
+        // (12 + 8 + 11) / 3, (12 + 8) / 2, and (12 + 8 + 2 * 11) / 4.
+        assert_close(Some(derived.typical_price), 31.0 / 3.0);
+        assert_close(Some(derived.median_price), 10.0);
+        assert_close(Some(derived.weighted_close), 10.5);
+
+        // The open is never used.
+        let derived_open = DerivedPrices::of(&Candle {
+            open: 11.5,
+            ..candle
+        });
+        assert_eq!(derived_open, derived);
+    }
+
+    #[test]
+    fn derives_the_close_from_a_flat_candle() {
+        let derived = DerivedPrices::of(&hlc(7.25, 7.25, 7.25));
+        assert_close(Some(derived.typical_price), 7.25);
+        assert_close(Some(derived.median_price), 7.25);
+        assert_close(Some(derived.weighted_close), 7.25);
+    }
+}
@@ -0,0 +1,199 @@
+//! Writes completed candles to InfluxDB v2 as line protocol points.
+
+use crate::sink::{CandleInfo, CandleSink};
+
+use cbadv::product::Candle;
+use reqwest::{Client, StatusCode};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{sleep, timeout};
+use tracing::warn;
+
+/// Maximum points waiting to be written, the oldest are dropped beyond this.
+const QUEUE_CAPACITY: usize = 10_000;
+/// Points written within a single request.
+const BATCH_SIZE: usize = 500;
+/// Maximum time a point waits before its batch is written.
+const BATCH_INTERVAL: Duration = Duration::from_secs(5);
+/// Attempts made to write a batch before giving up on it.
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled for each following retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Maximum time to wait for InfluxDB to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where and as whom points are written.
+#[derive(Debug, Clone)]
+pub struct InfluxTarget {
+    /// Base URL of the InfluxDB server, such as `http://localhost:8086`.
+    pub url: String,
+    /// Organization owning the bucket.
+    pub org: String,
+    /// Bucket the points are written to.
+    pub bucket: String,
+    /// API token with write access to the bucket.
+    pub token: Option<String>,
+}
+
+/// Points waiting to be written, shared with the writing task.
+struct Queue {
+    /// Pending lines, oldest first.
+    lines: Mutex<VecDeque<String>>,
+    /// Wakes the writing task when a batch is full or should be written early.
+    notify: Notify,
+}
+
+/// Writes each completed candle as a `candle` point tagged with its `product_id`,
+/// timestamped with its start. Points are written on a separate task in batches of
+/// up to 500 or every 5 seconds, so a slow or failing server never blocks candle
+/// processing.
+pub struct InfluxSink {
+    queue: Arc<Queue>,
+}
+
+impl InfluxSink {
+    /// Creates the sink and spawns the task writing to `target`.
+    pub fn new(target: InfluxTarget) -> Self {
+        let queue = Arc::new(Queue {
+            lines: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+        });
+
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        tokio::spawn(write_loop(client, target, Arc::clone(&queue)));
+        Self { queue }
+    }
+}
+
+impl CandleSink for InfluxSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        let mut lines = self.queue.lines.lock().unwrap();
+        lines.push_back(line(product_id, candle));
+        if lines.len() > QUEUE_CAPACITY {
+            lines.pop_front();
+            warn!("InfluxDB queue is full, dropped a candle.");
+        }
+        let full = lines.len() >= BATCH_SIZE;
+        drop(lines);
+
+        if full {
+            self.queue.notify.notify_one();
+        }
+    }
+
+    fn flush(&mut self) {
+        // Write what is queued without waiting for the interval.
+        self.queue.notify.notify_one();
+    }
+}
+
+impl Drop for InfluxSink {
+    fn drop(&mut self) {
+        // The task writes the remaining points and stops.
+        self.queue.notify.notify_one();
+    }
+}
+
+/// Line protocol point of a candle.
+fn line(product_id: &str, candle: &Candle) -> String {
+    format!(
+        "candle,product_id={} open={},high={},low={},close={},volume={} {}",
+        escape_tag(product_id),
+        candle.open,
+        candle.high,
+        candle.low,
+        candle.close,
+        candle.volume,
+        candle.start
+    )
+}
+
+/// Escapes the characters that end a tag value.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes queued points in batches until the sink is dropped.
+async fn write_loop(client: Client, target: InfluxTarget, queue: Arc<Queue>) {
+    let url = format!("{}/api/v2/write", target.url.trim_end_matches('/'));
+
+    loop {
+        let _ = timeout(BATCH_INTERVAL, queue.notify.notified()).await;
+
+        loop {
+            let batch: Vec<String> = {
+                let mut lines = queue.lines.lock().unwrap();
+                let len = lines.len().min(BATCH_SIZE);
+                lines.drain(..len).collect()
+            };
+            if batch.is_empty() {
+                break;
+            }
+
+            write(&client, &url, &target, &batch.join("\n")).await;
+        }
+
+        // Only the sink holds the queue besides this task.
+        if Arc::strong_count(&queue) == 1 {
+            return;
+        }
+    }
+}
+
+/// Writes a batch, retrying network failures and server errors with backoff.
+async fn write(client: &Client, url: &str, target: &InfluxTarget, body: &str) {
+    let mut backoff = RETRY_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = client
+            .post(url)
+            .query(&[
+                ("org", target.org.as_str()),
+                ("bucket", target.bucket.as_str()),
+                ("precision", "s"),
+            ])
+            .body(body.to_string());
+        if let Some(token) = &target.token {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+
+        let err = match request.send().await {
+            Ok(res) if res.status().is_success() => return,
+            Ok(res) => {
+                let status = res.status();
+                let transient = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+                let err = format!("{}: {}", status, res.text().await.unwrap_or_default());
+                if !transient {
+                    warn!("InfluxDB rejected a batch of points: {}", err);
+                    return;
+                }
+                err
+            }
+            Err(err) => err.to_string(),
+        };
+
+        warn!(
+            "InfluxDB write failed, attempt {}/{}: {}",
+            attempt, MAX_ATTEMPTS, err
+        );
+        if attempt < MAX_ATTEMPTS {
+            sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Randomizes reconnection delays so many watchers do not reconnect in lockstep.
+
+use rand::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// How the exponential backoff between reconnection attempts is randomized.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Jitter {
+    /// Waits exactly the backoff.
+    None,
+    /// Waits a random delay between 0 and the backoff.
+    #[default]
+    Full,
+    /// Waits half the backoff plus a random delay up to the other half.
+    Equal,
+}
+
+impl Jitter {
+    /// Delay to wait for the current `backoff`, never longer than it.
+    pub fn delay(&self, backoff: Duration) -> Duration {
+        match self {
+            Jitter::None => backoff,
+            Jitter::Full => backoff.mul_f64(rand::thread_rng().gen_range(0.0..=1.0)),
+            Jitter::Equal => {
+                let half = backoff / 2;
+                half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backoff the delays are drawn for.
+    const BACKOFF: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn none_waits_exactly_the_backoff() {
+        for _ in 0..100 {
+            assert_eq!(Jitter::None.delay(BACKOFF), BACKOFF);
+        }
+    }
+
+    #[test]
+    fn full_waits_up_to_the_backoff() {
+        for _ in 0..1_000 {
+            assert!(Jitter::Full.delay(BACKOFF) <= BACKOFF);
+        }
+        assert_eq!(Jitter::Full.delay(Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn equal_waits_between_half_and_all_of_the_backoff() {
+        for _ in 0..1_000 {
+            let delay = Jitter::Equal.delay(BACKOFF);
+            assert!((BACKOFF / 2..=BACKOFF).contains(&delay), "{:?}", delay);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Produces completed candles to a Kafka topic, requires the `kafka` feature.
+
+use crate::sink::{CandleInfo, CandleRecord, CandleSink};
+
+use cbadv::product::Candle;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::KafkaResult;
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::util::Timeout;
+use rdkafka::{ClientContext, Message};
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// Maximum time to wait for queued candles to be delivered on shutdown.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Converts candles into message payloads.
+pub trait CandleSerializer {
+    /// Serializes a single candle.
+    fn serialize(&self, record: &CandleRecord) -> Result<Vec<u8>, String>;
+}
+
+/// Serializes candles as JSON objects.
+pub struct JsonSerializer;
+
+impl CandleSerializer for JsonSerializer {
+    fn serialize(&self, record: &CandleRecord) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(record).map_err(|err| err.to_string())
+    }
+}
+
+/// Logs candles that failed to be delivered.
+struct DeliveryLogger;
+
+impl ClientContext for DeliveryLogger {}
+
+impl ProducerContext for DeliveryLogger {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((err, message)) = result {
+            let key = message
+                .key()
+                .map(String::from_utf8_lossy)
+                .unwrap_or_default();
+            error!(product_id = %key, "Kafka delivery failed: {}", err);
+        }
+    }
+}
+
+/// Produces each completed candle to a topic keyed by its product, so the candles of
+/// a product share a partition. Messages are delivered by a background thread and
+/// failures are reported through delivery callbacks.
+pub struct KafkaSink {
+    producer: ThreadedProducer<DeliveryLogger>,
+    topic: String,
+    serializer: Box<dyn CandleSerializer + Send>,
+}
+
+impl KafkaSink {
+    /// Creates a producer for `brokers`, a comma separated list, serializing as JSON.
+    pub fn new(brokers: &str, topic: String) -> KafkaResult<Self> {
+        Self::with_serializer(brokers, topic, Box::new(JsonSerializer))
+    }
+
+    /// Creates a producer for `brokers` using `serializer` for each candle.
+    pub fn with_serializer(
+        brokers: &str,
+        topic: String,
+        serializer: Box<dyn CandleSerializer + Send>,
+    ) -> KafkaResult<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create_with_context(DeliveryLogger)?;
+
+        Ok(Self {
+            producer,
+            topic,
+            serializer,
+        })
+    }
+}
+
+impl CandleSink for KafkaSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        let payload = match self
+            .serializer
+            .serialize(&CandleRecord::new(product_id, candle, info))
+        {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!(product_id, "unable to serialize candle for Kafka: {}", err);
+                return;
+            }
+        };
+
+        // Only queues the message, never waits on the brokers.
+        let record = BaseRecord::to(&self.topic)
+            .key(product_id)
+            .payload(&payload);
+        if let Err((err, _)) = self.producer.send(record) {
+            warn!(product_id, "unable to queue candle for Kafka: {}", err);
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Err(err) = self.producer.flush(Timeout::After(FLUSH_TIMEOUT)) {
+            error!("Unable to flush candles to Kafka: {}", err);
+        }
+    }
+}
@@ -0,0 +1,3265 @@
+//! Watches candles from the Coinbase Advanced WebSocket, ejecting each candle once a
+//! newer one arrives and passing it to any number of sinks.
+//!
+//! [`TaskTracker`] holds the in-progress candle of each product and records completed
+//! candles to a [`CandleSink`]. [`TrackerHandle`] shares a tracker between reconnects,
+//! and [`candle_watcher()`] drives it from a WebSocket client until Ctrl-C is received.
+
+pub mod aggregator;
+pub mod alerts;
+#[cfg(feature = "arrow")]
+pub mod arrow_sink;
+pub mod backfill;
+pub mod calendar;
+pub mod clock;
+#[cfg(unix)]
+pub mod control;
+pub mod durability;
+pub mod error;
+pub mod extremes;
+pub mod fanout;
+pub mod fields;
+pub mod filter;
+pub mod granularity;
+pub mod health;
+pub mod heikin_ashi;
+pub mod indicators;
+#[cfg(feature = "influx")]
+pub mod influx_sink;
+pub mod jitter;
+#[cfg(feature = "kafka")]
+pub mod kafka_sink;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod movers;
+#[cfg(feature = "mqtt")]
+pub mod mqtt_sink;
+#[cfg(feature = "nats")]
+pub mod nats_sink;
+pub mod observer;
+#[cfg(feature = "parquet")]
+pub mod parquet_sink;
+pub mod patterns;
+pub mod ratelimit;
+pub mod recorder;
+#[cfg(feature = "redis")]
+pub mod redis_sink;
+pub mod replay;
+pub mod rounding;
+pub mod sampling;
+pub mod server;
+pub mod settings;
+pub mod sink;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod state;
+pub mod stats;
+pub mod stream;
+pub mod template;
+pub mod validation;
+pub mod volume;
+pub mod watchdog;
+pub mod webhook;
+
+use cbadv::product::{Candle, CandleUpdate, ListProductsQuery, Product};
+use cbadv::rest::Client as RestClient;
+use cbadv::utils::Result as APIResult;
+use cbadv::websocket::{self, CandlesEvent, Channel, Message, MessageCallback, WebSocketReader};
+
+use aggregator::{Aggregator, Timeframe};
+use alerts::{AlertSink, AlertTracker, Thresholds};
+use backfill::Backfiller;
+use calendar::Calendar;
+use clock::{Clock, SystemClock};
+use durability::FilePolicy;
+pub use error::WatcherError;
+use extremes::{ExtremeDetector, ExtremeSink};
+use fanout::FanOutSink;
+use fields::OutputField;
+use filter::{MessageFilter, PassThrough};
+use futures::future::try_join_all;
+use granularity::Granularity;
+use heikin_ashi::HeikinAshi;
+use indicators::{
+    Atr, Bands, BollingerBands, DerivedPrices, Macd, MacdReading, Rsi, Sma, SmaReading, Vwap,
+};
+use jitter::Jitter;
+use movers::{MoverFilter, MoverSink};
+use observer::{CandleObserver, ConnectionObserver, LogObserver, NoopConnectionObserver};
+use patterns::{PatternSink, PatternTracker};
+use ratelimit::RateLimiter;
+use recorder::Recorder;
+use rounding::{Precision, Rounding};
+use sampling::{Sampler, Sampling};
+use server::BroadcastSink;
+use settings::{OutputFormat, TooManyProducts, WatcherSettings};
+use sink::{CandleInfo, CandleSink, ChannelSink, JsonSink, StdoutSink};
+use state::{SavedCandle, StateVersion, TrackerState, STATE_VERSION};
+use stats::{ProductStats, Stats};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::future;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::signal;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, timeout};
+use tracing::{debug, error, info, warn};
+use validation::{CandleRules, Rejection};
+use volume::{VolumeSpikeDetector, VolumeSpikeSink};
+use watchdog::StaleSink;
+use webhook::WebhookSink;
+
+/// Completed candle starts remembered for each product to ignore duplicates by default.
+const DEFAULT_DEDUPE_WINDOW: usize = 120;
+/// Initial delay before attempting to reconnect.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Maximum delay between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Time between checks for messages arriving within the heartbeat timeout.
+const HEARTBEAT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Default pause between the subscribe messages of a batched subscription.
+const DEFAULT_SUBSCRIBE_DELAY: Duration = Duration::from_millis(250);
+/// Maximum time to wait for the listener to stop during shutdown.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bytes of CSV rows buffered for a file before they are written regardless of the
+/// file policy.
+const CSV_BUFFER_LIMIT: usize = 64 * 1024;
+/// Completed candles buffered by the channel of `TaskTracker::with_channel`.
+const CHANNEL_CAPACITY: usize = 1_024;
+
+/// Appends rows to a CSV file, writing `header` first if the file is new or empty.
+fn append_csv(path: &Path, header: &str, rows: &str, fsync: bool) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    // Only write the header for new / empty files, allows resuming after restarts.
+    let mut output = String::new();
+    if file.metadata()?.len() == 0 {
+        output.push_str(header);
+        output.push('\n');
+    }
+
+    // Single write per file so appends from multiple writers do not interleave.
+    output.push_str(rows);
+    file.write_all(output.as_bytes())?;
+    durability::sync(&file, fsync)
+}
+
+/// Name of the CSV file for a product, suffixed by the timeframe for aggregated candles
+/// and by `ha` for Heikin-Ashi candles.
+fn csv_filename(product_id: &str, info: &CandleInfo) -> String {
+    match info.timeframe {
+        Some(tf) => format!("{}_{}.csv", product_id, tf),
+        None if info.heikin_ashi => format!("{}_ha.csv", product_id),
+        None => format!("{}.csv", product_id),
+    }
+}
+
+/// Tracks the candle watcher task, passing recorded candles to a sink.
+pub struct TaskTracker<S: CandleSink = StdoutSink> {
+    /// Total candle updates passed through `check_candle`.
+    processed: usize,
+    /// Total candles ejected as finished.
+    completed: usize,
+    /// Holds most recent candle processed for each product.
+    candles: HashMap<String, Candle>,
+    /// Starts of the most recently completed candles for each product, oldest first.
+    /// Updates for these candles are duplicates and are ignored.
+    recent: HashMap<String, VecDeque<u64>>,
+    /// Completed candle starts remembered for each product, 0 disables deduplication.
+    dedupe_window: usize,
+    /// Directory to write completed candles to, one CSV file per product.
+    csv_dir: Option<PathBuf>,
+    /// Columns of the CSV files, in order.
+    csv_fields: Vec<OutputField>,
+    /// When the CSV rows are written and whether they are synced to disk.
+    file_policy: FilePolicy,
+    /// Rows of each CSV file waiting to be written.
+    csv_pending: HashMap<PathBuf, String>,
+    /// Unix time, in seconds, the CSV rows were last written.
+    csv_written: u64,
+    /// Granularity of the candles being tracked.
+    granularity: Granularity,
+    /// Higher timeframes that completed candles are aggregated into.
+    timeframes: Vec<Timeframe>,
+    /// Decides where days start for daily bars and the VWAP.
+    calendar: Calendar,
+    /// Aggregators for each product, one per timeframe.
+    aggregators: HashMap<String, Vec<Aggregator>>,
+    /// Notified of gaps and other events in the candle series.
+    observer: Box<dyn CandleObserver + Send>,
+    /// Decides which messages are processed.
+    filter: Box<dyn MessageFilter + Send>,
+    /// Quote currency of each product, such as `USD`.
+    quotes: HashMap<String, String>,
+    /// Closes averaged by the simple moving average, 0 disables it.
+    sma_period: usize,
+    /// Simple moving average of the closes for each product.
+    sma: HashMap<String, Sma>,
+    /// Whether the MACD is calculated for each product.
+    macd_enabled: bool,
+    /// MACD of the closes for each product.
+    macd: HashMap<String, Macd>,
+    /// Changes used by the RSI, 0 disables it.
+    rsi_period: usize,
+    /// RSI of the closes for each product.
+    rsi: HashMap<String, Rsi>,
+    /// Candles averaged by the ATR, 0 disables it.
+    atr_period: usize,
+    /// ATR of the candles for each product.
+    atr: HashMap<String, Atr>,
+    /// Whether the daily VWAP is calculated for each product.
+    vwap_enabled: bool,
+    /// Whether completed candles carry their derived prices.
+    derived_enabled: bool,
+    /// VWAP of the current day for each product.
+    vwap: HashMap<String, Vwap>,
+    /// Checks completed closes against price thresholds.
+    alerts: AlertTracker,
+    /// Detects candlestick patterns in completed candles, `None` if disabled.
+    patterns: Option<PatternTracker>,
+    /// Detects completed candles with unusually high volume, `None` if disabled.
+    volume_spikes: Option<VolumeSpikeDetector>,
+    /// Reports completed candles whose close moved sharply, `None` if disabled.
+    movers: Option<MoverFilter>,
+    /// Reports new highs and lows over the history, `None` if disabled.
+    extremes: Option<ExtremeDetector>,
+    /// Builds Heikin-Ashi candles from the completed candles, `None` if disabled.
+    heikin_ashi: Option<HeikinAshi>,
+    /// Downsamples the completed candles passed to the sink, `None` passes all of them.
+    sampler: Option<Sampler>,
+    /// Rules that candle updates must satisfy, others are rejected.
+    rules: CandleRules,
+    /// Precision of the candles passed to the sink and CSV, `None` passes raw values.
+    rounding: Option<Rounding>,
+    /// Source of the current time, the system time unless replaced.
+    clock: Arc<dyn Clock>,
+    /// Seconds between partial updates forwarded for each product, `None` disables them.
+    partial_interval: Option<u64>,
+    /// Unix time, in seconds, a partial update was last forwarded for each product.
+    partial_sent: HashMap<String, u64>,
+    /// Products updated since partial updates were last forwarded.
+    partial_pending: HashSet<String>,
+    /// Bollinger Bands calculated from the history, `None` if disabled.
+    bollinger: Option<BollingerBands>,
+    /// Completed candles retained for each product, 0 disables the history.
+    history_len: usize,
+    /// Most recent completed candles of each product, oldest first. Kept contiguous so
+    /// it can be borrowed as a slice.
+    history: HashMap<String, VecDeque<Candle>>,
+    /// Receives each recorded candle.
+    sink: S,
+    /// Counters shared with the periodic summary.
+    stats: Arc<Stats>,
+    /// Counters for each product, read by the metrics endpoint.
+    product_stats: HashMap<String, ProductStats>,
+}
+
+impl TaskTracker<StdoutSink> {
+    /// Creates a new tracker that prints candles and does not write them to disk.
+    pub fn new() -> Self {
+        Self::with_sink(StdoutSink::new())
+    }
+}
+
+impl TaskTracker<ChannelSink> {
+    /// Creates a new tracker that sends completed candles to the returned receiver
+    /// instead of printing them. Candles are dropped with a warning while the channel
+    /// is full. Combine a `ChannelSink` with other sinks through `with_sink` to also
+    /// print them.
+    pub fn with_channel() -> (Self, Receiver<(String, Candle)>) {
+        let (sink, receiver) = ChannelSink::new(CHANNEL_CAPACITY);
+        (Self::with_sink(sink), receiver)
+    }
+}
+
+impl<S: CandleSink> TaskTracker<S> {
+    /// Creates a new tracker that passes recorded candles to `sink`.
+    pub fn with_sink(sink: S) -> Self {
+        Self {
+            processed: 0,
+            completed: 0,
+            candles: HashMap::new(),
+            recent: HashMap::new(),
+            dedupe_window: DEFAULT_DEDUPE_WINDOW,
+            csv_dir: None,
+            csv_fields: OutputField::CSV_DEFAULT.to_vec(),
+            file_policy: FilePolicy::default(),
+            csv_pending: HashMap::new(),
+            csv_written: 0,
+            granularity: Granularity::default(),
+            timeframes: vec![],
+            calendar: Calendar::default(),
+            aggregators: HashMap::new(),
+            observer: Box::new(LogObserver),
+            filter: Box::new(PassThrough),
+            quotes: HashMap::new(),
+            sma_period: 0,
+            sma: HashMap::new(),
+            macd_enabled: false,
+            macd: HashMap::new(),
+            rsi_period: 0,
+            rsi: HashMap::new(),
+            atr_period: 0,
+            atr: HashMap::new(),
+            vwap_enabled: false,
+            derived_enabled: false,
+            vwap: HashMap::new(),
+            alerts: AlertTracker::default(),
+            patterns: None,
+            volume_spikes: None,
+            movers: None,
+            extremes: None,
+            heikin_ashi: None,
+            sampler: None,
+            rules: CandleRules::default(),
+            rounding: None,
+            clock: Arc::new(SystemClock),
+            partial_interval: None,
+            partial_sent: HashMap::new(),
+            partial_pending: HashSet::new(),
+            bollinger: None,
+            history_len: 0,
+            history: HashMap::new(),
+            sink,
+            stats: Arc::new(Stats::default()),
+            product_stats: HashMap::new(),
+        }
+    }
+
+    /// Also appends recorded candles to CSV files within `dir`, one for each product
+    /// and series, such as `TaskTracker::new().with_csv_dir(dir)`.
+    pub fn with_csv_dir(mut self, dir: PathBuf) -> Self {
+        self.csv_dir = Some(dir);
+        self
+    }
+
+    /// Total candle updates processed.
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    /// Total candles ejected as finished.
+    pub fn completed(&self) -> usize {
+        self.completed
+    }
+
+    /// Counters that can be read without locking the tracker.
+    pub fn stats(&self) -> Arc<Stats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Sink receiving the recorded candles.
+    pub(crate) fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Current Unix time, in seconds, according to the clock of the tracker.
+    pub fn now(&self) -> u64 {
+        self.clock.unix_now()
+    }
+
+    /// Clock the tracker reads the current time from.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+
+    /// Replaces the clock the tracker and its timer tasks read the current time from,
+    /// such as with a [`clock::MockClock`] to control time-based behavior.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Counters for each product that has received an update.
+    pub fn product_stats(&self) -> &HashMap<String, ProductStats> {
+        &self.product_stats
+    }
+
+    /// Start of the most recent candle for each product.
+    pub fn latest_starts(&self) -> Vec<(String, u64)> {
+        let mut starts: Vec<(String, u64)> = self
+            .candles
+            .iter()
+            .map(|(product_id, candle)| (product_id.clone(), candle.start))
+            .collect();
+
+        // Finalized products have no in-progress candle until their next update.
+        for product_id in self.recent.keys() {
+            if !self.candles.contains_key(product_id) {
+                if let Some(start) = self.last_completed(product_id) {
+                    starts.push((product_id.clone(), start));
+                }
+            }
+        }
+        starts
+    }
+
+    /// Granularity of the candles being tracked.
+    pub fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// Sets the granularity of the candles being tracked, gaps and aggregation are
+    /// based on its length.
+    pub fn set_granularity(&mut self, granularity: Granularity) {
+        self.granularity = granularity;
+        self.aggregators.clear();
+    }
+
+    /// Sets the higher timeframes that completed candles are aggregated into.
+    pub fn set_timeframes(&mut self, timeframes: Vec<Timeframe>) {
+        self.timeframes = timeframes;
+        self.aggregators.clear();
+    }
+
+    /// Sets the calendar the daily bars and VWAP start over by, UTC by default.
+    pub fn set_calendar(&mut self, calendar: Calendar) {
+        self.calendar = calendar;
+        self.aggregators.clear();
+        self.vwap.clear();
+    }
+
+    /// Sets the amount of closes averaged by the simple moving average, 0 disables it.
+    pub fn set_sma_period(&mut self, period: usize) {
+        self.sma_period = period;
+        self.sma.clear();
+    }
+
+    /// Enables or disables calculating the MACD for each product.
+    pub fn set_macd(&mut self, enabled: bool) {
+        self.macd_enabled = enabled;
+        self.macd.clear();
+    }
+
+    /// Sets the amount of changes used by the RSI, 0 disables it.
+    pub fn set_rsi_period(&mut self, period: usize) {
+        self.rsi_period = period;
+        self.rsi.clear();
+    }
+
+    /// Sets the candles averaged by the ATR, 0 disables it.
+    pub fn set_atr_period(&mut self, period: usize) {
+        self.atr_period = period;
+        self.atr.clear();
+    }
+
+    /// Sets whether completed candles are recorded with their typical price, median
+    /// price, and weighted close.
+    pub fn set_derived(&mut self, enabled: bool) {
+        self.derived_enabled = enabled;
+    }
+
+    /// Sets whether the daily VWAP is calculated for each product.
+    pub fn set_vwap(&mut self, enabled: bool) {
+        self.vwap_enabled = enabled;
+        self.vwap.clear();
+    }
+
+    /// Sets the price thresholds for each product and where alerts are sent.
+    pub fn set_alerts(
+        &mut self,
+        thresholds: HashMap<String, Thresholds>,
+        sink: Box<dyn AlertSink + Send>,
+    ) {
+        self.alerts = AlertTracker::new(thresholds, sink);
+    }
+
+    /// Enables detection of candlestick patterns, passing them to `sink`.
+    pub fn set_patterns(&mut self, sink: Box<dyn PatternSink + Send>) {
+        self.patterns = Some(PatternTracker::new(sink));
+    }
+
+    /// Sets whether a Heikin-Ashi candle is recorded after each completed candle, as a
+    /// separate series of the product.
+    pub fn set_heikin_ashi(&mut self, enabled: bool) {
+        self.heikin_ashi = enabled.then(HeikinAshi::new);
+    }
+
+    /// Sets how many completed candle starts are remembered for each product to ignore
+    /// duplicate updates, the oldest are forgotten first. 0 disables deduplication.
+    pub fn set_dedupe_window(&mut self, window: usize) {
+        self.dedupe_window = window;
+        for starts in self.recent.values_mut() {
+            let excess = starts.len().saturating_sub(window.max(1));
+            starts.drain(..excess);
+        }
+        self.recent.retain(|_, starts| !starts.is_empty());
+    }
+
+    /// Sets the amount of completed candles retained for each product, 0 disables it.
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len;
+        self.history.clear();
+    }
+
+    /// Enables Bollinger Bands over `period` closes, `k` standard deviations wide. The
+    /// history is extended to at least `period` candles.
+    pub fn set_bollinger(&mut self, period: usize, k: f64) {
+        self.bollinger = Some(BollingerBands::new(period, k));
+    }
+
+    /// Most recent completed candles of a product, oldest first. Holds at least the
+    /// latest completed candle, empty if the product has not completed one.
+    pub fn history(&self, product_id: &str) -> &[Candle] {
+        match self.history.get(product_id) {
+            Some(history) => history.as_slices().0,
+            None => &[],
+        }
+    }
+
+    /// Enables detection of completed candles whose volume is at least `multiplier`
+    /// times the mean of the previous `window` candles, passing them to `sink`.
+    pub fn set_volume_spikes(
+        &mut self,
+        window: usize,
+        multiplier: f64,
+        sink: Box<dyn VolumeSpikeSink + Send>,
+    ) {
+        self.volume_spikes = Some(VolumeSpikeDetector::new(window, multiplier, sink));
+    }
+
+    /// Passes completed candles whose close changed by more than `threshold` percent
+    /// from the previous close to `sink`.
+    pub fn set_movers(&mut self, threshold: f64, sink: Box<dyn MoverSink + Send>) {
+        self.movers = Some(MoverFilter::new(threshold, sink));
+    }
+
+    /// Passes only a sample of the completed candles of each product to the sink, using
+    /// `default` for products not in `products`. Every candle still updates the
+    /// indicators and aggregators.
+    pub fn set_sampling(&mut self, default: Sampling, products: HashMap<String, Sampling>) {
+        if default.is_disabled() && products.values().all(Sampling::is_disabled) {
+            self.sampler = None;
+        } else {
+            self.sampler = Some(Sampler::new(default, products));
+        }
+    }
+
+    /// Writes only `fields` of each candle to the CSV files, in order. New files get
+    /// a matching header, empty restores the default columns.
+    pub fn set_output_fields(&mut self, fields: Vec<OutputField>) {
+        self.csv_fields = match fields.is_empty() {
+            true => OutputField::CSV_DEFAULT.to_vec(),
+            false => fields,
+        };
+    }
+
+    /// Sets how often the CSV rows are written and whether they are synced to disk. By
+    /// default every row is written as it is recorded. Rows are always written and
+    /// synced by `flush`.
+    pub fn set_file_policy(&mut self, policy: FilePolicy) {
+        self.file_policy = policy;
+    }
+
+    /// Rounds the candles passed to the sink and written to CSV, indicators are still
+    /// calculated from the raw values.
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.rounding = (!rounding.is_disabled()).then_some(rounding);
+    }
+
+    /// Forwards updates of the in-progress candles to the sink as partial candles, at
+    /// most one for each product every `interval` seconds, 0 forwards all of them.
+    /// `None` only passes candles once they complete.
+    pub fn set_partial_interval(&mut self, interval: Option<u64>) {
+        self.partial_interval = interval;
+        self.partial_sent.clear();
+        self.partial_pending.clear();
+    }
+
+    /// Sets the rules that candle updates must satisfy, malformed updates are rejected
+    /// before reaching the series.
+    pub fn set_candle_rules(&mut self, rules: CandleRules) {
+        self.rules = rules;
+    }
+
+    /// Passes completed candles whose high or low exceeds that of the previous `period`
+    /// candles to `sink`. The history is extended to at least `period` candles.
+    pub fn set_extremes(&mut self, period: usize, sink: Box<dyn ExtremeSink + Send>) {
+        self.extremes = Some(ExtremeDetector::new(period, sink));
+    }
+
+    /// Clears all state held for a product, allowing it to start over cleanly.
+    pub fn reset(&mut self, product_id: &str) {
+        self.candles.remove(product_id);
+        self.recent.remove(product_id);
+        self.aggregators.remove(product_id);
+        self.sma.remove(product_id);
+        self.macd.remove(product_id);
+        self.rsi.remove(product_id);
+        self.vwap.remove(product_id);
+        self.atr.remove(product_id);
+        self.history.remove(product_id);
+        self.partial_sent.remove(product_id);
+        self.partial_pending.remove(product_id);
+        self.alerts.reset(product_id);
+        if let Some(patterns) = &mut self.patterns {
+            patterns.reset(product_id);
+        }
+        if let Some(volume_spikes) = &mut self.volume_spikes {
+            volume_spikes.reset(product_id);
+        }
+        if let Some(heikin_ashi) = &mut self.heikin_ashi {
+            heikin_ashi.reset(product_id);
+        }
+    }
+
+    /// Writes the in-progress candles, history, and indicator state to `path` as JSON,
+    /// so they can be restored by `load_state` after a restart.
+    pub fn save_state(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(&self.state()).map_err(|err| err.to_string())?;
+        fs::write(path, json).map_err(|err| err.to_string())
+    }
+
+    /// Copy of the in-progress candles, history, and indicator state of every product.
+    pub(crate) fn state(&self) -> TrackerState {
+        TrackerState {
+            version: STATE_VERSION,
+            saved_at: self.now(),
+            candles: self
+                .candles
+                .iter()
+                .map(|(product_id, candle)| (product_id.clone(), SavedCandle::from(candle)))
+                .collect(),
+            sma: self.sma.clone(),
+            macd: self.macd.clone(),
+            rsi: self.rsi.clone(),
+            vwap: self.vwap.clone(),
+            atr: self.atr.clone(),
+            history: self
+                .history
+                .iter()
+                .map(|(product_id, candles)| {
+                    let candles = candles.iter().map(SavedCandle::from).collect();
+                    (product_id.clone(), candles)
+                })
+                .collect(),
+            recent: self
+                .recent
+                .iter()
+                .map(|(product_id, starts)| (product_id.clone(), starts.iter().copied().collect()))
+                .collect(),
+            aggregators: self
+                .aggregators
+                .iter()
+                .map(|(product_id, aggregators)| {
+                    let partials = aggregators
+                        .iter()
+                        .filter_map(|aggregator| {
+                            let candle = aggregator.partial()?;
+                            Some((aggregator.timeframe(), SavedCandle::from(candle)))
+                        })
+                        .collect();
+                    (product_id.clone(), partials)
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the state written by `save_state`, returning whether it was restored.
+    /// Missing files and state older than one candle of the granularity are ignored,
+    /// files of another version are errors.
+    pub fn load_state(&mut self, path: &Path) -> Result<bool, String> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let version: StateVersion = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        if version.version != STATE_VERSION {
+            return Err(format!(
+                "unsupported state version {}, expected {}",
+                version.version, STATE_VERSION
+            ));
+        }
+
+        let state: TrackerState = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        if self.now().saturating_sub(state.saved_at) > self.granularity.seconds() {
+            // The candles would have completed while stopped, their updates were missed.
+            return Ok(false);
+        }
+
+        self.candles = state
+            .candles
+            .into_iter()
+            .map(|(product_id, candle)| (product_id, Candle::from(candle)))
+            .collect();
+        self.sma = state.sma;
+        self.macd = state.macd;
+        self.rsi = state.rsi;
+        self.vwap = state.vwap;
+        self.atr = state.atr;
+
+        // Trimmed in case the history or dedupe window shrank while stopped.
+        let len = self.history_capacity();
+        self.history = state
+            .history
+            .into_iter()
+            .map(|(product_id, candles)| {
+                let skip = candles.len().saturating_sub(len);
+                let candles = candles.into_iter().skip(skip).map(Candle::from).collect();
+                (product_id, candles)
+            })
+            .collect();
+        let window = self.dedupe_window.max(1);
+        self.recent = state
+            .recent
+            .into_iter()
+            .map(|(product_id, starts)| {
+                let skip = starts.len().saturating_sub(window);
+                (product_id, starts.into_iter().skip(skip).collect())
+            })
+            .collect();
+
+        // Partial candles of timeframes no longer configured are dropped.
+        let interval = self.granularity.seconds();
+        let aggregators = state
+            .aggregators
+            .into_iter()
+            .map(|(product_id, mut partials)| {
+                let aggregators = self
+                    .timeframes
+                    .iter()
+                    .map(|tf| {
+                        let mut aggregator =
+                            Aggregator::with_calendar(*tf, interval, self.calendar);
+                        if let Some(candle) = partials.remove(tf) {
+                            aggregator.resume(Candle::from(candle));
+                        }
+                        aggregator
+                    })
+                    .collect();
+                (product_id, aggregators)
+            })
+            .collect();
+        self.aggregators = aggregators;
+        Ok(true)
+    }
+
+    /// Sets the observer notified of gaps and other events.
+    pub fn set_observer(&mut self, observer: Box<dyn CandleObserver + Send>) {
+        self.observer = observer;
+    }
+
+    /// Sets the quote currency of each product, products without one use the part of
+    /// their ID after the last `-`.
+    pub fn set_quotes(&mut self, quotes: HashMap<String, String>) {
+        self.quotes = quotes;
+    }
+
+    /// Quote currency of the market a product trades in.
+    pub fn quote(&self, product_id: &str) -> String {
+        match self.quotes.get(product_id) {
+            Some(quote) => quote.clone(),
+            None => quote_of(product_id).to_string(),
+        }
+    }
+
+    /// Sets the upper bounds, in seconds, of the completion latency buckets.
+    pub fn set_latency_buckets(&mut self, bounds: Vec<f64>) {
+        self.stats.set_latency_buckets(bounds);
+    }
+
+    /// Sets the filter consulted before each message is processed.
+    pub fn set_filter(&mut self, filter: Box<dyn MessageFilter + Send>) {
+        self.filter = filter;
+    }
+
+    /// Drains all in-progress candles, recording each of them as incomplete.
+    pub fn flush(&mut self) {
+        let candles: Vec<(String, Candle)> = self.candles.drain().collect();
+        for (product_id, candle) in candles {
+            let info = self.info(&product_id, None, false);
+            self.record(&product_id, &candle, &info);
+        }
+
+        // Partial candles of the higher timeframes.
+        let aggregators: Vec<(String, Vec<Aggregator>)> = self.aggregators.drain().collect();
+        for (product_id, mut aggregators) in aggregators {
+            for aggregator in aggregators.iter_mut() {
+                if let Some(candle) = aggregator.flush() {
+                    let info = self.info(&product_id, Some(aggregator.timeframe()), false);
+                    self.record(&product_id, &candle, &info);
+                }
+            }
+        }
+
+        // Products start over if they are tracked again.
+        self.sma.clear();
+        self.macd.clear();
+        self.rsi.clear();
+        self.vwap.clear();
+        self.atr.clear();
+        self.history.clear();
+
+        self.flush_buffers();
+    }
+
+    /// Writes the buffered CSV rows and flushes the sink, without recording the
+    /// in-progress candles. Used instead of `flush` when they were saved by `save_state`
+    /// and resume after a restart, so they are only recorded once they complete.
+    pub fn flush_buffers(&mut self) {
+        // Nothing buffered may be lost on a graceful shutdown.
+        if let Err(err) = self.write_pending_csv(true) {
+            error!("unable to write candles to CSV: {}", err);
+        }
+        self.sink.flush();
+    }
+
+    /// Passes the in-progress candle of each product to the sink as a snapshot, they
+    /// are not written to CSV. Returns the amount of candles passed.
+    pub fn snapshot(&mut self) -> usize {
+        let products: Vec<String> = self.candles.keys().cloned().collect();
+        for product_id in &products {
+            let mut info = self.info(product_id, None, false);
+            info.snapshot = true;
+            self.record_in_progress(product_id, &info);
+        }
+        products.len()
+    }
+
+    /// Forwards the in-progress candle of each product updated since the last call as
+    /// a partial candle, unless one was forwarded within the interval.
+    fn send_partials(&mut self) {
+        let interval = match self.partial_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        let now = self.now();
+        let pending: Vec<String> = self.partial_pending.drain().collect();
+        for product_id in pending {
+            let last = self.partial_sent.get(&product_id).copied();
+            if last.is_some_and(|last| now.saturating_sub(last) < interval) {
+                // Forwarded by a later message, such as a heartbeat, once the interval passed.
+                self.partial_pending.insert(product_id);
+                continue;
+            }
+
+            let mut info = self.info(&product_id, None, false);
+            info.partial = true;
+            if self.record_in_progress(&product_id, &info) {
+                self.partial_sent.insert(product_id, now);
+            }
+        }
+    }
+
+    /// Passes the in-progress candle of a product to the sink without writing it to
+    /// CSV, returning whether the product has one.
+    fn record_in_progress(&mut self, product_id: &str, info: &CandleInfo) -> bool {
+        let candle = match self.candles.get(product_id) {
+            Some(candle) => candle,
+            None => return false,
+        };
+        let rounded = self
+            .rounding
+            .as_ref()
+            .and_then(|r| r.round(product_id, candle));
+        self.sink
+            .on_candle(product_id, rounded.as_ref().unwrap_or(candle), info);
+        true
+    }
+
+    /// Passes a single candle update through the tracker as if it arrived from the
+    /// WebSocket, completing the previous candle of the product once a newer one
+    /// arrives. Returns whether the update was processed, malformed updates are
+    /// rejected and duplicates are skipped.
+    pub fn ingest(&mut self, product_id: &str, candle: Candle) -> bool {
+        let processed = self.processed;
+        let done = match self.update(product_id, candle) {
+            Ok(done) => done,
+            Err(_) => return false,
+        };
+        if self.processed == processed {
+            // Skipped by `accept` as a duplicate.
+            return false;
+        }
+
+        if let Some(done) = done {
+            self.complete(product_id, done);
+        }
+        self.send_partials();
+
+        let now = self.now();
+        self.product_stats
+            .entry(product_id.to_string())
+            .or_default()
+            .last_update = now;
+        self.stats
+            .update(self.processed, self.completed, self.candles.len());
+        true
+    }
+
+    /// Processes a single update, returning the previous candle once it completed.
+    /// Malformed updates are rejected before they reach the series.
+    fn update(&mut self, product_id: &str, candle: Candle) -> Result<Option<Candle>, Rejection> {
+        self.validate(product_id, &candle)?;
+        if !self.accept(product_id, candle.start) {
+            return Ok(None);
+        }
+
+        debug!(
+            product_id,
+            start = candle.start,
+            close = candle.close,
+            "candle update."
+        );
+        if self.partial_interval.is_some() {
+            self.partial_pending.insert(product_id.to_string());
+        }
+        Ok(self.check_candle(product_id, candle))
+    }
+
+    /// Start of the in-progress candle of a product, if it has one.
+    pub fn in_progress_start(&self, product_id: &str) -> Option<u64> {
+        self.candles.get(product_id).map(|candle| candle.start)
+    }
+
+    /// Reconciles the in-progress candle of a product with candles fetched after the
+    /// connection was lost, oldest first. The fetched candle with the same start
+    /// replaces it unless it holds less volume, newer candles are then processed as
+    /// updates and complete it. The newest candle may not be finalized by the API yet,
+    /// so it stays in progress until the live feed moves on. Returns the amount of
+    /// candles applied.
+    pub fn refresh(&mut self, product_id: &str, candles: Vec<Candle>) -> usize {
+        let current = match self.in_progress_start(product_id) {
+            Some(start) => start,
+            None => return 0,
+        };
+
+        let mut applied: usize = 0;
+        for candle in candles {
+            if candle.start < current || self.validate(product_id, &candle).is_err() {
+                continue;
+            }
+
+            if candle.start == current {
+                // Updates missed while disconnected only ever add volume.
+                if let Some(existing) = self.candles.get_mut(product_id) {
+                    if candle.volume >= existing.volume {
+                        *existing = candle;
+                        applied += 1;
+                    }
+                }
+                continue;
+            }
+
+            if let Ok(Some(done)) = self.update(product_id, candle) {
+                self.complete(product_id, done);
+            }
+            applied += 1;
+        }
+
+        self.stats
+            .update(self.processed, self.completed, self.candles.len());
+        applied
+    }
+
+    /// Replays candles obtained elsewhere (oldest first) through the completion path.
+    /// Candles at or after the in-progress candle are already tracked and skipped.
+    /// Returns the amount of candles replayed.
+    pub fn replay(&mut self, product_id: &str, candles: Vec<Candle>) -> usize {
+        let current = self.candles.get(product_id).map(|c| c.start);
+
+        let mut replayed: usize = 0;
+        for candle in candles {
+            if current.is_some_and(|start| candle.start >= start)
+                || self.validate(product_id, &candle).is_err()
+                || !self.accept(product_id, candle.start)
+            {
+                continue;
+            }
+
+            replayed += 1;
+            self.complete(product_id, candle);
+        }
+
+        self.stats
+            .update(self.processed, self.completed, self.candles.len());
+        replayed
+    }
+
+    /// Checks a candle update against the rules, logging and counting a rejection.
+    fn validate(&self, product_id: &str, candle: &Candle) -> Result<(), Rejection> {
+        let result = self.rules.check(candle, self.now());
+        if let Err(reason) = &result {
+            warn!(
+                product_id,
+                start = candle.start,
+                open = candle.open,
+                high = candle.high,
+                low = candle.low,
+                close = candle.close,
+                volume = candle.volume,
+                "rejected candle update, {}.",
+                reason
+            );
+            self.stats.record_rejected();
+        }
+        result
+    }
+
+    /// Counts a candle of a product as processed, unless it starts at a candle that
+    /// already completed. Those are resent after a reconnect or were already
+    /// backfilled, and are skipped without advancing any counter.
+    fn accept(&mut self, product_id: &str, start: u64) -> bool {
+        if self.is_duplicate(product_id, start) {
+            debug!(product_id, start, "skipped update of a completed candle.");
+            return false;
+        }
+
+        self.processed += 1;
+        true
+    }
+
+    /// Whether the candle starting at `start` has already completed for the product.
+    fn is_duplicate(&self, product_id: &str, start: u64) -> bool {
+        self.dedupe_window > 0
+            && self
+                .recent
+                .get(product_id)
+                .is_some_and(|starts| starts.contains(&start))
+    }
+
+    /// Remembers a completed candle so later updates for it are ignored. The latest
+    /// is kept even without deduplication to detect gaps and late updates.
+    fn remember(&mut self, product_id: &str, start: u64) {
+        let starts = self.recent.entry(product_id.to_string()).or_default();
+        starts.push_back(start);
+        if starts.len() > self.dedupe_window.max(1) {
+            starts.pop_front();
+        }
+    }
+
+    /// Records a completed candle along with any higher timeframe candles it completes.
+    fn complete(&mut self, product_id: &str, candle: Candle) {
+        self.remember(product_id, candle.start);
+        self.completed += 1;
+        self.product_stats
+            .entry(product_id.to_string())
+            .or_default()
+            .completed += 1;
+        let mut info = self.info(product_id, None, true);
+        info.sma = self.update_sma(product_id, &candle);
+        info.macd = self.update_macd(product_id, &candle);
+        info.rsi = self.update_rsi(product_id, &candle);
+        info.vwap = self.update_vwap(product_id, &candle);
+        info.atr = self.update_atr(product_id, &candle);
+        info.change = self.change(product_id, &candle);
+        info.derived = self.derived_enabled.then(|| DerivedPrices::of(&candle));
+        self.check_extremes(product_id, &candle);
+        self.remember_history(product_id, &candle);
+        info.bollinger = self.update_bollinger(product_id);
+        self.record(product_id, &candle, &info);
+        if let Some(heikin_ashi) = &mut self.heikin_ashi {
+            let smoothed = heikin_ashi.update(product_id, &candle);
+            let mut info = self.info(product_id, None, true);
+            info.heikin_ashi = true;
+            self.record(product_id, &smoothed, &info);
+        }
+        self.alerts.check(product_id, &candle);
+        if let Some(patterns) = &mut self.patterns {
+            patterns.check(product_id, &candle);
+        }
+        if let Some(volume_spikes) = &mut self.volume_spikes {
+            volume_spikes.check(product_id, &candle);
+        }
+        if let Some(movers) = &mut self.movers {
+            movers.check(product_id, &candle, info.change);
+        }
+
+        for (timeframe, aggregated) in self.aggregate(product_id, &candle) {
+            let mut info = self.info(product_id, Some(timeframe), true);
+            info.derived = self.derived_enabled.then(|| DerivedPrices::of(&aggregated));
+            self.record(product_id, &aggregated, &info);
+        }
+    }
+
+    /// Adds a completed candle to the products daily VWAP, the outer `Option` is
+    /// `None` when the VWAP is disabled.
+    fn update_vwap(&mut self, product_id: &str, candle: &Candle) -> Option<Option<f64>> {
+        if !self.vwap_enabled {
+            return None;
+        }
+
+        let vwap = self.vwap.entry(product_id.to_string()).or_default();
+        Some(vwap.update(candle, &self.calendar))
+    }
+
+    /// Adds a completed candle to the products ATR, the outer `Option` is `None` when
+    /// the ATR is disabled.
+    fn update_atr(&mut self, product_id: &str, candle: &Candle) -> Option<Option<f64>> {
+        if self.atr_period == 0 {
+            return None;
+        }
+
+        let period = self.atr_period;
+        let atr = self
+            .atr
+            .entry(product_id.to_string())
+            .or_insert_with(|| Atr::new(period));
+        Some(atr.update(candle))
+    }
+
+    /// Adds a completed candles close to the products RSI, the outer `Option` is
+    /// `None` when the RSI is disabled.
+    fn update_rsi(&mut self, product_id: &str, candle: &Candle) -> Option<Option<f64>> {
+        if self.rsi_period == 0 {
+            return None;
+        }
+
+        let period = self.rsi_period;
+        let rsi = self
+            .rsi
+            .entry(product_id.to_string())
+            .or_insert_with(|| Rsi::new(period));
+        Some(rsi.update(candle.close))
+    }
+
+    /// Adds a completed candles close to the products MACD.
+    fn update_macd(&mut self, product_id: &str, candle: &Candle) -> Option<MacdReading> {
+        if !self.macd_enabled {
+            return None;
+        }
+
+        let macd = self.macd.entry(product_id.to_string()).or_default();
+        Some(macd.update(candle.close))
+    }
+
+    /// Adds a completed candles close to the products moving average.
+    fn update_sma(&mut self, product_id: &str, candle: &Candle) -> Option<SmaReading> {
+        if self.sma_period == 0 {
+            return None;
+        }
+
+        let period = self.sma_period;
+        let sma = self
+            .sma
+            .entry(product_id.to_string())
+            .or_insert_with(|| Sma::new(period));
+        Some(SmaReading {
+            period,
+            value: sma.update(candle.close),
+        })
+    }
+
+    /// Seeds a product with historic candles (oldest first) without recording them.
+    /// The newest candle becomes the in-progress candle. Products that are already
+    /// being tracked are left untouched. Returns the amount of candles seeded.
+    pub fn seed(&mut self, product_id: &str, mut candles: Vec<Candle>) -> usize {
+        if self.candles.contains_key(product_id) {
+            return 0;
+        }
+
+        candles.retain(|candle| self.validate(product_id, candle).is_ok());
+        let seeded = candles.len();
+        let current = match candles.pop() {
+            Some(candle) => candle,
+            None => return 0,
+        };
+
+        for candle in candles {
+            self.update_sma(product_id, &candle);
+            self.update_macd(product_id, &candle);
+            self.update_rsi(product_id, &candle);
+            self.update_vwap(product_id, &candle);
+            self.update_atr(product_id, &candle);
+            self.remember_history(product_id, &candle);
+            self.aggregate(product_id, &candle);
+        }
+        self.observer.on_first_candle(product_id, &current);
+        self.candles.insert(product_id.to_string(), current);
+        seeded
+    }
+
+    /// Passes a completed candle to the products aggregators, returning the higher
+    /// timeframe candles that completed.
+    fn aggregate(&mut self, product_id: &str, candle: &Candle) -> Vec<(Timeframe, Candle)> {
+        let timeframes = &self.timeframes;
+        let interval = self.granularity.seconds();
+        let calendar = self.calendar;
+        let aggregators = self
+            .aggregators
+            .entry(product_id.to_string())
+            .or_insert_with(|| {
+                timeframes
+                    .iter()
+                    .map(|tf| Aggregator::with_calendar(*tf, interval, calendar))
+                    .collect()
+            });
+
+        let mut completed: Vec<(Timeframe, Candle)> = vec![];
+        for aggregator in aggregators.iter_mut() {
+            for aggregated in aggregator.update(candle) {
+                completed.push((aggregator.timeframe(), aggregated));
+            }
+        }
+        completed
+    }
+
+    /// Details for a candle being recorded, indicators are filled in by the caller.
+    fn info(&self, product_id: &str, timeframe: Option<Timeframe>, complete: bool) -> CandleInfo {
+        CandleInfo {
+            processed: self.processed,
+            quote: self.quote(product_id),
+            timeframe,
+            complete,
+            ..Default::default()
+        }
+    }
+
+    /// Passes a candle that is either complete or was flushed before completion to
+    /// the sink. Aggregated candles carry the timeframe they were built for.
+    fn record(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        let rounded = self
+            .rounding
+            .as_ref()
+            .and_then(|r| r.round(product_id, candle));
+        let candle = rounded.as_ref().unwrap_or(candle);
+
+        // Only completed candles of the subscribed granularity are sampled.
+        let sampled = info.complete && info.is_native() && !info.snapshot;
+        let now = self.now();
+        let keep = match &mut self.sampler {
+            Some(sampler) if sampled => sampler.keep(product_id, now),
+            _ => true,
+        };
+        if keep {
+            self.sink.on_candle(product_id, candle, info);
+        }
+
+        if let Err(err) = self.write_csv(product_id, candle, info) {
+            error!(product_id, "unable to write candle to CSV: {}", err);
+        }
+    }
+
+    /// Appends a completed candle to the products CSV file, if a directory is set.
+    /// Aggregated and Heikin-Ashi candles are written to a separate file per series.
+    /// Rows are buffered until the file policy writes them.
+    fn write_csv(
+        &mut self,
+        product_id: &str,
+        candle: &Candle,
+        info: &CandleInfo,
+    ) -> io::Result<()> {
+        let path = match &self.csv_dir {
+            // Rows have no marker, in-progress candles flushed on shutdown would look
+            // like completed ones.
+            Some(_) if !info.complete => return Ok(()),
+            Some(dir) => dir.join(csv_filename(product_id, info)),
+            None => return Ok(()),
+        };
+
+        let rows = self.csv_pending.entry(path).or_default();
+        rows.push_str(&fields::csv_row(&self.csv_fields, product_id, candle));
+        rows.push('\n');
+
+        let full = rows.len() >= CSV_BUFFER_LIMIT;
+        let elapsed = self.now().saturating_sub(self.csv_written);
+        if full || self.file_policy.is_due(elapsed) {
+            self.write_pending_csv(self.file_policy.fsync())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered CSV rows once the flush interval has elapsed since they
+    /// were last written.
+    fn write_due_csv(&mut self) {
+        let elapsed = self.now().saturating_sub(self.csv_written);
+        if self.csv_pending.is_empty() || !self.file_policy.is_due(elapsed) {
+            return;
+        }
+
+        if let Err(err) = self.write_pending_csv(self.file_policy.fsync()) {
+            error!("unable to write candles to CSV: {}", err);
+        }
+    }
+
+    /// Appends the buffered rows to their CSV files, syncing each to disk if `fsync`.
+    fn write_pending_csv(&mut self, fsync: bool) -> io::Result<()> {
+        self.csv_written = self.now();
+        let header = fields::csv_header(&self.csv_fields);
+        let mut result = Ok(());
+        for (path, rows) in std::mem::take(&mut self.csv_pending) {
+            if let Err(err) = append_csv(&path, &header, &rows, fsync) {
+                error!("unable to write '{}': {}", path.display(), err);
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// Processes a message, returning the candles it completed without recording them.
+    pub fn eject(&mut self, msg: APIResult<Message>) -> Vec<(String, Candle)> {
+        // Messages dropped by the filter still prove the connection is alive.
+        if let Ok(message) = &msg {
+            if !self.filter.keep(message) {
+                self.stats.record_message(self.now());
+                return vec![];
+            }
+        }
+
+        // Filter all non-candle and empty updates.
+        let ev: Vec<CandlesEvent> = match msg {
+            Ok(value) => match value {
+                Message::Heartbeats(_) => {
+                    // Heartbeats only prove the connection is alive.
+                    self.stats.record_message(self.now());
+                    return vec![];
+                }
+                Message::Candles(value) => {
+                    self.stats.record_message(self.now());
+                    if value.events.len() == 0 {
+                        // No events / updates to process.
+                        return vec![];
+                    }
+                    // Events being worked with.
+                    value.events
+                }
+                // Non-candle message.
+                _ => {
+                    self.stats.record_message(self.now());
+                    return vec![];
+                }
+            },
+            // WebSocket error.
+            Err(err) => {
+                error!("WebSocket error: {}", err);
+                return vec![];
+            }
+        };
+
+        // Combine all updates.
+        let updates: Vec<CandleUpdate> = ev.into_iter().flat_map(|c| c.candles).collect();
+
+        let now = self.now();
+        // Group the updates by product, a message may contain several for each.
+        let mut grouped: HashMap<String, Vec<Candle>> = HashMap::new();
+        for update in updates {
+            grouped
+                .entry(update.product_id)
+                .or_default()
+                .push(update.data);
+        }
+
+        // Check the candles oldest -> newest, see if there are completed cycles.
+        let mut completed: Vec<(String, Candle)> = vec![];
+        for (product_id, mut candles) in grouped {
+            // Updates normally arrive in order, only sort when they did not. Stable sort,
+            // later updates of the same candle replace earlier ones.
+            if !candles.windows(2).all(|w| w[0].start <= w[1].start) {
+                candles.sort_by(|a, b| a.start.cmp(&b.start));
+            }
+            for candle in candles {
+                // Only valid updates count towards the lag and freshness of the product.
+                let lag = now.saturating_sub(candle.start);
+                let done = match self.update(&product_id, candle) {
+                    Ok(done) => done,
+                    Err(_) => continue,
+                };
+                self.stats.observe_lag(lag);
+                self.product_stats
+                    .entry(product_id.clone())
+                    .or_default()
+                    .last_update = now;
+
+                if let Some(done) = done {
+                    completed.push((product_id.clone(), done));
+                }
+            }
+        }
+
+        self.stats
+            .update(self.processed, self.completed, self.candles.len());
+        completed
+    }
+
+    /// Ejects completed candles.
+    fn check_candle(&mut self, product_id: &str, new_candle: Candle) -> Option<Candle> {
+        match self.candles.get_mut(product_id) {
+            Some(candle) if candle.start < new_candle.start => {
+                // Eject complete candle, and replace with new series candle.
+                let (start, open) = (new_candle.start, new_candle.open);
+                let old = std::mem::replace(candle, new_candle);
+                self.observer.on_candle_open(product_id, start, open);
+
+                // Completed already, such as by the finalizer before a reconnect resent it.
+                if self.last_completed(product_id) == Some(old.start) {
+                    debug!(
+                        product_id,
+                        start = old.start,
+                        "skipped repeated completion of a candle."
+                    );
+                    self.stats.record_duplicate_ejection();
+                    return None;
+                }
+
+                // A newer candle than the next in the series means candles were missed.
+                let expected = old.start + self.granularity.seconds();
+                if start > expected {
+                    self.observer.on_gap(product_id, expected, start);
+                }
+                Some(old)
+            }
+            Some(candle) if candle.start == new_candle.start => {
+                // Replace existing.
+                *candle = new_candle;
+                None
+            }
+            Some(candle) => {
+                // Older than the in-progress candle, never let it replace the newer one.
+                let current_start = candle.start;
+                match self.last_completed(product_id) {
+                    Some(last) if new_candle.start <= last => {
+                        self.observer.on_late_candle(product_id, &new_candle);
+                    }
+                    _ => {
+                        self.observer
+                            .on_late_arrival(product_id, current_start, &new_candle);
+                    }
+                }
+                self.record_late(product_id);
+                None
+            }
+            None => {
+                // The previous candle may have been finalized before this one arrived.
+                match self.last_completed(product_id) {
+                    Some(last) => {
+                        if new_candle.start < last {
+                            self.observer.on_late_candle(product_id, &new_candle);
+                            self.record_late(product_id);
+                            return None;
+                        }
+
+                        let expected = last + self.granularity.seconds();
+                        if new_candle.start > expected {
+                            self.observer.on_gap(product_id, expected, new_candle.start);
+                        }
+                        // Already opened before it completed, only resent.
+                        if new_candle.start > last {
+                            self.observer.on_candle_open(
+                                product_id,
+                                new_candle.start,
+                                new_candle.open,
+                            );
+                        }
+                    }
+                    // Nothing has completed for the product, tracking it starts here.
+                    None => {
+                        self.observer.on_first_candle(product_id, &new_candle);
+                        self.observer
+                            .on_candle_open(product_id, new_candle.start, new_candle.open);
+                    }
+                }
+
+                // Insert first candle occurrence.
+                self.candles.insert(product_id.to_string(), new_candle);
+                None
+            }
+        }
+    }
+
+    /// Counts an update that arrived after a newer candle of the product.
+    fn record_late(&mut self, product_id: &str) {
+        self.product_stats
+            .entry(product_id.to_string())
+            .or_default()
+            .late += 1;
+    }
+
+    /// Adds a completed candle to the history of the product, dropping the oldest.
+    fn remember_history(&mut self, product_id: &str, candle: &Candle) {
+        let len = self.history_capacity();
+        if len == 0 {
+            return;
+        }
+
+        let history = self.history.entry(product_id.to_string()).or_default();
+        history.push_back(candle.clone());
+        if history.len() > len {
+            history.pop_front();
+        }
+        history.make_contiguous();
+    }
+
+    /// Completed candles retained for each product, enough for the indicators that
+    /// are calculated from the history. The latest is always kept for the change.
+    fn history_capacity(&self) -> usize {
+        let bollinger = self.bollinger.map_or(0, |bands| bands.period());
+        let extremes = self.extremes.as_ref().map_or(0, |e| e.period());
+        self.history_len.max(bollinger).max(extremes).max(1)
+    }
+
+    /// Checks a completed candle for new extremes before it joins the history.
+    fn check_extremes(&mut self, product_id: &str, candle: &Candle) {
+        let previous = match self.history.get(product_id) {
+            Some(history) => history.as_slices().0,
+            None => &[],
+        };
+        if let Some(extremes) = &mut self.extremes {
+            extremes.check(product_id, candle, previous);
+        }
+    }
+
+    /// Percent change of a completed close from the previous completed close of the
+    /// product, `None` for its first candle.
+    fn change(&self, product_id: &str, candle: &Candle) -> Option<f64> {
+        let previous = self.history(product_id).last()?;
+        if previous.close == 0.0 {
+            return None;
+        }
+        Some((candle.close - previous.close) / previous.close * 100.0)
+    }
+
+    /// Calculates the Bollinger Bands from the history of a product.
+    fn update_bollinger(&self, product_id: &str) -> Option<Option<Bands>> {
+        let bands = self.bollinger?;
+        Some(bands.calculate(self.history(product_id)))
+    }
+
+    /// Start of the newest candle that completed for the product.
+    fn last_completed(&self, product_id: &str) -> Option<u64> {
+        self.recent
+            .get(product_id)
+            .and_then(|starts| starts.iter().max().copied())
+    }
+
+    /// Records how long after its interval ended a live candle completed. A clock
+    /// behind the exchange is counted as no delay.
+    fn observe_latency(&self, candle: &Candle) {
+        let end = (candle.start + self.granularity.seconds()) as f64;
+        let now = self.clock.now().as_secs_f64();
+        self.stats.observe_latency((now - end).max(0.0));
+    }
+
+    /// Completes the in-progress candles whose interval ended at least `grace` seconds
+    /// before `now`, without waiting for the next candle of the product. Later updates
+    /// of those candles are skipped as duplicates. Returns the amount completed.
+    pub fn finalize(&mut self, now: u64, grace: u64) -> usize {
+        let interval = self.granularity.seconds();
+        let due: Vec<String> = self
+            .candles
+            .iter()
+            .filter(|(_, candle)| candle.start + interval + grace <= now)
+            .map(|(product_id, _)| product_id.clone())
+            .collect();
+
+        for product_id in &due {
+            if let Some(candle) = self.candles.remove(product_id) {
+                self.observe_latency(&candle);
+                self.complete(product_id, candle);
+            }
+        }
+        self.write_due_csv();
+
+        self.stats
+            .update(self.processed, self.completed, self.candles.len());
+        due.len()
+    }
+}
+
+impl Default for TaskTracker<StdoutSink> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: CandleSink> MessageCallback for TaskTracker<S> {
+    /// Required to pass TaskTracker to the websocket listener.
+    fn message_callback(&mut self, msg: APIResult<Message>) {
+        for (product_id, candle) in self.eject(msg) {
+            self.observe_latency(&candle);
+            self.complete(&product_id, candle);
+        }
+        // After the candles they replaced have completed.
+        self.send_partials();
+    }
+}
+
+/// Shared handle to a tracker, allows the tracked candles to survive reconnects.
+pub struct TrackerHandle<S: CandleSink = StdoutSink> {
+    inner: Arc<Mutex<TaskTracker<S>>>,
+}
+
+impl<S: CandleSink> Clone for TrackerHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<S: CandleSink + Send + 'static> TrackerHandle<S> {
+    /// Wraps a tracker so it can be shared between listeners.
+    pub fn new(tracker: TaskTracker<S>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(tracker)),
+        }
+    }
+
+    /// Total candle updates processed by the tracker.
+    pub fn processed(&self) -> usize {
+        self.inner.lock().unwrap().processed()
+    }
+
+    /// Total candles ejected as finished by the tracker.
+    pub fn completed(&self) -> usize {
+        self.inner.lock().unwrap().completed()
+    }
+
+    /// Counters that can be read without locking the tracker.
+    pub fn stats(&self) -> Arc<Stats> {
+        self.inner.lock().unwrap().stats()
+    }
+
+    /// Granularity of the candles being tracked.
+    pub fn granularity(&self) -> Granularity {
+        self.inner.lock().unwrap().granularity()
+    }
+
+    /// Current Unix time, in seconds, according to the clock of the tracker.
+    pub fn now(&self) -> u64 {
+        self.inner.lock().unwrap().now()
+    }
+
+    /// Clock the tracker reads the current time from.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.inner.lock().unwrap().clock()
+    }
+
+    /// Copy of the counters for each product.
+    pub fn product_stats(&self) -> HashMap<String, ProductStats> {
+        self.inner.lock().unwrap().product_stats().clone()
+    }
+
+    /// Spawns a task printing a summary of the counters every `interval`.
+    pub fn spawn_summary(&self, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(stats::report(vec![self.stats()], interval))
+    }
+
+    /// Start of the most recent candle for each product.
+    pub fn latest_starts(&self) -> Vec<(String, u64)> {
+        self.inner.lock().unwrap().latest_starts()
+    }
+
+    /// Completes the in-progress candles whose interval ended at least `grace` seconds
+    /// before `now`, returning the amount completed.
+    pub fn finalize(&self, now: u64, grace: u64) -> usize {
+        self.inner.lock().unwrap().finalize(now, grace)
+    }
+
+    /// Spawns a task completing candles once `grace` seconds have passed since their
+    /// interval ended, checked every `every`. Quiet products then complete on time
+    /// instead of when their next candle arrives.
+    pub fn spawn_finalizer(&self, grace: u64, every: Duration) -> JoinHandle<()> {
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(every);
+            loop {
+                ticker.tick().await;
+                tracker.finalize(tracker.now(), grace);
+            }
+        })
+    }
+
+    /// Spawns a task passing the in-progress candle of each product to the sink as a
+    /// snapshot every `every`, whether or not new updates arrived.
+    pub fn spawn_snapshots(&self, every: Duration) -> JoinHandle<()> {
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(every);
+            // First tick completes immediately.
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                tracker.inner.lock().unwrap().snapshot();
+            }
+        })
+    }
+
+    /// Spawns a task checking for stale products every `interval`, products without a
+    /// candle newer than `stale_after` seconds are passed to `sink`.
+    pub fn spawn_watchdog(
+        &self,
+        stale_after: u64,
+        interval: Duration,
+        sink: Box<dyn StaleSink + Send>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(watchdog::watch(self.clone(), stale_after, interval, sink))
+    }
+
+    /// Most recent completed candles of a product, oldest first.
+    pub fn history(&self, product_id: &str) -> Vec<Candle> {
+        self.inner.lock().unwrap().history(product_id).to_vec()
+    }
+
+    /// Passes a single candle update through the tracker, returns whether it was
+    /// processed.
+    pub fn ingest(&self, product_id: &str, candle: Candle) -> bool {
+        self.inner.lock().unwrap().ingest(product_id, candle)
+    }
+
+    /// Replays candles through the completion path, returns the amount replayed.
+    pub fn replay(&self, product_id: &str, candles: Vec<Candle>) -> usize {
+        self.inner.lock().unwrap().replay(product_id, candles)
+    }
+
+    /// Start of the in-progress candle of a product, if it has one.
+    pub fn in_progress_start(&self, product_id: &str) -> Option<u64> {
+        self.inner.lock().unwrap().in_progress_start(product_id)
+    }
+
+    /// Reconciles the in-progress candle of a product with fetched candles, returns
+    /// the amount applied.
+    pub fn refresh(&self, product_id: &str, candles: Vec<Candle>) -> usize {
+        self.inner.lock().unwrap().refresh(product_id, candles)
+    }
+
+    /// Seeds a product with historic candles, returns the amount seeded.
+    pub fn seed(&self, product_id: &str, candles: Vec<Candle>) -> usize {
+        self.inner.lock().unwrap().seed(product_id, candles)
+    }
+
+    /// Drains and records all in-progress candles.
+    pub fn flush(&self) {
+        self.inner.lock().unwrap().flush();
+    }
+
+    /// Writes buffered rows and flushes the sink, keeping the in-progress candles.
+    pub fn flush_buffers(&self) {
+        self.inner.lock().unwrap().flush_buffers();
+    }
+
+    /// Writes the in-progress candles and indicator state to `path`.
+    pub fn save_state(&self, path: &Path) -> Result<(), String> {
+        self.inner.lock().unwrap().save_state(path)
+    }
+
+    /// Restores the state written by `save_state`, returning whether it was restored.
+    pub fn load_state(&self, path: &Path) -> Result<bool, String> {
+        self.inner.lock().unwrap().load_state(path)
+    }
+
+    /// In-progress candles and indicator state of every product as JSON. Only the copy
+    /// is made while locked, it is serialized afterwards.
+    pub fn dump(&self) -> Result<serde_json::Value, String> {
+        let state = self.inner.lock().unwrap().state();
+        serde_json::to_value(&state).map_err(|err| err.to_string())
+    }
+
+    /// Starts the task tracking of candles, returns once the connection is closed.
+    pub async fn start(self, reader: WebSocketReader) {
+        // Start the listener.
+        websocket::listener_with(reader, self).await;
+    }
+}
+
+impl<S: CandleSink> MessageCallback for TrackerHandle<S> {
+    /// Passes messages to the shared tracker.
+    fn message_callback(&mut self, msg: APIResult<Message>) {
+        self.inner.lock().unwrap().message_callback(msg);
+    }
+}
+
+/// Controls how the watcher starts and reconnects after the connection is lost.
+#[derive(Debug, Clone)]
+pub struct WatcherOptions {
+    /// Maximum consecutive failed attempts before giving up, `None` retries forever.
+    pub max_retries: Option<u32>,
+    /// Minutes of historic candles to seed each product with before subscribing.
+    pub warmup_minutes: u64,
+    /// Reconnects if no message, including heartbeats, arrives within this window.
+    pub heartbeat_timeout: Option<Duration>,
+    /// Whether the HEARTBEATS channel is subscribed to. Without heartbeats only candle
+    /// updates prove the connection is alive, so the timeout is raised to at least
+    /// one candle of the granularity.
+    pub heartbeats: bool,
+    /// File the tracker state is saved to on shutdown, before the in-progress candles
+    /// are flushed.
+    pub state_path: Option<PathBuf>,
+    /// Records every message received, before it is processed.
+    pub recorder: Option<Recorder>,
+    /// Randomizes the backoff so many watchers do not reconnect at the same time.
+    pub jitter: Jitter,
+    /// Whether the in-progress candles are refreshed from the REST API before
+    /// reconnecting, since their updates were missed while disconnected.
+    pub refresh_on_reconnect: bool,
+    /// Most products subscribed to by a single subscribe message, so large product
+    /// lists stay within the message size limit. `None` subscribes in one message.
+    pub subscribe_batch: Option<usize>,
+    /// Pause between the subscribe messages of a batched subscription.
+    pub subscribe_delay: Duration,
+    /// Notified as the connections are established, lost, and retried.
+    pub connection_observer: Arc<dyn ConnectionObserver>,
+}
+
+impl Default for WatcherOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            warmup_minutes: 0,
+            heartbeat_timeout: None,
+            heartbeats: true,
+            state_path: None,
+            recorder: None,
+            jitter: Jitter::default(),
+            refresh_on_reconnect: true,
+            subscribe_batch: None,
+            subscribe_delay: DEFAULT_SUBSCRIBE_DELAY,
+            connection_observer: Arc::new(NoopConnectionObserver),
+        }
+    }
+}
+
+/// Passes messages of a single connection to the shared tracker, recording when the
+/// connection last received one.
+struct Connection<S: CandleSink = StdoutSink> {
+    /// Tracker shared by every connection.
+    tracker: TrackerHandle<S>,
+    /// Unix time, in seconds, this connection last received a message.
+    last_message: Arc<AtomicU64>,
+    /// Clock of the tracker, read without locking it.
+    clock: Arc<dyn Clock>,
+    /// Records messages before the tracker processes them.
+    recorder: Option<Recorder>,
+    /// Products currently subscribed to, rejected products are removed.
+    products: Arc<Mutex<Vec<String>>>,
+    /// Products the server refused to subscribe to, with its reason.
+    rejected: Arc<Mutex<Vec<(String, String)>>>,
+    /// Wakes the watcher when a product is rejected.
+    rejection: Arc<Notify>,
+    /// Subscribed products that have not sent a candle since connecting.
+    unacknowledged: Arc<Mutex<HashSet<String>>>,
+}
+
+impl<S: CandleSink> Clone for Connection<S> {
+    fn clone(&self) -> Self {
+        Self {
+            tracker: self.tracker.clone(),
+            last_message: Arc::clone(&self.last_message),
+            clock: Arc::clone(&self.clock),
+            recorder: self.recorder.clone(),
+            products: Arc::clone(&self.products),
+            rejected: Arc::clone(&self.rejected),
+            rejection: Arc::clone(&self.rejection),
+            unacknowledged: Arc::clone(&self.unacknowledged),
+        }
+    }
+}
+
+impl<S: CandleSink> Connection<S> {
+    /// Records the product a subscription failure refers to, if it names one.
+    fn check_rejection(&self, reason: &str) {
+        if !reason.to_lowercase().contains("subscri") {
+            return;
+        }
+
+        let products = self.products.lock().unwrap();
+        match rejected_product(reason, &products) {
+            Some(product_id) => {
+                let product_id = product_id.clone();
+                drop(products);
+                self.rejected
+                    .lock()
+                    .unwrap()
+                    .push((product_id, reason.to_string()));
+                self.rejection.notify_one();
+            }
+            None => warn!("Subscription failed without naming a product: {}", reason),
+        }
+    }
+
+    /// Marks the products of a candle message as acknowledged, logging once every
+    /// subscribed product has sent a candle.
+    fn acknowledge(&self, message: &Message) {
+        let events = match message {
+            Message::Candles(value) => &value.events,
+            _ => return,
+        };
+
+        let mut unacknowledged = self.unacknowledged.lock().unwrap();
+        if unacknowledged.is_empty() {
+            return;
+        }
+        for update in events.iter().flat_map(|event| &event.candles) {
+            if unacknowledged.remove(&update.product_id) && unacknowledged.is_empty() {
+                info!("Every subscribed product has sent a candle.");
+            }
+        }
+    }
+
+    /// Removes the rejected products, returning how many remain subscribed.
+    fn drop_rejected(&self) -> usize {
+        let rejected: Vec<(String, String)> = self.rejected.lock().unwrap().drain(..).collect();
+        let mut products = self.products.lock().unwrap();
+        for (product_id, reason) in rejected {
+            if let Some(index) = products.iter().position(|p| *p == product_id) {
+                products.remove(index);
+                error!(
+                    product_id,
+                    "Product rejected by the server, dropping it: {}", reason
+                );
+            }
+        }
+        products.len()
+    }
+}
+
+impl<S: CandleSink + Send + 'static> MessageCallback for Connection<S> {
+    fn message_callback(&mut self, msg: APIResult<Message>) {
+        match &msg {
+            Ok(message) => {
+                self.last_message
+                    .store(self.clock.unix_now(), Ordering::Relaxed);
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(message);
+                }
+                self.acknowledge(message);
+            }
+            Err(err) => self.check_rejection(&err.to_string()),
+        }
+        self.tracker.message_callback(msg);
+    }
+}
+
+/// Finds the subscribed product an error refers to. The longest match is taken so
+/// `BTC-USDC` is not mistaken for `BTC-USD`.
+fn rejected_product<'a>(reason: &str, products: &'a [String]) -> Option<&'a String> {
+    products
+        .iter()
+        .filter(|product_id| reason.contains(product_id.as_str()))
+        .max_by_key(|product_id| product_id.len())
+}
+
+/// Connects and subscribes to candles, returning the running listener.
+async fn connect<S: CandleSink + Send + 'static>(
+    client: &mut websocket::Client,
+    products: &Vec<String>,
+    connection: Connection<S>,
+    options: &WatcherOptions,
+) -> Result<JoinHandle<()>, String> {
+    // Each product acknowledges the subscription with a candle, tracked per connection.
+    *connection.unacknowledged.lock().unwrap() = products.iter().cloned().collect();
+    let unacknowledged = Arc::clone(&connection.unacknowledged);
+
+    // Connect and spawn a task.
+    let reader = match client.connect().await {
+        Ok(reader) => reader,
+        Err(err) => return Err(format!("unable to connect: {}", err)),
+    };
+    let listener = tokio::spawn(websocket::listener_with(reader, connection));
+
+    // Keep the connection open and subscribe to candles. The CANDLES channel takes no
+    // granularity, settings are validated to match the five minutes it provides.
+    if options.heartbeats {
+        if let Err(err) = client.sub(Channel::HEARTBEATS, &vec![]).await {
+            listener.abort();
+            return Err(format!("unable to subscribe to heartbeats: {}", err));
+        }
+    }
+
+    let size = options.subscribe_batch.unwrap_or(products.len()).max(1);
+    let batches = products.len().div_ceil(size);
+    for (index, batch) in products.chunks(size).enumerate() {
+        if index > 0 {
+            sleep(options.subscribe_delay).await;
+        }
+        if let Err(err) = client.sub(Channel::CANDLES, &batch.to_vec()).await {
+            listener.abort();
+            error!(
+                "Unable to subscribe to batch {}/{} of {} products: {}",
+                index + 1,
+                batches,
+                batch.len(),
+                err
+            );
+            return Err(format!("unable to subscribe to candles: {}", err));
+        }
+        if batches > 1 {
+            let pending = unacknowledged.lock().unwrap().len();
+            info!(
+                "Subscribed to batch {}/{} of {} products, {} of {} products sent a candle so far.",
+                index + 1,
+                batches,
+                batch.len(),
+                products.len() - pending,
+                products.len()
+            );
+        }
+    }
+
+    Ok(listener)
+}
+
+/// Completes once no message has been received within `window`, never if `None`.
+async fn heartbeat_lost(last_message: &AtomicU64, window: Option<Duration>, clock: &dyn Clock) {
+    let window = match window {
+        Some(window) => window.as_secs(),
+        None => return future::pending().await,
+    };
+
+    let mut ticker = interval(HEARTBEAT_CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        if clock
+            .unix_now()
+            .saturating_sub(last_message.load(Ordering::Relaxed))
+            > window
+        {
+            return;
+        }
+    }
+}
+
+/// Seeds the tracker with recent history so aggregators start out valid, returns
+/// `false` if Ctrl-C was received first.
+async fn warmup<S: CandleSink + Send + 'static>(
+    products: &[String],
+    backfiller: &Backfiller<S>,
+    options: &WatcherOptions,
+) -> bool {
+    if options.warmup_minutes == 0 {
+        return true;
+    }
+
+    tokio::select! {
+        _ = backfiller.warmup(products, options.warmup_minutes) => true,
+        _ = signal::ctrl_c() => false,
+    }
+}
+
+/// Keeps a single connection subscribed to `products` until Ctrl-C is received,
+/// reconnecting with exponential backoff whenever it is lost.
+async fn watch_connection<S: CandleSink + Send + 'static>(
+    client: &mut websocket::Client,
+    products: &Vec<String>,
+    tracker: TrackerHandle<S>,
+    backfiller: &Backfiller<S>,
+    options: &WatcherOptions,
+) -> Result<(), WatcherError> {
+    let shutdown = signal::ctrl_c();
+    tokio::pin!(shutdown);
+
+    // Quiet products may go a whole candle without an update when nothing else keeps
+    // the connection busy.
+    let liveness = match options.heartbeat_timeout {
+        Some(window) if !options.heartbeats => {
+            Some(window.max(Duration::from_secs(tracker.granularity().seconds())))
+        }
+        window => window,
+    };
+
+    let stats = tracker.stats();
+    let connection = Connection {
+        clock: tracker.clock(),
+        tracker,
+        last_message: Arc::new(AtomicU64::new(0)),
+        recorder: options.recorder.clone(),
+        products: Arc::new(Mutex::new(products.clone())),
+        rejected: Arc::new(Mutex::new(vec![])),
+        rejection: Arc::new(Notify::new()),
+        unacknowledged: Arc::new(Mutex::new(HashSet::new())),
+    };
+    let observer = &options.connection_observer;
+    let mut attempts: u32 = 0;
+    // Reconnection attempts since the connection was last established.
+    let mut reconnecting: u32 = 0;
+    let mut backoff = INITIAL_BACKOFF;
+    // Whether a connection was lost, the in-progress candles missed its updates.
+    let mut lost = false;
+
+    loop {
+        let products = connection.products.lock().unwrap().clone();
+        if lost && options.refresh_on_reconnect {
+            tokio::select! {
+                _ = backfiller.refresh(&products) => lost = false,
+                _ = &mut shutdown => break,
+            }
+        }
+
+        let connected = tokio::select! {
+            result = connect(client, &products, connection.clone(), options) => result,
+            _ = &mut shutdown => break,
+        };
+
+        match connected {
+            Ok(mut listener) => {
+                stats.connection_opened();
+                observer.on_connected();
+                reconnecting = 0;
+                connection
+                    .last_message
+                    .store(connection.clock.unix_now(), Ordering::Relaxed);
+                let mut timed_out = false;
+                let result = tokio::select! {
+                    result = &mut listener => Some(result),
+                    _ = heartbeat_lost(&connection.last_message, liveness, connection.clock.as_ref()) => {
+                        // Half-open connections never close on their own, tear it down.
+                        warn!("No messages received within the heartbeat timeout.");
+                        listener.abort();
+                        let _ = timeout(SHUTDOWN_TIMEOUT, &mut listener).await;
+                        timed_out = true;
+                        Some(Ok(()))
+                    }
+                    _ = connection.rejection.notified() => {
+                        // The subscription was refused, resubscribe without the product.
+                        listener.abort();
+                        let _ = timeout(SHUTDOWN_TIMEOUT, &mut listener).await;
+                        stats.connection_closed();
+                        observer.on_disconnected("product rejected by the server");
+                        if connection.drop_rejected() == 0 {
+                            return Err(WatcherError::WebSocket(
+                                "every product was rejected by the server".to_string(),
+                            ));
+                        }
+                        continue;
+                    }
+                    _ = &mut shutdown => None,
+                };
+                stats.connection_closed();
+
+                match result {
+                    Some(Ok(_)) => {
+                        // Connection was established before being lost, start the backoff over.
+                        warn!("WebSocket connection closed.");
+                        observer.on_disconnected(match timed_out {
+                            true => "no messages received within the heartbeat timeout",
+                            false => "connection closed",
+                        });
+                        attempts = 0;
+                        backoff = INITIAL_BACKOFF;
+                        lost = true;
+                    }
+                    Some(Err(err)) => {
+                        error!("WebSocket listener stopped: {}", err);
+                        observer.on_disconnected(&format!("listener stopped: {}", err));
+                        attempts += 1;
+                        lost = true;
+                    }
+                    None => {
+                        // Stop the listener, bounded in case the connection is hung.
+                        listener.abort();
+                        let _ = timeout(SHUTDOWN_TIMEOUT, listener).await;
+                        observer.on_disconnected("shutting down");
+                        break;
+                    }
+                }
+            }
+            Err(err) => {
+                error!("WebSocket error: {}", err);
+                observer.on_disconnected(&err);
+                attempts += 1;
+            }
+        }
+
+        if let Some(max) = options.max_retries {
+            if attempts > max {
+                return Err(WatcherError::WebSocket(format!(
+                    "exceeded {} reconnection attempts",
+                    max
+                )));
+            }
+        }
+
+        let delay = options.jitter.delay(backoff);
+        info!("Reconnecting in {:.1}s.", delay.as_secs_f64());
+        stats.record_reconnect();
+        reconnecting += 1;
+        observer.on_reconnecting(reconnecting, delay);
+        tokio::select! {
+            _ = sleep(delay) => (),
+            _ = &mut shutdown => break,
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    Ok(())
+}
+
+/// Saves the tracker state if configured, otherwise records the in-progress candles.
+fn shutdown<S: CandleSink + Send + 'static>(tracker: &TrackerHandle<S>, options: &WatcherOptions) {
+    let saved = match &options.state_path {
+        Some(path) => match tracker.save_state(path) {
+            Ok(()) => {
+                info!("Saved tracker state to '{}'.", path.display());
+                true
+            }
+            Err(err) => {
+                error!(
+                    "Unable to save tracker state to '{}': {}",
+                    path.display(),
+                    err
+                );
+                false
+            }
+        },
+        None => false,
+    };
+
+    // Saved candles complete after the restart, recording them now would write them
+    // twice, once incomplete.
+    if saved {
+        info!("Shutting down, in-progress candles resume from the saved state.");
+        tracker.flush_buffers();
+    } else {
+        info!("Shutting down, flushing in-progress candles.");
+        tracker.flush();
+    }
+}
+
+/// Watches candles for a set of products, producing candles once they are complete.
+/// Optionally warms up with recent history before subscribing.
+/// Reconnects with exponential backoff whenever the connection is lost and flushes
+/// the in-progress candles once Ctrl-C is received.
+pub async fn candle_watcher<S: CandleSink + Send + 'static>(
+    client: &mut websocket::Client,
+    products: &Vec<String>,
+    tracker: TrackerHandle<S>,
+    backfiller: &Backfiller<S>,
+    options: &WatcherOptions,
+) -> Result<(), WatcherError> {
+    if !warmup(products, backfiller, options).await {
+        return Ok(());
+    }
+
+    // In-progress candles are recorded even when the connection gave up.
+    let result = watch_connection(client, products, tracker.clone(), backfiller, options).await;
+    shutdown(&tracker, options);
+    result
+}
+
+/// Splits the products into shards of at most `size` products, in order.
+pub fn shard(products: &[String], size: usize) -> Vec<Vec<String>> {
+    products
+        .chunks(size.max(1))
+        .map(|shard| shard.to_vec())
+        .collect()
+}
+
+/// Watches candles like `candle_watcher`, but over one connection for each shard of
+/// at most `shard_size` products since Coinbase limits the products of a connection.
+/// Each connection is created by `new_client` and reconnects with its own backoff,
+/// all of them feed the same tracker. Stops with the first shard that gives up, after
+/// shutting the tracker down.
+pub async fn sharded_watcher<S, F>(
+    new_client: F,
+    products: &[String],
+    shard_size: usize,
+    tracker: TrackerHandle<S>,
+    backfiller: &Backfiller<S>,
+    options: &WatcherOptions,
+) -> Result<(), WatcherError>
+where
+    S: CandleSink + Send + 'static,
+    F: Fn() -> websocket::Client,
+{
+    if !warmup(products, backfiller, options).await {
+        return Ok(());
+    }
+
+    let shards = shard(products, shard_size);
+    info!(
+        "Watching {} products over {} connections.",
+        products.len(),
+        shards.len()
+    );
+
+    let watchers = shards.into_iter().map(|products| {
+        let mut client = new_client();
+        let tracker = tracker.clone();
+        async move { watch_connection(&mut client, &products, tracker, backfiller, options).await }
+    });
+    // Every shard feeds the same tracker, its candles are recorded whichever gave up.
+    let result = try_join_all(watchers).await;
+    shutdown(&tracker, options);
+    result.map(|_| ())
+}
+
+/// Splits the products into at most `count` groups of similar size, each watched by
+/// its own tracker and connection.
+pub fn partition(products: &[String], count: usize) -> Vec<Vec<String>> {
+    let count = count.clamp(1, products.len().max(1));
+    let mut groups = vec![vec![]; count];
+    for (i, product) in products.iter().enumerate() {
+        groups[i % count].push(product.clone());
+    }
+    groups
+}
+
+/// Creates the sink candles are recorded to based on the settings.
+pub async fn build_sink(
+    settings: &WatcherSettings,
+) -> Result<Box<dyn CandleSink + Send>, WatcherError> {
+    Ok(build_sinks(settings, 1).await?.remove(0))
+}
+
+/// Creates `count` independent sinks based on the settings, one for each partition.
+/// The WebSocket server is shared, every sink broadcasts to the same clients.
+pub async fn build_sinks(
+    settings: &WatcherSettings,
+    count: usize,
+) -> Result<Vec<Box<dyn CandleSink + Send>>, WatcherError> {
+    let broadcast = match settings.serve_ws {
+        Some(addr) => match server::serve_ws(addr).await {
+            Ok(broadcast) => Some(broadcast),
+            Err(err) => {
+                return Err(WatcherError::Sink(format!(
+                    "unable to serve WebSocket on {}: {}",
+                    addr, err
+                )))
+            }
+        },
+        None => None,
+    };
+
+    let mut sinks = vec![];
+    for _ in 0..count.max(1) {
+        sinks.push(compose_sink(settings, broadcast.clone())?);
+    }
+    Ok(sinks)
+}
+
+/// Combines the configured sinks, broadcasting to `broadcast` if set. Each sink runs
+/// on its own thread so a slow or failing sink does not hold up the others.
+fn compose_sink(
+    settings: &WatcherSettings,
+    broadcast: Option<BroadcastSink>,
+) -> Result<Box<dyn CandleSink + Send>, WatcherError> {
+    let output: Box<dyn CandleSink + Send> = match settings.output_format {
+        OutputFormat::Text => {
+            let mut stdout = match &settings.log_template {
+                Some(template) => {
+                    StdoutSink::with_template(template.parse().map_err(WatcherError::Config)?)
+                }
+                None => StdoutSink::new(),
+            };
+            stdout.set_color(settings.color && io::stdout().is_terminal());
+            stdout.set_calendar(Calendar::new(settings.timezone));
+            Box::new(stdout)
+        }
+        OutputFormat::Json => Box::new(JsonSink::with_fields(settings.output_fields.clone())),
+    };
+    let mut sinks = FanOutSink::builder().sink("output", output);
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &settings.sqlite_path {
+        let sqlite = match sqlite::SqliteSink::open(path) {
+            Ok(sqlite) => sqlite,
+            Err(err) => {
+                return Err(WatcherError::Sink(format!(
+                    "unable to open SQLite database: {}",
+                    err
+                )))
+            }
+        };
+        info!("Storing candles in '{}'.", path.display());
+        sinks = sinks.sink("sqlite", Box::new(sqlite));
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    if settings.sqlite_path.is_some() {
+        warn!("SQLite path is set but the 'sqlite' feature is not enabled.");
+    }
+
+    #[cfg(feature = "parquet")]
+    if let Some(dir) = &settings.parquet_dir {
+        let mut parquet = parquet_sink::ParquetSink::new(dir.clone());
+        parquet.set_policy(FilePolicy {
+            sync: settings.file_sync,
+            interval: settings.file_flush_interval,
+        });
+        parquet.set_calendar(Calendar::new(settings.timezone));
+        info!("Exporting candles to Parquet files in '{}'.", dir.display());
+        sinks = sinks.sink("parquet", Box::new(parquet));
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    if settings.parquet_dir.is_some() {
+        warn!("Parquet directory is set but the 'parquet' feature is not enabled.");
+    }
+
+    #[cfg(feature = "influx")]
+    if let Some(url) = &settings.influx_url {
+        let target = influx_sink::InfluxTarget {
+            url: url.clone(),
+            org: settings.influx_org.clone(),
+            bucket: settings.influx_bucket.clone(),
+            token: settings.influx_token.clone(),
+        };
+        info!(
+            "Writing candles to InfluxDB bucket '{}' at '{}'.",
+            settings.influx_bucket, url
+        );
+        sinks = sinks.sink("influx", Box::new(influx_sink::InfluxSink::new(target)));
+    }
+
+    #[cfg(not(feature = "influx"))]
+    if settings.influx_url.is_some() {
+        warn!("InfluxDB URL is set but the 'influx' feature is not enabled.");
+    }
+
+    #[cfg(feature = "redis")]
+    if let Some(url) = &settings.redis_url {
+        let redis = match redis_sink::RedisSink::new(url) {
+            Ok(redis) => redis,
+            Err(err) => return Err(WatcherError::Sink(format!("invalid Redis URL: {}", err))),
+        };
+        info!("Publishing candles to Redis at '{}'.", url);
+        sinks = sinks.sink("redis", Box::new(redis));
+    }
+
+    #[cfg(not(feature = "redis"))]
+    if settings.redis_url.is_some() {
+        warn!("Redis URL is set but the 'redis' feature is not enabled.");
+    }
+
+    #[cfg(feature = "kafka")]
+    if let Some(brokers) = &settings.kafka_brokers {
+        let kafka = match kafka_sink::KafkaSink::new(brokers, settings.kafka_topic.clone()) {
+            Ok(kafka) => kafka,
+            Err(err) => {
+                return Err(WatcherError::Sink(format!(
+                    "unable to create Kafka producer: {}",
+                    err
+                )))
+            }
+        };
+        info!(
+            "Producing candles to Kafka topic '{}' on '{}'.",
+            settings.kafka_topic, brokers
+        );
+        sinks = sinks.sink("kafka", Box::new(kafka));
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    if settings.kafka_brokers.is_some() {
+        warn!("Kafka brokers are set but the 'kafka' feature is not enabled.");
+    }
+
+    #[cfg(feature = "nats")]
+    if let Some(url) = &settings.nats_url {
+        let nats = nats_sink::NatsSink::new(
+            url.clone(),
+            settings.nats_subject_prefix.clone(),
+            settings.nats_stream.clone(),
+        );
+        info!(
+            "Publishing candles to NATS subjects '{}.*' on '{}'.",
+            settings.nats_subject_prefix, url
+        );
+        sinks = sinks.sink("nats", Box::new(nats));
+    }
+
+    #[cfg(not(feature = "nats"))]
+    if settings.nats_url.is_some() {
+        warn!("NATS URL is set but the 'nats' feature is not enabled.");
+    }
+
+    #[cfg(feature = "mqtt")]
+    if let Some(host) = &settings.mqtt_host {
+        let credentials = match (&settings.mqtt_username, &settings.mqtt_password) {
+            (Some(username), password) => {
+                Some((username.clone(), password.clone().unwrap_or_default()))
+            }
+            (None, _) => None,
+        };
+        let mqtt = mqtt_sink::MqttSink::new(mqtt_sink::MqttTarget {
+            host: host.clone(),
+            port: settings.mqtt_port,
+            client_id: settings.mqtt_client_id.clone(),
+            credentials,
+            topic_prefix: settings.mqtt_topic_prefix.clone(),
+            qos: settings.mqtt_qos,
+        });
+        info!(
+            "Publishing candles to MQTT topics '{}/<product_id>' on '{}:{}'.",
+            settings.mqtt_topic_prefix, host, settings.mqtt_port
+        );
+        sinks = sinks.sink("mqtt", Box::new(mqtt));
+    }
+
+    #[cfg(not(feature = "mqtt"))]
+    if settings.mqtt_host.is_some() {
+        warn!("MQTT host is set but the 'mqtt' feature is not enabled.");
+    }
+
+    if let Some(url) = &settings.webhook_url {
+        info!("Sending candles to webhook '{}'.", url);
+        sinks = sinks.sink("webhook", Box::new(WebhookSink::new(url.clone())));
+    }
+
+    if let Some(broadcast) = broadcast {
+        sinks = sinks.sink("websocket", Box::new(broadcast));
+    }
+
+    Ok(Box::new(sinks.build()))
+}
+
+/// Products grouped by the quote currency of their market, such as `USD` or `EUR`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Markets {
+    /// Products of each quote currency in the order they were added.
+    markets: BTreeMap<String, Vec<String>>,
+    /// Precision of the products, from their increments in the Product API.
+    precisions: HashMap<String, Precision>,
+}
+
+impl Markets {
+    /// Groups products by the part of their ID after the last `-`, for products that
+    /// were not obtained from the Product API.
+    pub fn from_products(products: &[String]) -> Self {
+        let mut markets = Self::default();
+        for product_id in products {
+            markets.insert(quote_of(product_id), product_id.clone());
+        }
+        markets
+    }
+
+    /// Adds a product to the market of `quote`.
+    pub fn insert(&mut self, quote: &str, product_id: String) {
+        self.markets
+            .entry(quote.to_uppercase())
+            .or_default()
+            .push(product_id);
+    }
+
+    /// Sets the precision of a product, such as from its increments.
+    pub fn set_precision(&mut self, product_id: &str, precision: Precision) {
+        self.precisions.insert(product_id.to_string(), precision);
+    }
+
+    /// Precision of each product whose increments are known, as used by [`Rounding`].
+    pub fn precisions(&self) -> HashMap<String, Precision> {
+        self.precisions.clone()
+    }
+
+    /// Quote currencies with their products, ordered by quote currency.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<String>)> {
+        self.markets.iter()
+    }
+
+    /// Every product, grouped by quote currency.
+    pub fn products(&self) -> Vec<String> {
+        self.markets.values().flatten().cloned().collect()
+    }
+
+    /// Quote currency of each product, as used by [`TaskTracker::set_quotes`].
+    pub fn quotes(&self) -> HashMap<String, String> {
+        self.markets
+            .iter()
+            .flat_map(|(quote, products)| {
+                products
+                    .iter()
+                    .map(move |product_id| (product_id.clone(), quote.clone()))
+            })
+            .collect()
+    }
+
+    /// Total amount of products across the markets.
+    pub fn len(&self) -> usize {
+        self.markets.values().map(Vec::len).sum()
+    }
+
+    /// Whether there are no products.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Status a product is filtered by, `disabled` when trading is disabled regardless of
+/// the status reported.
+fn product_status(product: &Product) -> String {
+    if product.is_disabled || product.trading_disabled {
+        "disabled".to_string()
+    } else {
+        product.status.to_lowercase()
+    }
+}
+
+/// Quote currency within a product ID, the part after the last `-`.
+fn quote_of(product_id: &str) -> &str {
+    product_id.rsplit('-').next().unwrap_or(product_id)
+}
+
+/// Enforces `max_tracked_products`, truncating to the first products or failing as
+/// configured. Applies to discovered products and those given directly alike.
+pub fn cap_tracked_products<T>(
+    products: &mut Vec<T>,
+    settings: &WatcherSettings,
+) -> Result<(), WatcherError> {
+    let max = match settings.max_tracked_products {
+        Some(max) if products.len() > max => max,
+        _ => return Ok(()),
+    };
+
+    match settings.on_max_tracked_products {
+        TooManyProducts::Truncate => {
+            warn!(
+                "{} products exceed the cap of {} tracked products, tracking the first {}.",
+                products.len(),
+                max,
+                max
+            );
+            products.truncate(max);
+            Ok(())
+        }
+        TooManyProducts::Error => Err(WatcherError::Config(format!(
+            "{} products exceed the cap of {} tracked products",
+            products.len(),
+            max
+        ))),
+    }
+}
+
+/// Obtain product names of candles to be obtained, grouped by their quote currency.
+/// Requests draw from the shared `limiter`.
+pub async fn get_products(
+    client: &RestClient,
+    limiter: &RateLimiter,
+    settings: &WatcherSettings,
+) -> Result<Markets, WatcherError> {
+    // Quote currencies are compared case-insensitively.
+    let quotes: Vec<String> = settings
+        .quote_currencies
+        .iter()
+        .map(|q| q.to_uppercase())
+        .collect();
+
+    if quotes.is_empty() {
+        info!("Getting products for all quote currencies.");
+    } else {
+        info!("Getting '*-{}' products.", quotes.join("', '*-"));
+    }
+
+    // Statuses are compared case-insensitively, empty allows any status.
+    let statuses: Vec<String> = settings
+        .product_statuses
+        .iter()
+        .map(|s| s.to_lowercase())
+        .collect();
+
+    // Number of products that matched each quote currency.
+    let mut matched: HashMap<String, usize> = HashMap::new();
+    // Number of matching products dropped for each status.
+    let mut dropped: HashMap<String, usize> = HashMap::new();
+    // Holds all of the product names with their quote currency.
+    let mut product_names: Vec<(String, String)> = vec![];
+    // Precision of the matched products from their increments.
+    let mut precisions: HashMap<String, Precision> = HashMap::new();
+    let mut fetched: usize = 0;
+    let mut pages: usize = 0;
+
+    // Pull pages of products from the Product API until a partial page is returned.
+    loop {
+        let query = ListProductsQuery {
+            limit: Some(settings.product_page_size),
+            offset: Some(fetched as u32),
+            ..Default::default()
+        };
+
+        let products =
+            fetch_products(client, limiter, &query, settings.product_fetch_attempts).await?;
+        pages += 1;
+        fetched += products.len();
+
+        // Filter products to only those with a configured quote currency.
+        for product in products.iter() {
+            let quote = product.quote_currency_id.to_uppercase();
+            if !quotes.is_empty() && !quotes.contains(&quote) {
+                continue;
+            }
+
+            // Products that cannot trade never produce candles.
+            let status = product_status(product);
+            if !statuses.is_empty() && !statuses.contains(&status) {
+                *dropped.entry(status).or_insert(0) += 1;
+                continue;
+            }
+
+            *matched.entry(quote.clone()).or_insert(0) += 1;
+            if let Some(precision) = Precision::from_increments(
+                &product.quote_increment.to_string(),
+                &product.base_increment.to_string(),
+            ) {
+                precisions.insert(product.product_id.clone(), precision);
+            }
+            product_names.push((product.product_id.clone(), quote));
+        }
+
+        if products.len() < settings.product_page_size as usize {
+            break;
+        }
+    }
+    info!("Fetched {} products over {} pages.", fetched, pages);
+
+    let mut counts: Vec<(String, usize)> = matched.into_iter().collect();
+    counts.sort();
+    for (quote, count) in counts {
+        info!("Matched {} '*-{}' products.", count, quote);
+    }
+
+    let mut dropped: Vec<(String, usize)> = dropped.into_iter().collect();
+    dropped.sort();
+    for (status, count) in dropped {
+        info!("Dropped {} '{}' products by status.", count, status);
+    }
+
+    // Apply the allow-list followed by the deny-list, IDs are matched exactly.
+    if let Some(allow) = &settings.products_allow {
+        product_names.retain(|(p, _)| allow.contains(p));
+    }
+    if let Some(deny) = &settings.products_deny {
+        product_names.retain(|(p, _)| !deny.contains(p));
+    }
+
+    if let Some(max) = settings.max_products {
+        if product_names.len() > max {
+            warn!(
+                "Limiting {} products to the first {}.",
+                product_names.len(),
+                max
+            );
+            product_names.truncate(max);
+        }
+    }
+    cap_tracked_products(&mut product_names, settings)?;
+
+    let mut markets = Markets::default();
+    for (product_id, quote) in product_names {
+        if let Some(precision) = precisions.get(&product_id) {
+            markets.set_precision(&product_id, *precision);
+        }
+        markets.insert(&quote, product_id);
+    }
+    for (quote, products) in markets.iter() {
+        info!("Resolved '*-{}' products: {}", quote, products.join(", "));
+    }
+    Ok(markets)
+}
+
+/// Obtains a page of products, retrying transient failures with exponential backoff.
+/// Authentication failures are returned immediately since retrying cannot fix them.
+/// Requests rejected for the rate limit are retried without using up an attempt.
+async fn fetch_products(
+    client: &RestClient,
+    limiter: &RateLimiter,
+    query: &ListProductsQuery,
+    attempts: u32,
+) -> Result<Vec<Product>, WatcherError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt: u32 = 1;
+    let mut throttled: u32 = 1;
+
+    loop {
+        limiter.acquire().await;
+        let err = match client.product.get_bulk(query).await {
+            Ok(products) => return Ok(products),
+            Err(err) => err.to_string(),
+        };
+
+        if limiter.throttled(&err, throttled) {
+            throttled += 1;
+            continue;
+        }
+
+        if is_auth_error(&err) {
+            return Err(WatcherError::Auth(format!(
+                "check the API key and secret: {}",
+                err
+            )));
+        }
+        if attempt >= attempts {
+            return Err(WatcherError::Rest(format!(
+                "unable to get products after {} attempts: {}",
+                attempt, err
+            )));
+        }
+
+        warn!(
+            "Unable to get products, attempt {}/{}, retrying in {}s: {}",
+            attempt,
+            attempts,
+            backoff.as_secs(),
+            err
+        );
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+/// Product requested to confirm the credentials are accepted.
+const VALIDATION_PRODUCT: &str = "BTC-USD";
+
+/// Makes a single authenticated request, failing with a description of whether the
+/// credentials were rejected or the API could not be reached.
+pub async fn validate_credentials(
+    client: &RestClient,
+    limiter: &RateLimiter,
+) -> Result<(), WatcherError> {
+    let mut throttled: u32 = 1;
+    let err = loop {
+        limiter.acquire().await;
+        let err = match client.product.get(VALIDATION_PRODUCT).await {
+            Ok(_) => return Ok(()),
+            Err(err) => err.to_string(),
+        };
+
+        if !limiter.throttled(&err, throttled) {
+            break err;
+        }
+        throttled += 1;
+    };
+
+    if is_auth_error(&err) {
+        Err(WatcherError::Auth(format!(
+            "invalid credentials, check the API key and secret in the configuration: {}",
+            err
+        )))
+    } else if is_network_error(&err) {
+        Err(WatcherError::Rest(format!(
+            "network unreachable, check connectivity to Coinbase: {}",
+            err
+        )))
+    } else {
+        Err(WatcherError::Rest(format!(
+            "unable to validate credentials: {}",
+            err
+        )))
+    }
+}
+
+/// Whether an API error was caused by the API being unreachable.
+fn is_network_error(err: &str) -> bool {
+    let err = err.to_lowercase();
+    ["connect", "dns", "timed out", "timeout", "unreachable"]
+        .iter()
+        .any(|cause| err.contains(cause))
+}
+
+/// Whether an API error was caused by rejected credentials.
+fn is_auth_error(err: &str) -> bool {
+    let err = err.to_lowercase();
+    ["401", "403", "unauthorized", "forbidden", "authentication"]
+        .iter()
+        .any(|status| err.contains(status))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Candle that opens and closes at `close` with a volume of 1.
+    pub(crate) fn candle(start: u64, close: f64) -> Candle {
+        Candle {
+            start,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    /// Candles message as sent by the WebSocket, holding an update for each candle.
+    pub(crate) fn candles_message(product_id: &str, candles: &[Candle]) -> Message {
+        let updates: Vec<(&str, Candle)> = candles
+            .iter()
+            .map(|candle| (product_id, candle.clone()))
+            .collect();
+        updates_message(&updates)
+    }
+
+    /// Candles message as sent by the WebSocket, holding the updates of any products.
+    pub(crate) fn updates_message(updates: &[(&str, Candle)]) -> Message {
+        let updates: Vec<serde_json::Value> = updates
+            .iter()
+            .map(|(product_id, candle)| {
+                json!({
+                    "product_id": product_id,
+                    "start": candle.start.to_string(),
+                    "open": candle.open.to_string(),
+                    "high": candle.high.to_string(),
+                    "low": candle.low.to_string(),
+                    "close": candle.close.to_string(),
+                    "volume": candle.volume.to_string(),
+                })
+            })
+            .collect();
+
+        serde_json::from_value(json!({
+            "channel": "candles",
+            "client_id": "",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "sequence_num": 0,
+            "events": [{ "type": "update", "candles": updates }],
+        }))
+        .expect("candles message")
+    }
+
+    /// Keeps every recorded candle along with its details.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct RecordingSink {
+        pub(crate) candles: Vec<(String, Candle, CandleInfo)>,
+    }
+
+    impl RecordingSink {
+        /// Starts of the completed candles of the series received from the WebSocket.
+        pub(crate) fn completed(&self) -> Vec<u64> {
+            self.candles
+                .iter()
+                .filter(|(_, _, info)| info.complete && info.is_native())
+                .map(|(_, candle, _)| candle.start)
+                .collect()
+        }
+    }
+
+    impl CandleSink for RecordingSink {
+        fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+            self.candles
+                .push((product_id.to_string(), candle.clone(), info.clone()));
+        }
+    }
+
+    #[test]
+    fn skips_repeated_ejection_of_a_completed_candle() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.set_dedupe_window(0);
+        tracker.message_callback(Ok(candles_message("BTC-USD", &[candle(0, 10.0)])));
+        assert_eq!(tracker.finalize(300, 0), 1);
+
+        // Resent after a reconnect and then replaced by the next candle, it completed already.
+        tracker.message_callback(Ok(candles_message(
+            "BTC-USD",
+            &[candle(0, 10.0), candle(300, 11.0)],
+        )));
+        assert_eq!(tracker.sink.completed(), vec![0]);
+        assert_eq!(tracker.stats().duplicate_ejections(), 1);
+        assert_eq!(tracker.completed(), 1);
+    }
+
+    #[test]
+    fn counts_handled_updates_and_completed_candles() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.message_callback(Ok(updates_message(&[
+            ("BTC-USD", candle(0, 10.0)),
+            ("ETH-USD", candle(0, 20.0)),
+            ("BTC-USD", candle(0, 10.5)),
+            ("BTC-USD", candle(300, 11.0)),
+            ("ETH-USD", candle(300, 21.0)),
+            // Rejected before it reaches the series, not processed.
+            ("BTC-USD", candle(600, 0.0)),
+        ])));
+
+        assert_eq!(tracker.processed(), 5);
+        assert_eq!(tracker.completed(), 2);
+        assert_eq!(tracker.stats().processed(), 5);
+        assert_eq!(tracker.stats().completed(), 2);
+        assert_eq!(tracker.stats().rejected(), 1);
+        assert_eq!(tracker.sink.completed(), vec![0, 0]);
+    }
+
+    #[test]
+    fn skips_resent_updates_without_counting_them() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        assert!(tracker.ingest("BTC-USD", candle(0, 10.0)));
+        assert!(tracker.ingest("BTC-USD", candle(300, 11.0)));
+        assert_eq!((tracker.processed(), tracker.completed()), (2, 1));
+
+        // Resent after a reconnect, both alone and within a message.
+        assert!(!tracker.ingest("BTC-USD", candle(0, 10.0)));
+        tracker.message_callback(Ok(candles_message("BTC-USD", &[candle(0, 10.0)])));
+        assert_eq!((tracker.processed(), tracker.completed()), (2, 1));
+        assert_eq!(tracker.stats().processed(), 2);
+        assert_eq!(tracker.sink.completed(), vec![0]);
+    }
+
+    #[test]
+    fn skips_backfilled_candles_that_already_completed() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.ingest("BTC-USD", candle(900, 13.0));
+        assert_eq!(
+            tracker.replay("BTC-USD", vec![candle(300, 11.0), candle(600, 12.0)]),
+            2
+        );
+        assert_eq!((tracker.processed(), tracker.completed()), (3, 2));
+
+        // Backfilled again, and resent live.
+        assert_eq!(
+            tracker.replay("BTC-USD", vec![candle(300, 11.0), candle(600, 12.0)]),
+            0
+        );
+        assert!(!tracker.ingest("BTC-USD", candle(600, 12.0)));
+        assert_eq!((tracker.processed(), tracker.completed()), (3, 2));
+        assert_eq!(tracker.sink.completed(), vec![300, 600]);
+    }
+
+    #[test]
+    fn shards_products_in_order() {
+        let products: Vec<String> = (0..300).map(|i| format!("P{}-USD", i)).collect();
+
+        let shards = shard(&products, 100);
+        assert_eq!(shards.len(), 3);
+        assert!(shards.iter().all(|shard| shard.len() == 100));
+        assert_eq!(shards.concat(), products);
+
+        // The last shard holds the remainder.
+        let shards = shard(&products, 128);
+        let sizes: Vec<usize> = shards.iter().map(|shard| shard.len()).collect();
+        assert_eq!(sizes, vec![128, 128, 44]);
+
+        // A size of 0 is treated as 1 rather than panicking.
+        assert_eq!(shard(&products[..3], 0).len(), 3);
+        assert!(shard(&[], 100).is_empty());
+    }
+
+    #[test]
+    fn forgets_the_oldest_completed_start_beyond_the_dedupe_window() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.set_dedupe_window(3);
+        for start in [0, 300, 600, 900, 1_200] {
+            tracker.ingest("BTC-USD", candle(start, 10.0));
+        }
+        // Four candles completed, one more than the window.
+        assert_eq!(tracker.sink.completed(), vec![0, 300, 600, 900]);
+
+        // Still remembered, and skipped.
+        assert!(!tracker.ingest("BTC-USD", candle(300, 10.0)));
+        // Forgotten, the resent update is processed again.
+        let processed = tracker.processed();
+        assert!(tracker.ingest("BTC-USD", candle(0, 10.0)));
+        assert_eq!(tracker.processed(), processed + 1);
+    }
+
+    #[test]
+    fn remembers_nothing_with_the_dedupe_window_disabled() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.set_dedupe_window(0);
+        tracker.ingest("BTC-USD", candle(0, 10.0));
+        tracker.ingest("BTC-USD", candle(300, 10.0));
+
+        assert!(tracker.ingest("BTC-USD", candle(0, 10.0)));
+    }
+
+    #[test]
+    fn writes_buffered_csv_rows_once_the_flush_interval_elapses() {
+        use clock::MockClock;
+        use durability::SyncPolicy;
+
+        for sync in [SyncPolicy::Flush, SyncPolicy::Fsync, SyncPolicy::None] {
+            let dir = std::env::temp_dir().join(format!(
+                "candle_watcher_{}_{:?}",
+                std::process::id(),
+                sync
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("BTC-USD.csv");
+            // Header and rows written to the file so far.
+            let lines = || match fs::read_to_string(&path) {
+                Ok(text) => text.lines().count(),
+                Err(_) => 0,
+            };
+
+            let clock = Arc::new(MockClock::new(10_000));
+            let mut tracker =
+                TaskTracker::with_sink(RecordingSink::default()).with_csv_dir(dir.clone());
+            tracker.set_clock(clock.clone());
+            tracker.set_file_policy(FilePolicy { sync, interval: 60 });
+
+            tracker.ingest("BTC-USD", candle(0, 10.0));
+            tracker.ingest("BTC-USD", candle(300, 10.0));
+            // Nothing was written before, so the first row is written immediately.
+            let first = match sync {
+                SyncPolicy::None => 0,
+                _ => 2,
+            };
+            assert_eq!(lines(), first, "{:?}", sync);
+
+            tracker.ingest("BTC-USD", candle(600, 10.0));
+            clock.advance(Duration::from_secs(59));
+            tracker.finalize(600, 0);
+            assert_eq!(lines(), first, "{:?}", sync);
+
+            clock.advance(Duration::from_secs(1));
+            tracker.finalize(600, 0);
+            let due = match sync {
+                SyncPolicy::None => 0,
+                _ => 3,
+            };
+            assert_eq!(lines(), due, "{:?}", sync);
+
+            // Shutdown writes everything buffered, but not the in-progress candle.
+            tracker.flush();
+            assert_eq!(lines(), 3, "{:?}", sync);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn restores_history_dedupe_and_partial_candles_from_saved_state() {
+        let path =
+            std::env::temp_dir().join(format!("candle_watcher_state_{}.json", std::process::id()));
+        let clock = Arc::new(clock::MockClock::new(10_000));
+        let tracker = || {
+            let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+            tracker.set_clock(clock.clone());
+            tracker.set_timeframes(vec![Timeframe::FifteenMinutes]);
+            tracker.set_history_len(5);
+            tracker
+        };
+
+        let mut saved = tracker();
+        for (start, close) in [(0, 10.0), (300, 11.0), (600, 12.0)] {
+            saved.ingest("BTC-USD", candle(start, close));
+        }
+        saved.save_state(&path).unwrap();
+
+        let mut restored = tracker();
+        assert_eq!(restored.load_state(&path), Ok(true));
+        fs::remove_file(&path).unwrap();
+
+        let starts: Vec<u64> = restored
+            .history("BTC-USD")
+            .iter()
+            .map(|candle| candle.start)
+            .collect();
+        assert_eq!(starts, vec![0, 300]);
+        // Still a duplicate after the restart.
+        assert!(!restored.ingest("BTC-USD", candle(300, 11.0)));
+
+        // Completes the restored in-progress candle, and with it the 15 minutes that
+        // were partially built before the restart.
+        restored.ingest("BTC-USD", candle(900, 13.0));
+        assert_eq!(restored.sink.completed(), vec![600]);
+        let aggregated: Vec<&Candle> = restored
+            .sink
+            .candles
+            .iter()
+            .filter(|(_, _, info)| info.timeframe == Some(Timeframe::FifteenMinutes))
+            .map(|(_, candle, _)| candle)
+            .collect();
+        assert_eq!(aggregated.len(), 1);
+        assert_eq!(aggregated[0].start, 0);
+        assert_eq!(aggregated[0].open, 10.0);
+        assert_eq!(aggregated[0].close, 12.0);
+        assert_eq!(aggregated[0].volume, 3.0);
+    }
+
+    /// Starts of the late updates seen by the observer, shared with the test.
+    #[derive(Debug, Clone, Default)]
+    struct LateObserver {
+        /// Current and late start of each update older than the in-progress candle.
+        arrivals: Arc<Mutex<Vec<(u64, u64)>>>,
+        /// Start of each update at or before the last completed candle.
+        candles: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl CandleObserver for LateObserver {
+        fn on_late_arrival(&mut self, _product_id: &str, current_start: u64, late: &Candle) {
+            self.arrivals
+                .lock()
+                .unwrap()
+                .push((current_start, late.start));
+        }
+
+        fn on_late_candle(&mut self, _product_id: &str, candle: &Candle) {
+            self.candles.lock().unwrap().push(candle.start);
+        }
+    }
+
+    #[test]
+    fn keeps_the_newer_candle_when_an_older_update_arrives() {
+        let observer = LateObserver::default();
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.set_observer(Box::new(observer.clone()));
+        tracker.ingest("BTC-USD", candle(0, 10.0));
+        tracker.ingest("BTC-USD", candle(600, 12.0));
+
+        // Older than the in-progress candle, but after the last completed one.
+        tracker.ingest("BTC-USD", candle(300, 11.0));
+        assert_eq!(*observer.arrivals.lock().unwrap(), vec![(600, 300)]);
+        assert!(observer.candles.lock().unwrap().is_empty());
+        assert_eq!(tracker.latest_starts(), vec![("BTC-USD".to_string(), 600)]);
+        assert_eq!(tracker.sink.completed(), vec![0]);
+        assert_eq!(tracker.product_stats()["BTC-USD"].late, 1);
+    }
+
+    #[test]
+    fn passes_updates_older_than_the_last_completed_candle_to_the_observer() {
+        let observer = LateObserver::default();
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.set_observer(Box::new(observer.clone()));
+        for start in [0, 600, 900] {
+            tracker.ingest("BTC-USD", candle(start, 10.0));
+        }
+
+        // Never completed, yet older than the completed candle at 600.
+        tracker.ingest("BTC-USD", candle(300, 11.0));
+        // Also once nothing is in progress after finalizing.
+        tracker.finalize(1_200, 0);
+        tracker.ingest("BTC-USD", candle(300, 11.0));
+
+        assert_eq!(*observer.candles.lock().unwrap(), vec![300, 300]);
+        assert!(observer.arrivals.lock().unwrap().is_empty());
+        assert_eq!(tracker.sink.completed(), vec![0, 600, 900]);
+        assert_eq!(tracker.product_stats()["BTC-USD"].late, 2);
+    }
+
+    #[test]
+    fn keeps_in_progress_candles_out_of_the_sink_when_flushing_buffers() {
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.ingest("BTC-USD", candle(0, 10.0));
+        tracker.ingest("BTC-USD", candle(300, 11.0));
+
+        tracker.flush_buffers();
+        assert_eq!(tracker.sink.candles.len(), 1);
+        assert_eq!(tracker.latest_starts(), vec![("BTC-USD".to_string(), 300)]);
+
+        // Recorded as incomplete by a full flush.
+        tracker.flush();
+        assert_eq!(tracker.sink.candles.len(), 2);
+        assert!(!tracker.sink.candles[1].2.complete);
+    }
+
+    #[test]
+    fn rejects_malformed_updates_before_they_reach_the_series() {
+        let clock = Arc::new(clock::MockClock::new(1_000));
+        let mut tracker = TaskTracker::with_sink(RecordingSink::default());
+        tracker.set_clock(clock.clone());
+        tracker.message_callback(Ok(candles_message("BTC-USD", &[candle(900, 10.0)])));
+
+        clock.advance(Duration::from_secs(60));
+        let inverted = Candle {
+            high: 9.0,
+            low: 11.0,
+            ..candle(0, 10.0)
+        };
+        let future = candle(5_000, 10.0);
+        tracker.message_callback(Ok(candles_message("BTC-USD", &[inverted, future])));
+
+        assert_eq!(tracker.stats().rejected(), 2);
+        assert_eq!(tracker.processed(), 1);
+        assert_eq!(tracker.latest_starts(), vec![("BTC-USD".to_string(), 900)]);
+        assert!(tracker.sink.candles.is_empty());
+        // Neither the lag nor the freshness of the product moved.
+        assert_eq!(tracker.stats().take_max_lag(), 100);
+        let stats = &tracker.product_stats()["BTC-USD"];
+        assert_eq!((stats.last_update, stats.late), (1_000, 0));
+
+        // Other paths into the series are checked the same way.
+        assert!(!tracker.ingest("BTC-USD", candle(1_200, f64::NAN)));
+        assert_eq!(tracker.replay("BTC-USD", vec![candle(600, -1.0)]), 0);
+        assert_eq!(tracker.stats().rejected(), 4);
+        assert_eq!(tracker.latest_starts(), vec![("BTC-USD".to_string(), 900)]);
+    }
+}
@@ -1,180 +1,580 @@
-use cbadv::config::{self, BaseConfig};
-use cbadv::product::{Candle, CandleUpdate, ListProductsQuery};
-use cbadv::rest::{self, Client as RestClient};
-use cbadv::utils::Result as APIResult;
-use cbadv::websocket::{self, CandlesEvent, Channel, Message, MessageCallback, WebSocketReader};
+mod cli;
 
-use std::cmp::{Ord, Ordering};
+use cbadv::config;
+
+use candle_watcher::aggregator::Timeframe;
+use candle_watcher::alerts::LogAlertSink;
+use candle_watcher::backfill::{BackfillObserver, Backfiller};
+use candle_watcher::calendar::Calendar;
+use candle_watcher::durability::FilePolicy;
+use candle_watcher::extremes::LogExtremeSink;
+use candle_watcher::health;
+use candle_watcher::movers::LogMoverSink;
+use candle_watcher::patterns::LogPatternSink;
+use candle_watcher::ratelimit::RateLimiter;
+use candle_watcher::recorder::Recorder;
+use candle_watcher::replay::{is_recording, read_candles, read_messages, replay, replay_messages};
+use candle_watcher::rounding::Rounding;
+use candle_watcher::settings::{
+    EmptyProducts, EnvOverrides, OutputFormat, WatcherConfig, WatcherSettings,
+};
+use candle_watcher::sink::{CandleSink, NullSink};
+use candle_watcher::stats::{self, SessionSummary};
+use candle_watcher::volume::LogVolumeSpikeSink;
+use candle_watcher::watchdog::LogStaleSink;
+use candle_watcher::{
+    build_sink, build_sinks, candle_watcher, cap_tracked_products, get_products, partition,
+    sharded_watcher, validate_credentials, Markets, TaskTracker, TrackerHandle, WatcherError,
+    WatcherOptions,
+};
+use chrono::Utc;
+use chrono_tz::Tz;
+use clap::Parser;
+use cli::{Args, Command};
+use futures::future;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::signal;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::EnvFilter;
+
+/// Time between checks for stale products.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+/// Time between checks for candles due to be finalized.
+const FINALIZE_INTERVAL: Duration = Duration::from_secs(1);
+/// Seconds between summaries of a dry run, unless another interval is configured.
+const DRY_RUN_SUMMARY_INTERVAL: u64 = 60;
+/// Format of log timestamps, RFC 3339 with microseconds and the UTC offset.
+const LOG_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
 
-/// Tracks the candle watcher task.
-pub struct TaskTracker {
-    /// Total processed candles.
-    processed: usize,
-    /// Holds most recent candle processed for each product.
-    candles: HashMap<String, Candle>,
+/// Initializes logging, `RUST_LOG` takes priority over the configured level. Logs are
+/// moved to stderr when `stderr` is set, since stdout carries JSON candles or a list
+/// of products, and are only colored if `color` is set and they are written to a
+/// terminal. Times are shown in `timezone`.
+fn init_logging(default_level: &str, stderr: bool, color: bool, timezone: Option<Tz>) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let logger = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_timer(ZonedTime(timezone));
+    if stderr {
+        logger
+            .with_ansi(color && io::stderr().is_terminal())
+            .with_writer(io::stderr)
+            .init();
+    } else {
+        logger.with_ansi(color && io::stdout().is_terminal()).init();
+    }
 }
 
-impl TaskTracker {
-    /// Starts the task tracking of candles.
-    pub async fn start(reader: WebSocketReader) {
-        let tracker: TaskTracker = TaskTracker {
-            processed: 0,
-            candles: HashMap::new(),
-        };
+/// Log timestamps in the configured timezone, UTC if `None`.
+struct ZonedTime(Option<Tz>);
 
-        // Start the listener.
-        websocket::listener_with(reader, tracker).await;
-    }
-
-    /// Ejects completed candles.
-    fn check_candle(&mut self, product_id: &str, new_candle: Candle) -> Option<Candle> {
-        match self.candles.get(product_id) {
-            Some(candle) => {
-                if candle.start < new_candle.start {
-                    // Remove/eject complete candle, and replace with new series candle.
-                    let old = self.candles.remove(product_id).unwrap();
-                    self.candles.insert(product_id.to_string(), new_candle);
-                    return Some(old as Candle);
-                } else {
-                    // Replace existing.
-                    self.candles.insert(product_id.to_string(), new_candle);
-                }
+impl FormatTime for ZonedTime {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        let now = Utc::now();
+        match self.0 {
+            Some(tz) => write!(w, "{}", now.with_timezone(&tz).format(LOG_TIME_FORMAT)),
+            None => write!(w, "{}", now.format(LOG_TIME_FORMAT)),
+        }
+    }
+}
+
+/// Creates a tracker recording to `sink` with the configured indicators.
+fn build_tracker(
+    settings: &WatcherSettings,
+    sink: Box<dyn CandleSink + Send>,
+) -> TaskTracker<Box<dyn CandleSink + Send>> {
+    let mut tracker = TaskTracker::with_sink(sink);
+    if let Some(dir) = &settings.csv_dir {
+        tracker = tracker.with_csv_dir(dir.clone());
+    }
+    tracker.set_granularity(settings.granularity);
+    let mut timeframes = settings.timeframes.clone();
+    if settings.daily_bar && !timeframes.contains(&Timeframe::OneDay) {
+        // Daily bars are the 1d aggregation, built from the local day of each candle.
+        timeframes.push(Timeframe::OneDay);
+    }
+    tracker.set_timeframes(timeframes);
+    tracker.set_calendar(Calendar::new(settings.timezone));
+    tracker.set_latency_buckets(settings.latency_buckets.clone());
+    tracker.set_sma_period(settings.sma_period);
+    tracker.set_history_len(settings.history_len);
+    tracker.set_dedupe_window(settings.dedupe_window);
+    tracker.set_sampling(settings.sampling, settings.product_sampling.clone());
+    tracker.set_candle_rules(settings.candle_rules);
+    tracker.set_output_fields(settings.output_fields.clone());
+    tracker.set_file_policy(FilePolicy {
+        sync: settings.file_sync,
+        interval: settings.file_flush_interval,
+    });
+    if settings.emit_partial {
+        tracker.set_partial_interval(Some(settings.partial_interval));
+    }
+    tracker.set_macd(settings.macd);
+    tracker.set_rsi_period(settings.rsi_period);
+    tracker.set_vwap(settings.vwap);
+    tracker.set_derived(settings.emit_derived);
+    tracker.set_heikin_ashi(settings.heikin_ashi);
+    tracker.set_atr_period(settings.atr_period);
+    if settings.bollinger_period > 0 {
+        tracker.set_bollinger(settings.bollinger_period, settings.bollinger_k);
+    }
+    tracker.set_alerts(settings.alerts.clone(), Box::new(LogAlertSink));
+    if settings.patterns {
+        tracker.set_patterns(Box::new(LogPatternSink));
+    }
+    if settings.volume_spike_window > 0 {
+        tracker.set_volume_spikes(
+            settings.volume_spike_window,
+            settings.volume_spike_multiplier,
+            Box::new(LogVolumeSpikeSink),
+        );
+    }
+    if let Some(threshold) = settings.movers_threshold {
+        tracker.set_movers(threshold, Box::new(LogMoverSink));
+    }
+    if settings.extremes_period > 0 {
+        tracker.set_extremes(settings.extremes_period, Box::new(LogExtremeSink));
+    }
+    tracker
+}
+
+/// Feeds the candles recorded in `path` through a tracker instead of watching live.
+async fn run_replay(
+    settings: &WatcherSettings,
+    path: &Path,
+    speed: f64,
+) -> Result<(), WatcherError> {
+    let sink = build_sink(settings).await?;
+    let tracker = TrackerHandle::new(build_tracker(settings, sink));
+
+    if is_recording(path) {
+        let messages = read_messages(path).map_err(WatcherError::Config)?;
+        info!(
+            "Replaying {} messages from '{}'.",
+            messages.len(),
+            path.display()
+        );
+        tokio::select! {
+            replayed = replay_messages(&tracker, messages, speed) => {
+                info!("Replayed {} messages.", replayed);
             }
-            None => {
-                // Insert first candle occurrence.
-                self.candles.insert(product_id.to_string(), new_candle);
+            _ = signal::ctrl_c() => (),
+        }
+    } else {
+        let candles = read_candles(path).map_err(WatcherError::Config)?;
+        info!(
+            "Replaying {} candles from '{}'.",
+            candles.len(),
+            path.display()
+        );
+        tokio::select! {
+            processed = replay(&tracker, candles, speed) => {
+                info!("Replayed {} candle updates.", processed);
             }
+            _ = signal::ctrl_c() => (),
         }
-        return None;
     }
+
+    // The final candle of each product never saw a successor.
+    tracker.flush();
+    info!(
+        "Processed {} candle updates, {} candles completed.",
+        tracker.processed(),
+        tracker.completed()
+    );
+    Ok(())
 }
 
-impl MessageCallback for TaskTracker {
-    /// Required to pass TaskTracker to the websocket listener.
-    fn message_callback(&mut self, msg: APIResult<Message>) {
-        // Filter all non-candle and empty updates.
-        let ev: Vec<CandlesEvent> = match msg {
-            Ok(value) => match value {
-                Message::Candles(value) => {
-                    if value.events.len() == 0 {
-                        // No events / updates to process.
-                        return;
-                    }
-                    // Events being worked with.
-                    value.events
-                }
-                // Non-candle message.
-                _ => return,
-            },
-            // WebSocket error.
-            Err(err) => {
-                println!("!WEBSOCKET ERROR! {}", err);
-                return;
-            }
-        };
+/// State file of partition `index` out of `count`, numbered only with multiple.
+fn state_path(path: &Path, index: usize, count: usize) -> PathBuf {
+    if count == 1 {
+        return path.to_path_buf();
+    }
 
-        // Combine all updates and sort by more recent -> oldest.
-        let mut updates: Vec<CandleUpdate> = ev.iter().flat_map(|c| c.candles.clone()).collect();
-        self.processed += updates.len();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("json");
+    path.with_extension(format!("{}.{}", index, extension))
+}
 
-        match updates.len().cmp(&1usize) {
-            // Sort if there are more than 1 update.
-            Ordering::Greater => updates.sort_by(|a, b| b.data.start.cmp(&a.data.start)),
-            // No updates to process.
-            Ordering::Less => return,
-            _ => (),
-        };
+#[tokio::main]
+async fn main() {
+    if let Err(err) = run(Args::parse()).await {
+        error!("Unable to continue, {}", err);
+        exit(err.exit_code());
+    }
+}
 
-        // Check the candle, see if there is a completed cycle.
-        let update = updates.remove(0);
-        let product_id: String = update.product_id;
-        let candle = match self.check_candle(&product_id, update.data) {
-            Some(c) => c,
-            None => return,
-        };
+/// Watches the configured products until Ctrl-C is received.
+async fn run(args: Args) -> Result<(), WatcherError> {
+    let started = Instant::now();
+    // Load the configuration file, logging starts as soon as the level is known.
+    // Environment variables override the file, the arguments override both.
+    let mut loaded = config::load::<WatcherConfig>(&args.config);
+    let overridden = match &mut loaded {
+        Ok(c) => c.watcher.apply_env(std::env::vars()),
+        Err(_) => Ok(EnvOverrides::default()),
+    };
+    let (level, format, color, timezone) = match &loaded {
+        Ok(c) => (
+            c.watcher.log_level.clone(),
+            args.format.unwrap_or(c.watcher.output_format),
+            c.watcher.color,
+            c.watcher.timezone,
+        ),
+        Err(_) => (
+            "info".to_string(),
+            args.format.unwrap_or_default(),
+            true,
+            None,
+        ),
+    };
+    let stderr = format == OutputFormat::Json || args.command.is_some();
+    init_logging(&level, stderr, color && !args.no_color, timezone);
+
+    let mut config: WatcherConfig = match loaded {
+        Ok(c) => c,
+        Err(err) => {
+            if config::exists(&args.config) {
+                return Err(WatcherError::Config(format!(
+                    "could not load configuration file '{}': {}",
+                    args.config, err
+                )));
+            }
 
-        // Total Processed | Product_Id | Candle Start
-        println!(
-            "{} {:>10} ({}): finished candle.",
-            self.processed, product_id, candle.start
+            // Create a new configuration file with defaults.
+            if let Err(err) = config::create_base_config(&args.config) {
+                return Err(WatcherError::Config(format!(
+                    "could not create configuration file '{}': {}",
+                    args.config, err
+                )));
+            }
+            return Err(WatcherError::Config(format!(
+                "empty configuration file '{}' created, please update it",
+                args.config
+            )));
+        }
+    };
+    let overridden = overridden.map_err(WatcherError::Config)?;
+    for name in &overridden.ignored {
+        warn!(
+            "Ignoring environment variable {}, no setting has this name.",
+            name
         );
-        // println!("{} {}: {:#?}", self.processed, product_id, candle);
     }
-}
+    if !overridden.applied.is_empty() {
+        info!("Settings overridden by {}.", overridden.applied.join(", "));
+    }
+    args.apply(&mut config.watcher);
+    config.watcher.validate().map_err(WatcherError::Config)?;
+    if args.dry_run {
+        // Nothing is written, the summary shows that candles are flowing.
+        warn!("DRY RUN: candles are received but not recorded to any sink.");
+        config.watcher.state_path = None;
+        config.watcher.record_path = None;
+        config.watcher.csv_dir = None;
+        if config.watcher.summary_interval == 0 {
+            config.watcher.summary_interval = DRY_RUN_SUMMARY_INTERVAL;
+        }
+    }
+    if let Some(profile) = &args.profile {
+        config
+            .select_profile(profile)
+            .map_err(WatcherError::Config)?;
+        info!("Using profile '{}'.", profile);
+    }
+    info!("Loaded configuration from '{}'.", args.config);
+    info!("Resolved settings: {:?}", config.watcher);
 
-/// Watches candles for a set of products, producing candles once they are complete.
-async fn candle_watcher(client: &mut websocket::Client, products: &Vec<String>) {
-    // Connect and spawn a task.
-    let reader = client.connect().await.unwrap();
-    let listener = tokio::spawn(TaskTracker::start(reader));
+    // Recorded candles need neither credentials nor a connection.
+    if let Some(path) = &args.replay {
+        return run_replay(&config.watcher, path, args.speed).await;
+    }
 
-    // Keep the connection open and subscribe to candles.
-    client.sub(Channel::HEARTBEATS, &vec![]).await.unwrap();
-    client.sub(Channel::CANDLES, products).await.unwrap();
-    listener.await.unwrap()
-}
+    // Create a client to interact with the API, every request shares one budget.
+    let rclient = config.rest_client();
+    let limiter = RateLimiter::new(
+        config.watcher.rest_requests_per_second,
+        config.watcher.rest_burst,
+    );
 
-/// Obtain product names of candles to be obtained.
-async fn get_products(client: &RestClient) -> Vec<String> {
-    println!("Getting '*-USD' products.");
-    let query = ListProductsQuery {
-        ..Default::default()
+    // Fail fast on bad credentials rather than on the first WebSocket error.
+    validate_credentials(&rclient, &limiter).await?;
+    info!("Credentials accepted.");
+
+    // Preview the products a watch would use, the same filters apply.
+    if let Some(Command::Products) = args.command {
+        let markets = get_products(&rclient, &limiter, &config.watcher).await?;
+        for product_id in markets.products() {
+            println!("{}", product_id);
+        }
+        return Ok(());
+    }
+
+    // Products of interest, grouped by their market.
+    let mut markets = match &args.products {
+        Some(products) => {
+            info!(
+                "Using products from the command line: {}",
+                products.join(", ")
+            );
+            Markets::from_products(products)
+        }
+        None => get_products(&rclient, &limiter, &config.watcher).await?,
     };
 
-    // Holds all of the product names.
-    let mut product_names: Vec<String> = vec![];
+    // Products may be listed later, keep discovering them until some match.
+    let wait = args.products.is_none() && config.watcher.on_empty_products == EmptyProducts::Wait;
+    while wait && markets.is_empty() {
+        let interval = config.watcher.empty_products_interval;
+        warn!(
+            "No products matched the filters, checking again in {}s.",
+            interval
+        );
+        tokio::select! {
+            _ = signal::ctrl_c() => return Ok(()),
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => (),
+        }
 
-    // Pull multiple products from the Product API.
-    match client.product.get_bulk(&query).await {
-        Ok(products) => {
-            product_names = products
-                .iter()
-                // Filter products to only containing *-USD pairs.
-                .filter_map(|p| match p.quote_currency_id.as_str() {
-                    "USD" => Some(p.product_id.clone()),
-                    _ => None,
-                })
-                .collect();
+        match get_products(&rclient, &limiter, &config.watcher).await {
+            Ok(found) => markets = found,
+            Err(err) => warn!("Unable to obtain products: {}", err),
         }
-        Err(error) => println!("Unable to get products: {}", error),
+    }
+    let mut products = markets.products();
+    // let products = vec!["BTC-USD".to_string()];
+    cap_tracked_products(&mut products, &config.watcher)?;
+    info!("Obtained {} products.", products.len());
+    if products.is_empty() {
+        return Err(WatcherError::Config(
+            "no products to watch, check the product filters".to_string(),
+        ));
     }
 
-    product_names
-}
+    // Start watching candles, each partition with its own tracker and connection.
+    let groups = partition(&products, config.watcher.partitions);
+    let sinks: Vec<Box<dyn CandleSink + Send>> = match args.dry_run {
+        true => groups.iter().map(|_| Box::new(NullSink) as _).collect(),
+        false => build_sinks(&config.watcher, groups.len()).await?,
+    };
+    if groups.len() > 1 {
+        info!("Watching products in {} partitions.", groups.len());
+    }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    // Load the configuration file.
-    let config: BaseConfig = match config::load("config.toml") {
-        Ok(c) => c,
-        Err(err) => {
-            println!("Could not load configuration file.");
-            if config::exists("config.toml") {
-                println!("File exists, {}", err);
-                exit(1);
-            }
+    let mut trackers = vec![];
+    let mut gaps = vec![];
+    for sink in sinks {
+        let (gap_tx, gap_rx) = mpsc::unbounded_channel();
+        let mut tracker = build_tracker(&config.watcher, sink);
+        tracker.set_quotes(markets.quotes());
+        let precisions = match config.watcher.round_to_increment {
+            true => markets.precisions(),
+            false => HashMap::new(),
+        };
+        tracker.set_rounding(Rounding::new(config.watcher.round_decimals, precisions));
+        tracker.set_observer(Box::new(BackfillObserver::new(gap_tx)));
+        trackers.push(TrackerHandle::new(tracker));
+        gaps.push(gap_rx);
+    }
 
-            // Create a new configuration file with defaults.
-            config::create_base_config("config.toml").unwrap();
-            println!("Empty configuration file created, please update it.");
-            exit(1);
+    // Expose metrics for Prometheus to scrape.
+    #[cfg(feature = "metrics")]
+    if let Some(port) = config.watcher.metrics_port {
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        if let Err(err) = candle_watcher::metrics::serve_metrics(addr, trackers.clone()) {
+            return Err(WatcherError::Sink(format!(
+                "unable to serve metrics: {}",
+                err
+            )));
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    if config.watcher.metrics_port.is_some() {
+        warn!("Metrics port is set but the 'metrics' feature is not enabled.");
+    }
+
+    // Let orchestrators probe whether the watcher is alive and receiving candles.
+    if let Some(port) = config.watcher.health_port {
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        let stale_after = config.watcher.stale_after;
+        if let Err(err) = health::serve_health(addr, trackers.clone(), stale_after).await {
+            return Err(WatcherError::Sink(format!(
+                "unable to serve health checks: {}",
+                err
+            )));
+        }
+    }
+
+    // Answer debugging commands without stopping the watcher.
+    if let Some(path) = &config.watcher.control_socket {
+        #[cfg(unix)]
+        if let Err(err) = candle_watcher::control::serve_control(path.clone(), trackers.clone()) {
+            return Err(WatcherError::Config(format!(
+                "unable to serve commands on '{}': {}",
+                path.display(),
+                err
+            )));
         }
+
+        #[cfg(not(unix))]
+        warn!(
+            "Control socket '{}' is set but Unix sockets are not supported.",
+            path.display()
+        );
+    }
+
+    // Periodically summarize throughput.
+    if config.watcher.summary_interval > 0 {
+        let stats = trackers.iter().map(|tracker| tracker.stats()).collect();
+        let interval = Duration::from_secs(config.watcher.summary_interval);
+        tokio::spawn(stats::report(stats, interval));
+    }
+
+    let mut options = WatcherOptions {
+        warmup_minutes: config.watcher.warmup_minutes,
+        heartbeat_timeout: match config.watcher.heartbeat_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+        heartbeats: config.watcher.heartbeats,
+        jitter: config.watcher.reconnect_jitter,
+        refresh_on_reconnect: config.watcher.refresh_on_reconnect,
+        subscribe_batch: config.watcher.subscribe_batch_size,
+        subscribe_delay: Duration::from_millis(config.watcher.subscribe_batch_delay_ms),
+        ..Default::default()
     };
 
-    // Create a client to interact with the API.
-    let rclient = rest::from_config(&config);
-    let mut wsclient = websocket::from_config(&config);
+    // Capture the session so it can be replayed, shared by every partition.
+    if let Some(path) = &config.watcher.record_path {
+        let recorder = Recorder::new(path.clone(), config.watcher.record_max_bytes)
+            .map_err(WatcherError::Config)?;
+        options.recorder = Some(recorder);
+        info!("Recording messages to '{}'.", path.display());
+    }
 
-    // Products of interest.
-    let products = get_products(&rclient).await;
-    // let products = vec!["BTC-USD".to_string()];
-    println!("Obtained {} products.", products.len());
+    let config = Arc::new(config);
+    let count = groups.len();
+    let mut tasks = vec![];
+    for (index, ((products, tracker), gap_rx)) in
+        groups.into_iter().zip(&trackers).zip(gaps).enumerate()
+    {
+        // Resume the candles that were in progress when last stopped.
+        let mut options = options.clone();
+        options.state_path = config
+            .watcher
+            .state_path
+            .as_deref()
+            .map(|path| state_path(path, index, count));
+        if let Some(path) = &options.state_path {
+            match tracker.load_state(path) {
+                Ok(true) => {
+                    // The indicators already include the warmup candles.
+                    info!("Restored tracker state from '{}'.", path.display());
+                    options.warmup_minutes = 0;
+                }
+                Ok(false) => (),
+                Err(err) => warn!("Ignoring state file '{}': {}", path.display(), err),
+            }
+        }
+
+        // Warn about products that stopped receiving candles.
+        if config.watcher.stale_after > 0 {
+            tracker.spawn_watchdog(
+                config.watcher.stale_after,
+                WATCHDOG_INTERVAL,
+                Box::new(LogStaleSink),
+            );
+        }
+
+        // Periodically report the latest candle of every product.
+        if config.watcher.snapshot_interval > 0 {
+            tracker.spawn_snapshots(Duration::from_secs(config.watcher.snapshot_interval));
+        }
+
+        // Complete candles of quiet products once their interval has ended.
+        if let Some(grace) = config.watcher.finalize_grace {
+            tracker.spawn_finalizer(grace, FINALIZE_INTERVAL);
+        }
+
+        // Fill any gaps in the candle series from the REST API.
+        let backfiller = Backfiller::new(config.rest_client(), limiter.clone(), tracker.clone());
+        tokio::spawn(backfiller.clone().run(gap_rx));
+
+        // Every watcher stops on its own shutdown signal and flushes its tracker.
+        let config = Arc::clone(&config);
+        let tracker = tracker.clone();
+        tasks.push(tokio::spawn(async move {
+            match config.watcher.shard_size {
+                Some(size) => {
+                    let new_client = || config.websocket_client();
+                    sharded_watcher(new_client, &products, size, tracker, &backfiller, &options)
+                        .await
+                }
+                None => {
+                    let mut wsclient = config.websocket_client();
+                    candle_watcher(&mut wsclient, &products, tracker, &backfiller, &options).await
+                }
+            }
+        }));
+    }
 
-    // Start watching candles.
-    let task = candle_watcher(&mut wsclient, &products);
-    task.await;
+    // Report the session even when a watcher gave up, then return its error.
+    let results = future::join_all(tasks).await;
 
+    info!(
+        "Processed {} candle updates, {} candles completed.",
+        trackers.iter().map(|t| t.processed()).sum::<usize>(),
+        trackers.iter().map(|t| t.completed()).sum::<usize>()
+    );
+    print_summary(
+        &products,
+        &trackers,
+        started.elapsed(),
+        config.watcher.output_format,
+    );
+
+    for result in results {
+        result.map_err(|err| WatcherError::WebSocket(format!("watcher stopped: {}", err)))??;
+    }
     Ok(())
 }
+
+/// Prints the totals of the session, as a single JSON object with the JSON format.
+fn print_summary<S: CandleSink + Send + 'static>(
+    products: &[String],
+    trackers: &[TrackerHandle<S>],
+    runtime: Duration,
+    format: OutputFormat,
+) {
+    let mut product_stats = HashMap::new();
+    for tracker in trackers {
+        product_stats.extend(tracker.product_stats());
+    }
+    let reconnects = trackers.iter().map(|t| t.stats().reconnects()).sum();
+    let duplicates = trackers
+        .iter()
+        .map(|t| t.stats().duplicate_ejections())
+        .sum();
+    let summary = SessionSummary::new(products, &product_stats, reconnects, duplicates, runtime);
+
+    match format {
+        OutputFormat::Text => println!("{}", summary),
+        OutputFormat::Json => match serde_json::to_string(&summary) {
+            Ok(json) => println!("{}", json),
+            Err(err) => error!("Unable to serialize the session summary: {}", err),
+        },
+    }
+}
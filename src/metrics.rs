@@ -0,0 +1,193 @@
+//! Prometheus metrics served over HTTP, requires the `metrics` feature.
+
+use crate::sink::CandleSink;
+use crate::stats::Histogram;
+use crate::TrackerHandle;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{self, MetricFamily};
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use prometheus::{TextEncoder, TEXT_FORMAT};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Serves `/metrics` on `addr` until the returned task is aborted. Metrics are read
+/// from the trackers of every partition when scraped and combined, the message path
+/// only updates their counters.
+pub fn serve_metrics<S: CandleSink + Send + 'static>(
+    addr: SocketAddr,
+    trackers: Vec<TrackerHandle<S>>,
+) -> Result<JoinHandle<()>, hyper::Error> {
+    let trackers = Arc::new(trackers);
+    let make_service = make_service_fn(move |_conn| {
+        let trackers = Arc::clone(&trackers);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let trackers = Arc::clone(&trackers);
+                async move { Ok::<_, Infallible>(respond(&trackers, req)) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!("Serving metrics on http://{}/metrics.", addr);
+
+    Ok(tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("Metrics server stopped: {}", err);
+        }
+    }))
+}
+
+/// Responds to a single request, only `/metrics` is served.
+fn respond<S: CandleSink + Send + 'static>(
+    trackers: &[TrackerHandle<S>],
+    req: Request<Body>,
+) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    if req.uri().path() != "/metrics" {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+
+    match render(trackers) {
+        Ok(text) => {
+            response
+                .headers_mut()
+                .insert("content-type", TEXT_FORMAT.parse().unwrap());
+            *response.body_mut() = Body::from(text);
+        }
+        Err(err) => {
+            error!("Unable to render metrics: {}", err);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    response
+}
+
+/// Exposes a histogram kept by the tracker, which the `prometheus` histogram cannot
+/// be built from.
+struct HistogramCollector {
+    desc: Desc,
+    histogram: Histogram,
+}
+
+impl HistogramCollector {
+    fn new(name: &str, help: &str, histogram: Histogram) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            desc: Desc::new(name.to_string(), help.to_string(), vec![], HashMap::new())?,
+            histogram,
+        })
+    }
+}
+
+impl Collector for HistogramCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut histogram = proto::Histogram::new();
+        histogram.set_sample_count(self.histogram.count());
+        histogram.set_sample_sum(self.histogram.sum());
+        for (bound, count) in self
+            .histogram
+            .bounds()
+            .iter()
+            .zip(self.histogram.cumulative())
+        {
+            let mut bucket = proto::Bucket::new();
+            bucket.set_upper_bound(*bound);
+            bucket.set_cumulative_count(count);
+            histogram.mut_bucket().push(bucket);
+        }
+
+        let mut metric = proto::Metric::new();
+        metric.set_histogram(histogram);
+
+        let mut family = MetricFamily::new();
+        family.set_name(self.desc.fq_name.clone());
+        family.set_help(self.desc.help.clone());
+        family.set_field_type(proto::MetricType::HISTOGRAM);
+        family.mut_metric().push(metric);
+        vec![family]
+    }
+}
+
+/// Registers the current value of every metric and encodes them as text.
+fn render<S: CandleSink + Send + 'static>(
+    trackers: &[TrackerHandle<S>],
+) -> Result<String, prometheus::Error> {
+    let registry = Registry::new_custom(Some("candle_watcher".to_string()), None)?;
+
+    let processed = IntCounter::new("processed_total", "Candle updates processed.")?;
+    let connected = IntGauge::new(
+        "websocket_connected",
+        "WebSocket connections currently connected.",
+    )?;
+    let reconnects = IntCounter::new("reconnects_total", "Reconnection attempts made.")?;
+    let rejected = IntCounter::new("rejected_total", "Candle updates rejected as malformed.")?;
+    let duplicates = IntCounter::new(
+        "duplicate_ejections_total",
+        "Completed candles skipped as a repeat of the last completed one.",
+    )?;
+    let completed = IntCounterVec::new(
+        Opts::new("completed_total", "Candles completed for each product."),
+        &["product_id"],
+    )?;
+    let age = IntGaugeVec::new(
+        Opts::new(
+            "last_candle_age_seconds",
+            "Seconds since the last update was received for each product.",
+        ),
+        &["product_id"],
+    )?;
+
+    registry.register(Box::new(processed.clone()))?;
+    registry.register(Box::new(connected.clone()))?;
+    registry.register(Box::new(reconnects.clone()))?;
+    registry.register(Box::new(rejected.clone()))?;
+    registry.register(Box::new(duplicates.clone()))?;
+    registry.register(Box::new(completed.clone()))?;
+    registry.register(Box::new(age.clone()))?;
+
+    // Each product belongs to a single partition, so only the totals are combined.
+    let mut latency: Option<Histogram> = None;
+    for tracker in trackers {
+        let now = tracker.now();
+        let stats = tracker.stats();
+        processed.inc_by(stats.processed() as u64);
+        connected.add(stats.connections() as i64);
+        reconnects.inc_by(stats.reconnects() as u64);
+        rejected.inc_by(stats.rejected() as u64);
+        duplicates.inc_by(stats.duplicate_ejections() as u64);
+        match &mut latency {
+            Some(latency) => latency.merge(&stats.latency()),
+            None => latency = Some(stats.latency()),
+        }
+
+        for (product_id, product) in tracker.product_stats() {
+            completed
+                .with_label_values(&[&product_id])
+                .inc_by(product.completed as u64);
+            age.with_label_values(&[&product_id])
+                .set(now.saturating_sub(product.last_update) as i64);
+        }
+    }
+
+    registry.register(Box::new(HistogramCollector::new(
+        "completion_latency_seconds",
+        "Seconds between the end of a candles interval and it completing.",
+        latency.unwrap_or_default(),
+    )?))?;
+
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&registry.gather(), &mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
@@ -0,0 +1,69 @@
+//! Reports completed candles whose close moved sharply from the previous close.
+
+use cbadv::product::Candle;
+use tracing::info;
+
+/// Completed candle whose close changed by at least the threshold.
+#[derive(Debug, Clone)]
+pub struct Mover {
+    /// Product the candle belongs to.
+    pub product_id: String,
+    /// Start of the candle.
+    pub candle_start: u64,
+    /// Close of the candle.
+    pub close: f64,
+    /// Percent change of the close from the previous completed close.
+    pub change: f64,
+}
+
+/// Receives movers as they are detected.
+pub trait MoverSink {
+    /// Called for each completed candle whose absolute change exceeds the threshold.
+    fn on_mover(&mut self, mover: &Mover);
+}
+
+/// Prints each mover.
+pub struct LogMoverSink;
+
+impl MoverSink for LogMoverSink {
+    fn on_mover(&mut self, mover: &Mover) {
+        info!(
+            product_id = %mover.product_id,
+            start = mover.candle_start,
+            close = mover.close,
+            "Mover, close changed {:+.2}%.",
+            mover.change
+        );
+    }
+}
+
+/// Passes completed candles that moved by more than a threshold to a sink.
+pub struct MoverFilter {
+    /// Smallest absolute percent change that is reported.
+    threshold: f64,
+    /// Receives the movers.
+    sink: Box<dyn MoverSink + Send>,
+}
+
+impl MoverFilter {
+    /// Creates a filter passing candles that moved more than `threshold` percent,
+    /// in either direction, to `sink`.
+    pub fn new(threshold: f64, sink: Box<dyn MoverSink + Send>) -> Self {
+        Self { threshold, sink }
+    }
+
+    /// Checks the `change` of a completed candle, `None` for the first candle.
+    pub fn check(&mut self, product_id: &str, candle: &Candle, change: Option<f64>) {
+        let change = match change {
+            Some(change) if change.abs() > self.threshold => change,
+            _ => return,
+        };
+
+        self.sink.on_mover(&Mover {
+            product_id: product_id.to_string(),
+            candle_start: candle.start,
+            close: candle.close,
+            change,
+        });
+    }
+}
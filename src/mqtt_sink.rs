@@ -0,0 +1,144 @@
+//! Publishes completed candles to an MQTT broker, requires the `mqtt` feature.
+
+use crate::sink::{CandleInfo, CandleRecord, CandleSink};
+
+use cbadv::product::Candle;
+use rumqttc::{AsyncClient, ConnectionError, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// Maximum candles waiting to be published, new candles are dropped beyond this.
+const QUEUE_CAPACITY: usize = 1_000;
+/// Interval of the keep alive pings sent to the broker.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+/// Delay before the first reconnection attempt, doubled for each following attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the delay between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Maximum time to wait on shutdown for the queued candles to be sent.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Broker that candles are published to and how.
+#[derive(Debug, Clone)]
+pub struct MqttTarget {
+    /// Host name or address of the broker.
+    pub host: String,
+    /// Port of the broker, usually 1883.
+    pub port: u16,
+    /// Identifies the connection to the broker, unique between watchers.
+    pub client_id: String,
+    /// Username and password, if the broker requires them.
+    pub credentials: Option<(String, String)>,
+    /// Prefix of the topics, candles are published to `<prefix>/<product_id>`.
+    pub topic_prefix: String,
+    /// Delivery guarantee of each candle: 0 at most once, 1 at least once, 2 exactly once.
+    pub qos: u8,
+}
+
+/// Publishes each completed candle as JSON to `<prefix>/<product_id>`. The connection
+/// is kept by a separate task that reconnects on its own, candles are buffered while
+/// the broker is unavailable. Disconnects cleanly once flushed on shutdown.
+pub struct MqttSink {
+    client: AsyncClient,
+    prefix: String,
+    qos: QoS,
+    /// Signaled by the connection task once the disconnect was sent.
+    closed: Receiver<()>,
+}
+
+impl MqttSink {
+    /// Creates the sink and spawns the task connecting to the broker of `target`.
+    pub fn new(target: MqttTarget) -> Self {
+        let mut options = MqttOptions::new(target.client_id, target.host, target.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some((username, password)) = target.credentials {
+            options.set_credentials(username, password);
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, QUEUE_CAPACITY);
+        let (done, closed) = mpsc::channel();
+        tokio::spawn(connection_loop(eventloop, done));
+
+        Self {
+            client,
+            prefix: target.topic_prefix,
+            qos: qos_of(target.qos),
+            closed,
+        }
+    }
+}
+
+impl CandleSink for MqttSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        let payload = match serde_json::to_vec(&CandleRecord::new(product_id, candle, info)) {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!(product_id, "unable to serialize candle for MQTT: {}", err);
+                return;
+            }
+        };
+
+        // Only queues the candle, never waits on the broker.
+        let topic = format!("{}/{}", self.prefix, product_id);
+        if let Err(err) = self.client.try_publish(topic, self.qos, false, payload) {
+            warn!(product_id, "MQTT queue is full, dropped candle: {}", err);
+        }
+    }
+
+    fn flush(&mut self) {
+        // Queued after the candles, so they are sent first.
+        if let Err(err) = self.client.try_disconnect() {
+            warn!("Unable to disconnect from MQTT: {}", err);
+            return;
+        }
+        if self.closed.recv_timeout(DISCONNECT_TIMEOUT).is_err() {
+            warn!("MQTT broker did not disconnect in time, queued candles may be lost.");
+        }
+    }
+}
+
+/// Quality of service for a level, levels above 2 are treated as 2.
+fn qos_of(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Drives the connection until the disconnect is sent or the sink is dropped,
+/// reconnecting with backoff.
+/// Unacknowledged candles are sent again once reconnected.
+async fn connection_loop(mut eventloop: EventLoop, done: Sender<()>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT.");
+                backoff = INITIAL_BACKOFF;
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                let _ = done.send(());
+                return;
+            }
+            Ok(_) => (),
+            // The sink was dropped without disconnecting.
+            Err(ConnectionError::RequestsDone) => return,
+            Err(err) => {
+                warn!(
+                    "MQTT connection failed, retrying in {}s: {}",
+                    backoff.as_secs(),
+                    err
+                );
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
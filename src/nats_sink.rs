@@ -0,0 +1,196 @@
+//! Publishes completed candles to NATS subjects, requires the `nats` feature.
+
+use crate::sink::{CandleInfo, CandleRecord, CandleSink};
+
+use async_nats::jetstream::{self, stream};
+use async_nats::Client;
+use cbadv::product::Candle;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// Maximum candles waiting to be published, the oldest are dropped beyond this.
+const QUEUE_CAPACITY: usize = 1_000;
+/// Delay before the first retry, doubled for each following attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the delay between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Candles waiting to be published, shared with the publishing task.
+struct Queue {
+    /// Pending candles, oldest first.
+    candles: Mutex<VecDeque<CandleRecord>>,
+    /// Wakes the publishing task when candles are queued.
+    notify: Notify,
+}
+
+impl Queue {
+    /// Adds a candle to the back of the queue, dropping the oldest past the capacity.
+    fn push(&self, record: CandleRecord) {
+        let mut candles = self.candles.lock().unwrap();
+        candles.push_back(record);
+        if candles.len() > QUEUE_CAPACITY {
+            if let Some(dropped) = candles.pop_front() {
+                warn!(
+                    "NATS queue is full, dropped candle for {} ({}).",
+                    dropped.product_id, dropped.start
+                );
+            }
+        }
+    }
+
+    /// Puts a candle that failed to publish back at the front, unless the queue filled.
+    fn retry(&self, record: CandleRecord) {
+        let mut candles = self.candles.lock().unwrap();
+        if candles.len() < QUEUE_CAPACITY {
+            candles.push_front(record);
+        }
+    }
+}
+
+/// Where candles are published once connected.
+enum Publisher {
+    /// Plain subjects, delivered only to connected subscribers.
+    Core(Client),
+    /// Subjects captured by a JetStream stream, each publish waits for the ack.
+    JetStream(jetstream::Context),
+}
+
+impl Publisher {
+    /// Connects to `url`, creating `stream` over the subjects of `prefix` if set.
+    async fn connect(url: &str, prefix: &str, stream: Option<&str>) -> Result<Self, String> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let name = match stream {
+            Some(name) => name,
+            None => return Ok(Publisher::Core(client)),
+        };
+
+        let context = jetstream::new(client);
+        context
+            .get_or_create_stream(stream::Config {
+                name: name.to_string(),
+                subjects: vec![format!("{}.>", prefix)],
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok(Publisher::JetStream(context))
+    }
+
+    /// Publishes a single payload to `subject`.
+    async fn publish(&self, subject: String, payload: Vec<u8>) -> Result<(), String> {
+        match self {
+            Publisher::Core(client) => client
+                .publish(subject, payload.into())
+                .await
+                .map_err(|err| err.to_string()),
+            Publisher::JetStream(context) => {
+                let ack = context
+                    .publish(subject, payload.into())
+                    .await
+                    .map_err(|err| err.to_string())?;
+                ack.await.map(|_| ()).map_err(|err| err.to_string())
+            }
+        }
+    }
+}
+
+/// Publishes each completed candle as JSON to `<prefix>.<product_id>`, optionally
+/// into a JetStream stream for durability. Publishing happens on a separate task,
+/// candles are queued while NATS is unavailable.
+pub struct NatsSink {
+    queue: Arc<Queue>,
+}
+
+impl NatsSink {
+    /// Creates the sink and spawns the task that publishes to the server at `url`.
+    pub fn new(url: String, prefix: String, stream: Option<String>) -> Self {
+        let queue = Arc::new(Queue {
+            candles: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+        });
+
+        tokio::spawn(publish_loop(url, prefix, stream, Arc::clone(&queue)));
+        Self { queue }
+    }
+}
+
+impl CandleSink for NatsSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        self.queue.push(CandleRecord::new(product_id, candle, info));
+        self.queue.notify.notify_one();
+    }
+}
+
+/// Publishes queued candles in order. The client reconnects on its own once
+/// connected, failed publishes are retried with backoff.
+async fn publish_loop(url: String, prefix: String, stream: Option<String>, queue: Arc<Queue>) {
+    let mut publisher: Option<Publisher> = None;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let empty = queue.candles.lock().unwrap().is_empty();
+        if empty {
+            queue.notify.notified().await;
+            continue;
+        }
+
+        if publisher.is_none() {
+            match Publisher::connect(&url, &prefix, stream.as_deref()).await {
+                Ok(connected) => {
+                    info!("Connected to NATS.");
+                    publisher = Some(connected);
+                    backoff = INITIAL_BACKOFF;
+                }
+                Err(err) => {
+                    warn!(
+                        "Unable to connect to NATS, retrying in {}s: {}",
+                        backoff.as_secs(),
+                        err
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            }
+        }
+
+        let next = queue.candles.lock().unwrap().pop_front();
+        let record = match next {
+            Some(record) => record,
+            None => continue,
+        };
+        let payload = match serde_json::to_vec(&record) {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!("Unable to serialize candle for NATS: {}", err);
+                continue;
+            }
+        };
+
+        let subject = format!("{}.{}", prefix, record.product_id);
+        let connected = publisher.as_ref().unwrap();
+        if let Err(err) = connected.publish(subject, payload).await {
+            warn!(
+                "NATS publish failed, retrying in {}s: {}",
+                backoff.as_secs(),
+                err
+            );
+            queue.retry(record);
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        } else {
+            backoff = INITIAL_BACKOFF;
+        }
+    }
+}
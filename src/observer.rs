@@ -0,0 +1,91 @@
+//! Hooks for events that occur while tracking candles.
+
+use cbadv::product::Candle;
+use std::fmt;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Receives notable events from the tracker, every hook defaults to doing nothing.
+pub trait CandleObserver {
+    /// A candle was expected to start at `expected_start` but the next candle started
+    /// at `actual_start`, leaving a gap in the series.
+    fn on_gap(&mut self, _product_id: &str, _expected_start: u64, _actual_start: u64) {}
+
+    /// Tracking of a product started with its first candle, either from the WebSocket
+    /// or the newest seeded candle. Called once per product until it is reset or
+    /// flushed, separately from completions.
+    fn on_first_candle(&mut self, _product_id: &str, _candle: &Candle) {}
+
+    /// A candle starting at `start` began for a product, called once for each start
+    /// as the previous candle completes. `open` is its opening price.
+    fn on_candle_open(&mut self, _product_id: &str, _start: u64, _open: f64) {}
+
+    /// An update for an older candle arrived after the in-progress candle starting at
+    /// `current_start`. The newer candle is kept and the update ignored.
+    fn on_late_arrival(&mut self, _product_id: &str, _current_start: u64, _late: &Candle) {}
+
+    /// An update arrived for a candle at or before the last completed candle of the
+    /// product. It is not recorded again, but can correct candles already stored.
+    fn on_late_candle(&mut self, _product_id: &str, _candle: &Candle) {}
+}
+
+/// Observer that logs each event.
+pub struct LogObserver;
+
+impl CandleObserver for LogObserver {
+    fn on_gap(&mut self, product_id: &str, expected_start: u64, actual_start: u64) {
+        let missing = actual_start.saturating_sub(expected_start);
+        warn!(
+            product_id,
+            expected_start,
+            actual_start,
+            "gap of {} minutes detected.",
+            missing / 60
+        );
+    }
+
+    fn on_first_candle(&mut self, product_id: &str, candle: &Candle) {
+        debug!(product_id, start = candle.start, "tracking started.");
+    }
+
+    fn on_candle_open(&mut self, product_id: &str, start: u64, open: f64) {
+        debug!(product_id, start, open, "candle opened.");
+    }
+
+    fn on_late_arrival(&mut self, product_id: &str, current_start: u64, late: &Candle) {
+        debug!(
+            product_id,
+            current_start,
+            start = late.start,
+            "late update ignored, a newer candle is in progress."
+        );
+    }
+
+    fn on_late_candle(&mut self, product_id: &str, candle: &Candle) {
+        warn!(
+            product_id,
+            start = candle.start,
+            "late update for a candle that already completed."
+        );
+    }
+}
+
+/// Receives the lifecycle of the WebSocket connections, every hook defaults to doing
+/// nothing. The connections of every shard share it, so it is called from several tasks.
+pub trait ConnectionObserver: fmt::Debug + Send + Sync {
+    /// The connection was established and subscribed to candles.
+    fn on_connected(&self) {}
+
+    /// The connection was lost or could not be established, `reason` describes why.
+    fn on_disconnected(&self, _reason: &str) {}
+
+    /// Reconnection `attempt`, counted from 1 since the connection was last
+    /// established, starts once `delay` has passed.
+    fn on_reconnecting(&self, _attempt: u32, _delay: Duration) {}
+}
+
+/// Connection observer that ignores every event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopConnectionObserver;
+
+impl ConnectionObserver for NoopConnectionObserver {}
@@ -0,0 +1,204 @@
+//! Exports candles to Parquet files, one file per product each day.
+
+use crate::arrow_sink::{candle_batch, candle_schema};
+use crate::calendar::Calendar;
+use crate::durability::{self, FilePolicy};
+use crate::sink::{CandleInfo, CandleSink};
+
+use arrow::datatypes::Schema;
+use cbadv::product::Candle;
+use chrono::{Duration, NaiveDate};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::Result as ParquetResult;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{error, info};
+
+/// Candles of a single product for a single day, waiting to be written.
+struct DayBuffer {
+    /// Days since the Unix epoch of the candles, in the timezone of the calendar.
+    day: i64,
+    /// Candles in the order they completed.
+    candles: Vec<Candle>,
+}
+
+/// Buffers completed candles in memory and writes them to `<dir>/<product>/<date>.parquet`
+/// once the day rolls over, every flush interval of the file policy, or on shutdown.
+/// Parquet files cannot be appended to, so a day written more than once gains a
+/// numbered suffix such as `2024-01-15.1.parquet`.
+pub struct ParquetSink {
+    /// Directory containing a subdirectory for each product.
+    dir: PathBuf,
+    /// Candles waiting to be written for each product.
+    buffers: HashMap<String, DayBuffer>,
+    /// Columns of every file written.
+    schema: Arc<Schema>,
+    /// When the buffered candles are written and whether they are synced to disk.
+    policy: FilePolicy,
+    /// Time the buffered candles were last written on the flush interval.
+    written: Instant,
+    /// Days the files are rotated by.
+    calendar: Calendar,
+}
+
+impl ParquetSink {
+    /// Creates a sink writing files within `dir`.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            buffers: HashMap::new(),
+            schema: candle_schema(),
+            policy: FilePolicy::default(),
+            written: Instant::now(),
+            calendar: Calendar::default(),
+        }
+    }
+
+    /// Rotates the files at midnight in the timezone of `calendar`, UTC by default.
+    pub fn set_calendar(&mut self, calendar: Calendar) {
+        self.calendar = calendar;
+    }
+
+    /// Sets how often the buffered candles are written and whether the files are synced
+    /// to disk. A flush interval of 0 only writes when the day rolls over, since every
+    /// write starts a new file.
+    pub fn set_policy(&mut self, policy: FilePolicy) {
+        self.policy = policy;
+    }
+
+    /// Writes every buffered candle, keeping the days they belong to.
+    fn write_buffered(&mut self, fsync: bool) {
+        self.written = Instant::now();
+        let buffered: Vec<(String, DayBuffer)> = self
+            .buffers
+            .iter_mut()
+            .map(|(product_id, buffer)| {
+                let candles = std::mem::take(&mut buffer.candles);
+                let day = buffer.day;
+                (product_id.clone(), DayBuffer { day, candles })
+            })
+            .collect();
+        for (product_id, buffer) in buffered {
+            self.write(&product_id, buffer, fsync);
+        }
+    }
+
+    /// Writes the buffered candles of a product, logging any failure.
+    fn write(&self, product_id: &str, buffer: DayBuffer, fsync: bool) {
+        if buffer.candles.is_empty() {
+            return;
+        }
+
+        match self.write_file(product_id, &buffer, fsync) {
+            Ok(path) => info!(
+                product_id,
+                "wrote {} candles to '{}'.",
+                buffer.candles.len(),
+                path.display()
+            ),
+            Err(err) => error!(product_id, "unable to write Parquet file: {}", err),
+        }
+    }
+
+    /// Writes the candles to a new file, returning its path.
+    fn write_file(
+        &self,
+        product_id: &str,
+        buffer: &DayBuffer,
+        fsync: bool,
+    ) -> ParquetResult<PathBuf> {
+        let dir = self.dir.join(product_id);
+        fs::create_dir_all(&dir)?;
+        let path = available_path(&dir, &format_date(buffer.day));
+
+        let batch = candle_batch(&buffer.candles)?;
+
+        let mut writer =
+            ArrowWriter::try_new(File::create(&path)?, Arc::clone(&self.schema), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        durability::sync(&File::open(&path)?, fsync)?;
+        Ok(path)
+    }
+}
+
+impl CandleSink for ParquetSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        // Only native candles that completed, files would otherwise mix granularities.
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        let day = self.calendar.day(candle.start);
+        let rotated = match self.buffers.get_mut(product_id) {
+            Some(buffer) if buffer.day == day => None,
+            Some(buffer) => Some(std::mem::replace(
+                buffer,
+                DayBuffer {
+                    day,
+                    candles: vec![],
+                },
+            )),
+            None => {
+                self.buffers.insert(
+                    product_id.to_string(),
+                    DayBuffer {
+                        day,
+                        candles: vec![],
+                    },
+                );
+                None
+            }
+        };
+
+        // The previous day is complete, write it before buffering the new day.
+        if let Some(previous) = rotated {
+            self.write(product_id, previous, self.policy.fsync());
+        }
+        if let Some(buffer) = self.buffers.get_mut(product_id) {
+            buffer.candles.push(candle.clone());
+        }
+
+        let elapsed = self.written.elapsed().as_secs();
+        if self.policy.interval > 0 && self.policy.is_due(elapsed) {
+            self.write_buffered(self.policy.fsync());
+        }
+    }
+
+    fn flush(&mut self) {
+        // Nothing buffered may be lost on a graceful shutdown.
+        for (product_id, buffer) in std::mem::take(&mut self.buffers) {
+            self.write(&product_id, buffer, true);
+        }
+    }
+}
+
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Path of a file for `date` within `dir` that does not exist yet.
+fn available_path(dir: &Path, date: &str) -> PathBuf {
+    let path = dir.join(format!("{}.parquet", date));
+    if !path.exists() {
+        return path;
+    }
+
+    (1..)
+        .map(|n| dir.join(format!("{}.{}.parquet", date, n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Formats days since the Unix epoch as a `YYYY-MM-DD` date.
+fn format_date(days: i64) -> String {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    (epoch + Duration::days(days))
+        .format("%Y-%m-%d")
+        .to_string()
+}
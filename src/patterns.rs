@@ -0,0 +1,234 @@
+//! Detects simple candlestick patterns in completed candles.
+
+use cbadv::product::Candle;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::info;
+
+/// Largest body, as a fraction of the candles range, that is considered a doji.
+const DOJI_BODY_RATIO: f64 = 0.1;
+/// Smallest lower wick of a hammer, as a multiple of its body.
+const HAMMER_WICK_RATIO: f64 = 2.0;
+/// Largest upper wick of a hammer, as a fraction of the candles range.
+const HAMMER_UPPER_RATIO: f64 = 0.1;
+
+/// Patterns that can be detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Open and close are nearly equal relative to the range.
+    Doji,
+    /// Small body near the high with a long lower wick.
+    Hammer,
+    /// Rising body that engulfs the previous falling body.
+    BullishEngulfing,
+    /// Falling body that engulfs the previous rising body.
+    BearishEngulfing,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Doji => write!(f, "doji"),
+            Pattern::Hammer => write!(f, "hammer"),
+            Pattern::BullishEngulfing => write!(f, "bullish engulfing"),
+            Pattern::BearishEngulfing => write!(f, "bearish engulfing"),
+        }
+    }
+}
+
+/// Detects the patterns formed by `cur`, engulfing patterns also require `prev`.
+///
+/// - Doji: the body is at most 10% of the range.
+/// - Hammer: not a doji, the lower wick is at least twice the body and the upper
+///   wick is at most 10% of the range.
+/// - Engulfing: the previous body moved the opposite direction and is contained
+///   within the current body, which is larger.
+pub fn detect(prev: Option<&Candle>, cur: &Candle) -> Vec<Pattern> {
+    let mut patterns = vec![];
+
+    let range = cur.high - cur.low;
+    let body = (cur.close - cur.open).abs();
+    if range > 0.0 {
+        let upper = cur.high - cur.open.max(cur.close);
+        let lower = cur.open.min(cur.close) - cur.low;
+
+        if body <= range * DOJI_BODY_RATIO {
+            patterns.push(Pattern::Doji);
+        } else if lower >= body * HAMMER_WICK_RATIO && upper <= range * HAMMER_UPPER_RATIO {
+            patterns.push(Pattern::Hammer);
+        }
+    }
+
+    if let Some(prev) = prev {
+        let prev_body = (prev.close - prev.open).abs();
+        if body > prev_body {
+            let rising = cur.close > cur.open;
+            let falling = cur.close < cur.open;
+
+            if prev.close < prev.open && rising && cur.open <= prev.close && cur.close >= prev.open
+            {
+                patterns.push(Pattern::BullishEngulfing);
+            } else if prev.close > prev.open
+                && falling
+                && cur.open >= prev.close
+                && cur.close <= prev.open
+            {
+                patterns.push(Pattern::BearishEngulfing);
+            }
+        }
+    }
+
+    patterns
+}
+
+/// Receives patterns as they are detected.
+pub trait PatternSink {
+    /// Called for each pattern formed by a completed candle.
+    fn on_pattern(&mut self, product_id: &str, candle: &Candle, pattern: Pattern);
+}
+
+/// Prints each detected pattern.
+pub struct LogPatternSink;
+
+impl PatternSink for LogPatternSink {
+    fn on_pattern(&mut self, product_id: &str, candle: &Candle, pattern: Pattern) {
+        info!(
+            product_id,
+            start = candle.start,
+            close = candle.close,
+            "{} pattern detected.",
+            pattern
+        );
+    }
+}
+
+/// Checks completed candles of each product against the previous completed candle.
+pub struct PatternTracker {
+    /// Previous completed candle for each product.
+    previous: HashMap<String, Candle>,
+    /// Receives detected patterns.
+    sink: Box<dyn PatternSink + Send>,
+}
+
+impl PatternTracker {
+    /// Creates a tracker passing detected patterns to `sink`.
+    pub fn new(sink: Box<dyn PatternSink + Send>) -> Self {
+        Self {
+            previous: HashMap::new(),
+            sink,
+        }
+    }
+
+    /// Detects the patterns formed by a completed candle.
+    pub fn check(&mut self, product_id: &str, candle: &Candle) {
+        let prev = self.previous.get(product_id);
+        for pattern in detect(prev, candle) {
+            self.sink.on_pattern(product_id, candle, pattern);
+        }
+        self.previous.insert(product_id.to_string(), candle.clone());
+    }
+
+    /// Forgets the previous candle of a product.
+    pub fn reset(&mut self, product_id: &str) {
+        self.previous.remove(product_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Candle with the given prices.
+    fn ohlc(open: f64, high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            start: 0,
+            open,
+            high,
+            low,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    #[test]
+    fn detects_a_doji() {
+        assert_eq!(
+            detect(None, &ohlc(100.0, 105.0, 95.0, 100.5)),
+            vec![Pattern::Doji]
+        );
+        assert!(detect(None, &ohlc(100.0, 105.0, 95.0, 103.0)).is_empty());
+        // Without a range there is nothing to compare the body to.
+        assert!(detect(None, &ohlc(100.0, 100.0, 100.0, 100.0)).is_empty());
+    }
+
+    #[test]
+    fn detects_a_hammer() {
+        assert_eq!(
+            detect(None, &ohlc(99.0, 100.6, 95.0, 100.5)),
+            vec![Pattern::Hammer]
+        );
+        // The upper wick is too long.
+        assert!(detect(None, &ohlc(99.0, 103.0, 95.0, 100.5)).is_empty());
+        // The lower wick is too short.
+        assert!(detect(None, &ohlc(99.0, 100.6, 97.5, 100.5)).is_empty());
+    }
+
+    #[test]
+    fn detects_a_bullish_engulfing() {
+        let falling = ohlc(102.0, 103.0, 99.0, 100.0);
+        let rising = ohlc(99.5, 103.5, 99.0, 103.0);
+        assert_eq!(
+            detect(Some(&falling), &rising),
+            vec![Pattern::BullishEngulfing]
+        );
+        assert!(detect(None, &rising).is_empty());
+
+        // The previous candle rose as well.
+        let prev_rising = ohlc(100.0, 102.5, 99.5, 102.0);
+        assert!(detect(Some(&prev_rising), &rising).is_empty());
+        // Opens above the previous close, the previous body is not contained.
+        let gap_up = ohlc(100.5, 103.5, 100.5, 103.5);
+        assert!(detect(Some(&falling), &gap_up).is_empty());
+    }
+
+    #[test]
+    fn detects_a_bearish_engulfing() {
+        let rising = ohlc(100.0, 102.5, 99.5, 102.0);
+        let falling = ohlc(102.5, 103.0, 98.5, 99.0);
+        assert_eq!(
+            detect(Some(&rising), &falling),
+            vec![Pattern::BearishEngulfing]
+        );
+
+        // A smaller body never engulfs.
+        let small = ohlc(102.0, 102.5, 100.5, 101.0);
+        assert!(detect(Some(&rising), &small).is_empty());
+    }
+
+    /// Keeps the patterns detected for each product.
+    struct Detected(Arc<Mutex<Vec<(String, Pattern)>>>);
+
+    impl PatternSink for Detected {
+        fn on_pattern(&mut self, product_id: &str, _candle: &Candle, pattern: Pattern) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((product_id.to_string(), pattern));
+        }
+    }
+
+    #[test]
+    fn compares_with_the_previous_candle_of_the_same_product() {
+        let detected = Arc::new(Mutex::new(vec![]));
+        let mut tracker = PatternTracker::new(Box::new(Detected(Arc::clone(&detected))));
+        tracker.check("BTC-USD", &ohlc(102.0, 103.0, 99.0, 100.0));
+        tracker.check("ETH-USD", &ohlc(99.5, 103.5, 99.0, 103.0));
+        tracker.check("BTC-USD", &ohlc(99.5, 103.5, 99.0, 103.0));
+
+        assert_eq!(
+            *detected.lock().unwrap(),
+            vec![("BTC-USD".to_string(), Pattern::BullishEngulfing)]
+        );
+    }
+}
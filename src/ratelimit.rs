@@ -0,0 +1,147 @@
+//! Shares a budget of REST requests between everything that calls the API, so warmup,
+//! backfill, and product discovery together stay within the Coinbase rate limits.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+use tracing::warn;
+
+/// Rate limited requests retried before the error is returned.
+const MAX_RETRIES: u32 = 5;
+/// Wait after the first rate limited request when the API does not say how long.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest wait after a rate limited request.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Tokens available to requests, refilled continuously.
+#[derive(Debug)]
+struct Bucket {
+    /// Tokens added each second, 0 disables the limit.
+    rate: f64,
+    /// Most tokens held, the largest burst of requests.
+    capacity: f64,
+    /// Tokens currently held.
+    tokens: f64,
+    /// When the tokens were last refilled.
+    refilled: Instant,
+    /// Requests wait until this after the API rejected one for its rate.
+    paused_until: Option<Instant>,
+}
+
+impl Bucket {
+    /// Takes a token, otherwise returns how long until one is available.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.paused_until {
+            if now < until {
+                return Err(until - now);
+            }
+            self.paused_until = None;
+        }
+        if self.rate <= 0.0 {
+            return Ok(());
+        }
+
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// Token bucket limiting the REST requests of every clone to `rate` per second.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `rate` requests per second on average and bursts of
+    /// up to `burst`. A `rate` of 0 never delays requests.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate,
+                capacity,
+                tokens: capacity,
+                refilled: Instant::now(),
+                paused_until: None,
+            })),
+        }
+    }
+
+    /// Waits until a request can be made.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                match bucket.take(Instant::now()) {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+
+    /// Handles a failed request. If the API rejected it for its rate, every request
+    /// is paused for the `Retry-After` in the error or an exponential backoff, and
+    /// true is returned so the request is retried after [`Self::acquire`]. `attempt`
+    /// starts at 1 and the request is given up on after a few attempts.
+    pub fn throttled(&self, err: &str, attempt: u32) -> bool {
+        if !is_rate_limited(err) || attempt > MAX_RETRIES {
+            return false;
+        }
+
+        let wait = retry_after(err).unwrap_or_else(|| {
+            (INITIAL_BACKOFF * 2u32.saturating_pow(attempt - 1)).min(MAX_BACKOFF)
+        });
+        warn!(
+            "Rate limited by the API, attempt {}/{}, retrying in {}s.",
+            attempt,
+            MAX_RETRIES,
+            wait.as_secs()
+        );
+
+        let mut bucket = self.bucket.lock().unwrap();
+        let until = Instant::now() + wait;
+        if bucket.paused_until.map_or(true, |paused| paused < until) {
+            bucket.paused_until = Some(until);
+        }
+        true
+    }
+}
+
+impl Default for RateLimiter {
+    /// Never delays requests.
+    fn default() -> Self {
+        Self::new(0.0, 1)
+    }
+}
+
+/// Whether an API error was caused by exceeding the rate limit.
+fn is_rate_limited(err: &str) -> bool {
+    let err = err.to_lowercase();
+    ["429", "too many requests", "rate limit"]
+        .iter()
+        .any(|cause| err.contains(cause))
+}
+
+/// Seconds to wait given by a `Retry-After` within an API error, if any.
+fn retry_after(err: &str) -> Option<Duration> {
+    let err = err.to_lowercase();
+    let rest = &err[err.find("retry-after")? + "retry-after".len()..];
+    let seconds: String = rest
+        .trim_start_matches(|c: char| c == ':' || c == '=' || c == '"' || c.is_whitespace())
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    let seconds: u64 = seconds.parse().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_BACKOFF))
+}
@@ -0,0 +1,130 @@
+//! Records raw WebSocket messages to newline delimited JSON so a live session can be
+//! reproduced later.
+//!
+//! Each line holds the message and the Unix time, in milliseconds, it was received:
+//! `{"received":1700000000123,"message":{...}}`.
+
+use cbadv::websocket::Message;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::{error, info, warn};
+
+/// Lines waiting to be written, messages are dropped beyond this.
+const QUEUE_CAPACITY: usize = 4_096;
+
+/// Single recorded message.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordedMessage {
+    /// Unix time, in milliseconds, the message was received.
+    pub received: u64,
+    /// Message as received.
+    pub message: Message,
+}
+
+/// Queues messages to be written by a separate task, never blocking the caller.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    sender: Sender<String>,
+}
+
+impl Recorder {
+    /// Opens `path` for appending and spawns the task writing to it. Once the file
+    /// exceeds `max_bytes` it is renamed to `<path>.1`, replacing the previous one,
+    /// and a new file is started. A `max_bytes` of 0 never rotates.
+    pub fn new(path: PathBuf, max_bytes: u64) -> Result<Self, String> {
+        let file = open(&path)?;
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(write_loop(path, max_bytes, file, receiver));
+        Ok(Self { sender })
+    }
+
+    /// Queues a message received now, dropping it if the writer has fallen behind.
+    pub fn record(&self, message: &Message) {
+        let received = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+
+        let line = match serde_json::to_string(&BorrowedMessage { received, message }) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Unable to serialize message for recording: {}", err);
+                return;
+            }
+        };
+
+        match self.sender.try_send(line) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => warn!("Recorder is behind, dropped a message."),
+            Err(TrySendError::Closed(_)) => (),
+        }
+    }
+}
+
+/// Serialized identically to [`RecordedMessage`], without taking the message.
+#[derive(Serialize)]
+struct BorrowedMessage<'a> {
+    received: u64,
+    message: &'a Message,
+}
+
+/// Opens `path` for appending, creating it if needed.
+fn open(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("unable to open recording '{}': {}", path.display(), err))
+}
+
+/// Writes queued lines until every recorder is dropped, rotating the file by size.
+async fn write_loop(path: PathBuf, max_bytes: u64, file: File, mut receiver: Receiver<String>) {
+    let mut written = file.metadata().map(|meta| meta.len()).unwrap_or_default();
+    let mut writer = BufWriter::new(file);
+
+    while let Some(line) = receiver.recv().await {
+        if let Err(err) = writeln!(writer, "{}", line) {
+            error!("Unable to write recording '{}': {}", path.display(), err);
+            continue;
+        }
+        written += line.len() as u64 + 1;
+
+        // Only flush once caught up, bursts are written together.
+        if receiver.is_empty() {
+            if let Err(err) = writer.flush() {
+                error!("Unable to write recording '{}': {}", path.display(), err);
+            }
+        }
+
+        if max_bytes > 0 && written >= max_bytes {
+            match rotate(&path, &mut writer) {
+                Ok(()) => written = 0,
+                Err(err) => error!("{}", err),
+            }
+        }
+    }
+
+    if let Err(err) = writer.flush() {
+        error!("Unable to write recording '{}': {}", path.display(), err);
+    }
+}
+
+/// Moves the current recording to `<path>.1` and continues in a new file.
+fn rotate(path: &Path, writer: &mut BufWriter<File>) -> Result<(), String> {
+    writer
+        .flush()
+        .map_err(|err| format!("unable to write recording '{}': {}", path.display(), err))?;
+
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    fs::rename(path, &rotated)
+        .map_err(|err| format!("unable to rotate recording '{}': {}", path.display(), err))?;
+
+    *writer = BufWriter::new(open(path)?);
+    info!("Rotated recording '{}'.", path.display());
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! Publishes completed candles to Redis channels, requires the `redis` feature.
+
+use crate::sink::{CandleInfo, CandleRecord, CandleSink};
+
+use cbadv::product::Candle;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Client};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+/// Maximum candles waiting to be published, the oldest are dropped beyond this.
+const QUEUE_CAPACITY: usize = 1_000;
+/// Delay before the first reconnection attempt, doubled for each following attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the delay between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Maximum time to wait for queued candles to be published when flushing.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Candles waiting to be published, shared with the publishing task.
+struct Queue {
+    /// Pending candles, oldest first.
+    candles: Mutex<VecDeque<CandleRecord>>,
+    /// Wakes the publishing task when candles are queued.
+    notify: Notify,
+    /// Whether a candle taken from the queue is still being published.
+    publishing: AtomicBool,
+    /// Signalled each time the publishing task finishes with a candle.
+    published: Condvar,
+}
+
+impl Queue {
+    /// Adds a candle to the back of the queue, dropping the oldest past the capacity.
+    fn push(&self, record: CandleRecord) {
+        let mut candles = self.candles.lock().unwrap();
+        candles.push_back(record);
+        if candles.len() > QUEUE_CAPACITY {
+            if let Some(dropped) = candles.pop_front() {
+                warn!(
+                    "Redis queue is full, dropped candle for {} ({}).",
+                    dropped.product_id, dropped.start
+                );
+            }
+        }
+    }
+
+    /// Takes the oldest candle to publish, marking it as being published.
+    fn pop(&self) -> Option<CandleRecord> {
+        let mut candles = self.candles.lock().unwrap();
+        let record = candles.pop_front();
+        self.publishing.store(record.is_some(), Ordering::SeqCst);
+        record
+    }
+
+    /// Marks the candle being published as finished, returning it to the front of
+    /// the queue to be retried unless the queue filled meanwhile.
+    fn done(&self, retry: Option<CandleRecord>) {
+        let mut candles = self.candles.lock().unwrap();
+        if let Some(record) = retry {
+            if candles.len() < QUEUE_CAPACITY {
+                candles.push_front(record);
+            }
+        }
+        self.publishing.store(false, Ordering::SeqCst);
+        self.published.notify_all();
+    }
+}
+
+/// Publishes each completed candle as JSON to `candles:<product_id>`. Publishing
+/// happens on a separate task that reconnects on its own, candles are queued while
+/// Redis is unavailable.
+pub struct RedisSink {
+    queue: Arc<Queue>,
+}
+
+impl RedisSink {
+    /// Creates the sink and spawns the task that publishes to the server at `url`.
+    pub fn new(url: &str) -> redis::RedisResult<Self> {
+        let client = Client::open(url)?;
+        let queue = Arc::new(Queue {
+            candles: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            publishing: AtomicBool::new(false),
+            published: Condvar::new(),
+        });
+
+        tokio::spawn(publish_loop(client, Arc::clone(&queue)));
+        Ok(Self { queue })
+    }
+}
+
+impl CandleSink for RedisSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        self.queue.push(CandleRecord::new(product_id, candle, info));
+        self.queue.notify.notify_one();
+    }
+
+    fn flush(&mut self) {
+        let candles = self.queue.candles.lock().unwrap();
+        let (candles, _) = self
+            .queue
+            .published
+            .wait_timeout_while(candles, FLUSH_TIMEOUT, |candles| {
+                !candles.is_empty() || self.queue.publishing.load(Ordering::SeqCst)
+            })
+            .unwrap();
+
+        if !candles.is_empty() || self.queue.publishing.load(Ordering::SeqCst) {
+            error!(
+                "Redis did not flush in time, {} candles still queued.",
+                candles.len()
+            );
+        }
+    }
+}
+
+/// Publishes queued candles in order, reconnecting with backoff whenever the
+/// connection fails.
+async fn publish_loop(client: Client, queue: Arc<Queue>) {
+    let mut conn: Option<MultiplexedConnection> = None;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let empty = queue.candles.lock().unwrap().is_empty();
+        if empty {
+            queue.notify.notified().await;
+            continue;
+        }
+
+        if conn.is_none() {
+            match client.get_multiplexed_tokio_connection().await {
+                Ok(connection) => {
+                    info!("Connected to Redis.");
+                    conn = Some(connection);
+                    backoff = INITIAL_BACKOFF;
+                }
+                Err(err) => {
+                    warn!(
+                        "Unable to connect to Redis, retrying in {}s: {}",
+                        backoff.as_secs(),
+                        err
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            }
+        }
+
+        let next = queue.pop();
+        let record = match next {
+            Some(record) => record,
+            None => continue,
+        };
+        let payload = match serde_json::to_string(&record) {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!("Unable to serialize candle for Redis: {}", err);
+                queue.done(None);
+                continue;
+            }
+        };
+
+        let channel = format!("candles:{}", record.product_id);
+        let connection = conn.as_mut().unwrap();
+        match connection.publish::<_, _, ()>(channel, payload).await {
+            Ok(()) => queue.done(None),
+            Err(err) => {
+                warn!("Redis publish failed, reconnecting: {}", err);
+                conn = None;
+
+                // Retry the candle first once reconnected.
+                queue.done(Some(record));
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Replays recorded candles through a tracker as if they arrived live, reproducing a
+//! sequence without a connection.
+//!
+//! Candles are read from the CSV files written by the tracker, `<product_id>.csv`, or
+//! from the JSON lines written by the JSON output. Sessions captured by the
+//! [`Recorder`](crate::recorder::Recorder) are replayed message by message instead.
+
+use crate::fields::OutputField;
+use crate::recorder::RecordedMessage;
+use crate::sink::CandleSink;
+use crate::TrackerHandle;
+
+use cbadv::product::Candle;
+use cbadv::websocket::MessageCallback;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Candle as written by the JSON output, other fields are ignored.
+#[derive(Deserialize, Debug)]
+struct JsonCandle {
+    product_id: String,
+    start: u64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    /// Snapshots are repeats of a candle that is recorded again once it completes.
+    #[serde(default)]
+    snapshot: bool,
+    /// Partial updates are also repeated once the candle completes.
+    #[serde(default)]
+    partial: bool,
+    /// Only written for candles flushed before they completed.
+    #[serde(default = "complete")]
+    complete: bool,
+}
+
+/// Candles are complete unless marked otherwise.
+fn complete() -> bool {
+    true
+}
+
+/// Reads the candles recorded in `path`, ordered by their start. Files ending in
+/// `.csv` hold the candles of the product they are named after, anything else is
+/// read as JSON lines.
+pub fn read_candles(path: &Path) -> Result<Vec<(String, Candle)>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut candles = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => read_csv(path, &text)?,
+        _ => read_json_lines(&text)?,
+    };
+
+    // Stable sort, candles of different products keep their recorded order.
+    candles.sort_by_key(|(_, candle)| candle.start);
+    Ok(candles)
+}
+
+/// Parses a CSV file written by the tracker, columns are found by the names in its
+/// header. The product is taken from a `product_id` column, or else the file name.
+fn read_csv(path: &Path, text: &str) -> Result<Vec<(String, Candle)>, String> {
+    let product_id = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if stem.contains('_') => {
+            return Err("aggregated timeframe files cannot be replayed".to_string())
+        }
+        Some(stem) => stem.to_string(),
+        None => return Err("unable to determine the product from the file name".to_string()),
+    };
+
+    let mut lines = text.lines().enumerate();
+    let header = match lines.next() {
+        Some((_, header)) => header,
+        None => return Ok(vec![]),
+    };
+    let names: Vec<&str> = header.split(',').map(str::trim).collect();
+    let known = |name: &str| {
+        OutputField::CSV_DEFAULT
+            .iter()
+            .chain([&OutputField::ProductId])
+            .any(|field| field.name() == name)
+    };
+    if let Some(name) = names.iter().find(|name| !known(name)) {
+        return Err(format!("line 1: unknown column '{}'", name));
+    }
+
+    // Position of the start, open, high, low, close, and volume columns.
+    let mut columns = [0; 6];
+    for (column, field) in columns.iter_mut().zip(OutputField::CSV_DEFAULT) {
+        *column = names
+            .iter()
+            .position(|name| *name == field.name())
+            .ok_or_else(|| format!("line 1: missing the '{}' column", field))?;
+    }
+    let product_column = names
+        .iter()
+        .position(|name| *name == OutputField::ProductId.name());
+
+    let mut candles = vec![];
+    for (number, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let bad_line = |err: String| format!("line {}: {}", number + 1, err);
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != names.len() {
+            return Err(bad_line(format!(
+                "expected {} fields, found {}",
+                names.len(),
+                fields.len()
+            )));
+        }
+
+        let price = |i: usize| {
+            fields[columns[i]]
+                .parse::<f64>()
+                .map_err(|err| bad_line(err.to_string()))
+        };
+        let candle = Candle {
+            start: fields[columns[0]]
+                .parse::<u64>()
+                .map_err(|err| bad_line(err.to_string()))?,
+            open: price(1)?,
+            high: price(2)?,
+            low: price(3)?,
+            close: price(4)?,
+            volume: price(5)?,
+        };
+        let product_id = match product_column {
+            Some(i) => fields[i].to_string(),
+            None => product_id.clone(),
+        };
+        candles.push((product_id, candle));
+    }
+    Ok(candles)
+}
+
+/// Parses JSON lines written by the JSON output. Snapshots, partial updates, and
+/// candles flushed in-progress on shutdown are skipped, as the CSV files leave them out.
+fn read_json_lines(text: &str) -> Result<Vec<(String, Candle)>, String> {
+    let mut candles = vec![];
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let json: JsonCandle =
+            serde_json::from_str(line).map_err(|err| format!("line {}: {}", number + 1, err))?;
+        if json.snapshot || json.partial || !json.complete {
+            continue;
+        }
+
+        let candle = Candle {
+            start: json.start,
+            open: json.open,
+            high: json.high,
+            low: json.low,
+            close: json.close,
+            volume: json.volume,
+        };
+        candles.push((json.product_id, candle));
+    }
+    Ok(candles)
+}
+
+/// Feeds `candles` through the tracker in order, returning the amount processed.
+/// With a `speed` above 0 the time between candle starts is waited, divided by the
+/// speed, so 1 replays in real-time. A `speed` of 0 replays as fast as possible.
+pub async fn replay<S: CandleSink + Send + 'static>(
+    tracker: &TrackerHandle<S>,
+    candles: Vec<(String, Candle)>,
+    speed: f64,
+) -> usize {
+    let mut processed: usize = 0;
+    let mut previous: Option<u64> = None;
+
+    for (product_id, candle) in candles {
+        if speed > 0.0 {
+            if let Some(previous) = previous {
+                let elapsed = candle.start.saturating_sub(previous);
+                if elapsed > 0 {
+                    sleep(Duration::from_secs_f64(elapsed as f64 / speed)).await;
+                }
+            }
+            previous = Some(candle.start);
+        }
+
+        if tracker.ingest(&product_id, candle) {
+            processed += 1;
+        }
+    }
+    processed
+}
+
+/// Whether `path` holds messages captured by the recorder rather than candles.
+pub fn is_recording(path: &Path) -> bool {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    text.lines()
+        .find(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str::<RecordedMessage>(line).is_ok())
+        .unwrap_or(false)
+}
+
+/// Reads the messages captured by the recorder in `path`, in the order received.
+pub fn read_messages(path: &Path) -> Result<Vec<RecordedMessage>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut messages = vec![];
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message = serde_json::from_str::<RecordedMessage>(line)
+            .map_err(|err| format!("line {}: {}", number + 1, err))?;
+        messages.push(message);
+    }
+    Ok(messages)
+}
+
+/// Passes recorded messages to the tracker as if received live, returning the amount
+/// passed. With a `speed` above 0 the time between messages being received is
+/// waited, divided by the speed. A `speed` of 0 replays as fast as possible.
+pub async fn replay_messages<S: CandleSink + Send + 'static>(
+    tracker: &TrackerHandle<S>,
+    messages: Vec<RecordedMessage>,
+    speed: f64,
+) -> usize {
+    let mut tracker = tracker.clone();
+    let mut previous: Option<u64> = None;
+    let mut replayed: usize = 0;
+
+    for recorded in messages {
+        if speed > 0.0 {
+            if let Some(previous) = previous {
+                let elapsed = recorded.received.saturating_sub(previous);
+                if elapsed > 0 {
+                    sleep(Duration::from_secs_f64(elapsed as f64 / 1_000.0 / speed)).await;
+                }
+            }
+            previous = Some(recorded.received);
+        }
+
+        tracker.message_callback(Ok(recorded.message));
+        replayed += 1;
+    }
+    replayed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_csv_columns_by_their_header() {
+        let path = Path::new("BTC-USD.csv");
+        let text = "start,open,high,low,close,volume\n300,1,3,0.5,2,10\n";
+        let candles = read_csv(path, text).unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].0, "BTC-USD");
+        assert_eq!(candles[0].1.close, 2.0);
+
+        // Selected fields in another order, with the product written per row.
+        let text = "product_id,close,volume,start,low,high,open\nETH-USD,2,10,300,0.5,3,1\n";
+        let (product_id, candle) = &read_csv(path, text).unwrap()[0];
+        assert_eq!(product_id, "ETH-USD");
+        assert_eq!(candle.start, 300);
+        assert_eq!(candle.open, 1.0);
+        assert_eq!(candle.high, 3.0);
+        assert_eq!(candle.low, 0.5);
+        assert_eq!(candle.close, 2.0);
+        assert_eq!(candle.volume, 10.0);
+    }
+
+    #[test]
+    fn rejects_csv_headers_missing_a_column() {
+        let path = Path::new("BTC-USD.csv");
+        let err = read_csv(path, "start,close\n300,2\n").unwrap_err();
+        assert_eq!(err, "line 1: missing the 'open' column");
+
+        let err = read_csv(path, "start,open,high,low,close,volume,vwap\n").unwrap_err();
+        assert_eq!(err, "line 1: unknown column 'vwap'");
+    }
+
+    #[test]
+    fn skips_json_lines_of_candles_that_had_not_completed() {
+        let text = concat!(
+            r#"{"processed":1,"product_id":"BTC-USD","start":0,"open":1,"high":1,"low":1,"close":1,"volume":1}"#,
+            "\n",
+            r#"{"processed":2,"complete":false,"product_id":"BTC-USD","start":300,"open":1,"high":1,"low":1,"close":1,"volume":1}"#,
+            "\n",
+        );
+        let candles = read_json_lines(text).unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].1.start, 0);
+    }
+}
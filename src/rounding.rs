@@ -0,0 +1,86 @@
+//! Rounds the candles passed to sinks, the tracker keeps the raw values.
+
+use cbadv::product::Candle;
+use std::collections::HashMap;
+
+/// Decimals that the prices and volume of a product are rounded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    /// Decimals of the open, high, low, and close.
+    pub price: u32,
+    /// Decimals of the volume.
+    pub volume: u32,
+}
+
+impl Precision {
+    /// Precision of a product from the increments of its quote and base currencies,
+    /// such as `0.01` and `0.00000001`. `None` if either is not a decimal number.
+    pub fn from_increments(quote_increment: &str, base_increment: &str) -> Option<Self> {
+        Some(Self {
+            price: decimals_of(quote_increment)?,
+            volume: decimals_of(base_increment)?,
+        })
+    }
+}
+
+/// Decimals needed to represent an increment, ignoring trailing zeros.
+pub fn decimals_of(increment: &str) -> Option<u32> {
+    let increment = increment.trim();
+    increment.parse::<f64>().ok().filter(|i| *i > 0.0)?;
+    let decimals = match increment.split_once('.') {
+        Some((_, fraction)) => fraction.trim_end_matches('0').len(),
+        None => 0,
+    };
+    u32::try_from(decimals).ok()
+}
+
+/// Rounds a value to `decimals` places.
+fn round(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals.min(15) as i32);
+    (value * scale).round() / scale
+}
+
+/// Decides the precision the candles of each product are recorded with.
+#[derive(Debug, Clone, Default)]
+pub struct Rounding {
+    /// Decimals of products without their own precision, `None` leaves them as is.
+    default: Option<u32>,
+    /// Precision of specific products, such as from their increments.
+    products: HashMap<String, Precision>,
+}
+
+impl Rounding {
+    /// Rounds the candles of `products` to their precision and the rest to `default`
+    /// decimals, if set.
+    pub fn new(default: Option<u32>, products: HashMap<String, Precision>) -> Self {
+        Self { default, products }
+    }
+
+    /// Whether no candle is rounded.
+    pub fn is_disabled(&self) -> bool {
+        self.default.is_none() && self.products.is_empty()
+    }
+
+    /// Rounded copy of a candle, `None` if the product is not rounded.
+    pub fn round(&self, product_id: &str, candle: &Candle) -> Option<Candle> {
+        let precision = match self.products.get(product_id) {
+            Some(precision) => *precision,
+            None => {
+                let decimals = self.default?;
+                Precision {
+                    price: decimals,
+                    volume: decimals,
+                }
+            }
+        };
+
+        Some(Candle {
+            start: candle.start,
+            open: round(candle.open, precision.price),
+            high: round(candle.high, precision.price),
+            low: round(candle.low, precision.price),
+            close: round(candle.close, precision.price),
+            volume: round(candle.volume, precision.volume),
+        })
+    }
+}
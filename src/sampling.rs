@@ -0,0 +1,78 @@
+//! Downsamples the completed candles passed to the sink, for products that only need
+//! a coarse view. Sampled out candles still update the indicators and aggregators.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// How often completed candles of a product are passed to the sink.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Sampling {
+    /// Passes every Kth completed candle, 0 or 1 passes all of them.
+    pub every: usize,
+    /// Passes at most one completed candle per this many seconds of wall-clock time,
+    /// such as 60 for one a minute. 0 disables it.
+    pub interval: u64,
+}
+
+impl Sampling {
+    /// Whether every candle is passed.
+    pub fn is_disabled(&self) -> bool {
+        self.every <= 1 && self.interval == 0
+    }
+}
+
+/// Progress of a product towards its next passed candle.
+#[derive(Debug, Default)]
+struct Progress {
+    /// Completed candles seen since the last passed one.
+    skipped: usize,
+    /// Wall-clock interval the last passed candle fell within.
+    last_interval: Option<u64>,
+}
+
+/// Decides which completed candles of each product are passed to the sink.
+#[derive(Debug, Default)]
+pub struct Sampler {
+    /// Sampling of products without their own.
+    default: Sampling,
+    /// Sampling of specific products, replacing the default.
+    products: HashMap<String, Sampling>,
+    /// Progress of each product.
+    progress: HashMap<String, Progress>,
+}
+
+impl Sampler {
+    /// Creates a sampler using `default` for every product not in `products`.
+    pub fn new(default: Sampling, products: HashMap<String, Sampling>) -> Self {
+        Self {
+            default,
+            products,
+            progress: HashMap::new(),
+        }
+    }
+
+    /// Whether a completed candle of the product, completing at Unix time `now`, is
+    /// passed to the sink. The first candle of each product is always passed.
+    pub fn keep(&mut self, product_id: &str, now: u64) -> bool {
+        let sampling = self.products.get(product_id).unwrap_or(&self.default);
+        if sampling.is_disabled() {
+            return true;
+        }
+
+        let progress = self.progress.entry(product_id.to_string()).or_default();
+        let first = progress.last_interval.is_none();
+        let due_count = sampling.every <= 1 || progress.skipped + 1 >= sampling.every;
+        let interval = now.checked_div(sampling.interval).unwrap_or(now);
+        let due_time = sampling.interval == 0 || progress.last_interval != Some(interval);
+
+        if first || (due_count && due_time) {
+            progress.skipped = 0;
+            progress.last_interval = Some(interval);
+            true
+        } else {
+            progress.skipped += 1;
+            false
+        }
+    }
+}
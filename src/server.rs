@@ -0,0 +1,135 @@
+//! Re-broadcasts completed candles to local WebSocket clients.
+//!
+//! Clients receive every candle until they send a subscription, after which only
+//! candles for the listed products are sent. An empty list subscribes to all.
+//!
+//! ```json
+//! {"type": "subscribe", "product_ids": ["BTC-USD", "ETH-USD"]}
+//! ```
+
+use crate::sink::{CandleInfo, CandleRecord, CandleSink};
+
+use cbadv::product::Candle;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::{info, warn};
+
+/// Candles buffered for each client, slow clients skip candles beyond this.
+const CLIENT_BUFFER: usize = 256;
+
+/// Candle serialized once and shared between all clients.
+#[derive(Debug)]
+struct Broadcast {
+    /// Product the candle belongs to, used to filter subscriptions.
+    product_id: String,
+    /// Candle serialized as JSON.
+    json: String,
+}
+
+/// Requests sent by clients.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Request {
+    /// Only receive candles for these products, empty for all.
+    Subscribe { product_ids: Vec<String> },
+}
+
+/// Sink that broadcasts completed candles to the WebSocket server clients.
+#[derive(Clone)]
+pub struct BroadcastSink {
+    sender: broadcast::Sender<Arc<Broadcast>>,
+}
+
+impl CandleSink for BroadcastSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        let json = match serde_json::to_string(&CandleRecord::new(product_id, candle, info)) {
+            Ok(json) => json,
+            Err(_) => return,
+        };
+
+        // Errors only when there are no clients connected.
+        let _ = self.sender.send(Arc::new(Broadcast {
+            product_id: product_id.to_string(),
+            json,
+        }));
+    }
+}
+
+/// Starts a WebSocket server on `addr`, returning the sink that feeds it.
+pub async fn serve_ws(addr: SocketAddr) -> std::io::Result<BroadcastSink> {
+    let listener = TcpListener::bind(addr).await?;
+    let (sender, _) = broadcast::channel(CLIENT_BUFFER);
+
+    let clients = sender.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    tokio::spawn(handle_client(stream, peer, clients.subscribe()));
+                }
+                Err(err) => warn!("Unable to accept WebSocket client: {}", err),
+            }
+        }
+    });
+
+    info!("Serving candles on ws://{}.", addr);
+    Ok(BroadcastSink { sender })
+}
+
+/// Sends candles to a single client until it disconnects.
+async fn handle_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    mut candles: broadcast::Receiver<Arc<Broadcast>>,
+) {
+    let socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            warn!("WebSocket handshake with {} failed: {}", peer, err);
+            return;
+        }
+    };
+    let (mut writer, mut reader) = socket.split();
+
+    // Products the client is subscribed to, empty for all.
+    let mut products: HashSet<String> = HashSet::new();
+
+    loop {
+        tokio::select! {
+            incoming = reader.next() => match incoming {
+                Some(Ok(WsMessage::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(Request::Subscribe { product_ids }) => {
+                        products = product_ids.into_iter().collect();
+                    }
+                    Err(err) => warn!("Invalid request from {}: {}", peer, err),
+                },
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => (),
+            },
+            candle = candles.recv() => match candle {
+                Ok(candle) => {
+                    if !products.is_empty() && !products.contains(&candle.product_id) {
+                        continue;
+                    }
+                    if writer.send(WsMessage::Text(candle.json.clone())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket client {} is too slow, skipped {} candles.", peer, skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
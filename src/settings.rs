@@ -0,0 +1,855 @@
+//! Watcher specific configuration, extends the cbadv configuration file with a
+//! `[watcher]` section.
+
+use crate::aggregator::Timeframe;
+use crate::alerts::Thresholds;
+use crate::durability::SyncPolicy;
+use crate::fields::OutputField;
+use crate::granularity::Granularity;
+use crate::jitter::Jitter;
+use crate::sampling::Sampling;
+use crate::stats::DEFAULT_LATENCY_BUCKETS;
+use crate::template::Template;
+use crate::validation::CandleRules;
+use cbadv::config::{CoinbaseConfig, ConfigFile};
+use cbadv::{rest, websocket};
+use chrono_tz::Tz;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Prefix of the environment variables that override settings.
+pub const ENV_PREFIX: &str = "CW_";
+
+/// Environment variables seen by [`WatcherSettings::apply_env`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvOverrides {
+    /// Names of the variables that overrode a setting.
+    pub applied: Vec<String>,
+    /// Names of the `CW_` variables that name no setting, these are ignored.
+    pub ignored: Vec<String>,
+}
+
+/// Configuration file containing the Coinbase credentials and watcher settings.
+#[derive(Deserialize, Debug)]
+pub struct WatcherConfig {
+    /// Coinbase API credentials, shared with cbadv. Replaced by the credentials of the
+    /// profile once one is selected.
+    pub coinbase: CoinbaseConfig,
+    /// Additional named accounts that can be selected instead of `coinbase`.
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// Settings for the watcher, defaults are used if the section is absent.
+    #[serde(default)]
+    pub watcher: WatcherSettings,
+}
+
+impl WatcherConfig {
+    /// Uses the credentials of the profile called `name` for every client created from
+    /// the configuration. Errors if no profile has that name.
+    pub fn select_profile(&mut self, name: &str) -> Result<(), String> {
+        let index = match self.profiles.iter().position(|p| p.name == name) {
+            Some(index) => index,
+            None => {
+                let names: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+                return Err(if names.is_empty() {
+                    format!("unknown profile '{}', no profiles are configured", name)
+                } else {
+                    format!(
+                        "unknown profile '{}', expected one of: {}",
+                        name,
+                        names.join(", ")
+                    )
+                });
+            }
+        };
+
+        self.coinbase = self.profiles.remove(index).coinbase;
+        Ok(())
+    }
+
+    /// Creates a REST client, pointed at `rest_url` instead of Coinbase when set.
+    pub fn rest_client(&self) -> rest::Client {
+        let mut client = rest::from_config(self);
+        if let Some(url) = &self.watcher.rest_url {
+            client.set_base_url(url);
+        }
+        client
+    }
+
+    /// Creates a WebSocket client, pointed at `ws_url` instead of Coinbase when set.
+    pub fn websocket_client(&self) -> websocket::Client {
+        let mut client = websocket::from_config(self);
+        if let Some(url) = &self.watcher.ws_url {
+            client.set_base_url(url);
+        }
+        client
+    }
+}
+
+impl ConfigFile for WatcherConfig {
+    fn coinbase(&self) -> &CoinbaseConfig {
+        &self.coinbase
+    }
+}
+
+/// Named set of Coinbase credentials, such as a second account with its own rate
+/// limits.
+#[derive(Deserialize, Debug)]
+pub struct Profile {
+    /// Name the profile is selected by with `--profile`.
+    pub name: String,
+    /// Coinbase API credentials of the account.
+    pub coinbase: CoinbaseConfig,
+}
+
+/// Settings that control which products are watched and how.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WatcherSettings {
+    /// Quote currencies of products to watch, empty watches every product.
+    pub quote_currencies: Vec<String>,
+    /// Statuses of products to watch, such as `online` or `delisted`. Products with
+    /// trading disabled have the status `disabled`. Empty watches any status.
+    pub product_statuses: Vec<String>,
+    /// Only these product IDs are watched when set.
+    pub products_allow: Option<Vec<String>>,
+    /// Product IDs that are never watched.
+    pub products_deny: Option<Vec<String>>,
+    /// Products requested from the REST API per page while discovering products.
+    pub product_page_size: u32,
+    /// Attempts made to fetch each page of products before giving up.
+    pub product_fetch_attempts: u32,
+    /// REST requests made per second on average across discovery, warmup, and
+    /// backfill, 0 disables the limit.
+    pub rest_requests_per_second: f64,
+    /// REST requests that can be made at once before the average applies.
+    pub rest_burst: u32,
+    /// Upper bound for the amount of products watched.
+    pub max_products: Option<usize>,
+    /// Safety cap on the products tracked, however they were obtained, since each holds
+    /// candles and indicator state. `None` disables it.
+    pub max_tracked_products: Option<usize>,
+    /// What happens when more products than `max_tracked_products` would be tracked.
+    pub on_max_tracked_products: TooManyProducts,
+    /// What happens when no products match the filters.
+    pub on_empty_products: EmptyProducts,
+    /// Seconds between attempts to discover products while waiting for them.
+    pub empty_products_interval: u64,
+    /// Granularity of the candles subscribed to, the WebSocket only supports five minutes.
+    pub granularity: Granularity,
+    /// Higher timeframes to aggregate completed candles into.
+    pub timeframes: Vec<Timeframe>,
+    /// Whether a daily OHLC bar is built for each product, the same as the `1d` timeframe.
+    pub daily_bar: bool,
+    /// Minutes of historic candles to seed each product with on startup, 0 disables.
+    pub warmup_minutes: u64,
+    /// Directory to append recorded candles to, one CSV file for each product and
+    /// series such as `BTC-USD.csv`.
+    pub csv_dir: Option<PathBuf>,
+    /// SQLite database to store completed candles in, requires the `sqlite` feature.
+    pub sqlite_path: Option<PathBuf>,
+    /// Directory to export completed candles to as daily Parquet files, requires the
+    /// `parquet` feature.
+    pub parquet_dir: Option<PathBuf>,
+    /// What the CSV and Parquet output does with buffered candles every
+    /// `file_flush_interval`, they are always written and synced on shutdown.
+    pub file_sync: SyncPolicy,
+    /// Seconds between writes of the buffered candles to files, 0 writes CSV rows as
+    /// they are recorded and Parquet files only when the day rolls over.
+    pub file_flush_interval: u64,
+    /// Seconds between printed summaries of throughput and lag, 0 disables.
+    pub summary_interval: u64,
+    /// Upper bounds, in seconds, of the buckets measuring how long after its interval
+    /// ends each candle completes.
+    pub latency_buckets: Vec<f64>,
+    /// Seconds between snapshots of the in-progress candle of each product, 0 disables.
+    pub snapshot_interval: u64,
+    /// Whether updates of the in-progress candles are forwarded to the sinks as partial
+    /// candles as they arrive.
+    pub emit_partial: bool,
+    /// Seconds between the partial candles forwarded for each product, 0 forwards every
+    /// update.
+    pub partial_interval: u64,
+    /// Completed closes averaged by the simple moving average, 0 disables it.
+    pub sma_period: usize,
+    /// Completed candles retained in memory for each product, 0 disables the history.
+    pub history_len: usize,
+    /// Completed candle starts remembered for each product to ignore duplicate updates,
+    /// 0 disables deduplication.
+    pub dedupe_window: usize,
+    /// Whether the 12/26/9 MACD of completed closes is calculated.
+    pub macd: bool,
+    /// Changes used by the RSI of completed closes, 0 disables it.
+    pub rsi_period: usize,
+    /// Whether the VWAP of completed candles is calculated, starting over each day.
+    pub vwap: bool,
+    /// Whether completed candles carry their typical price, median price, and weighted
+    /// close.
+    pub emit_derived: bool,
+    /// Whether a Heikin-Ashi candle is recorded after each completed candle, as a
+    /// separate series of the product.
+    pub heikin_ashi: bool,
+    /// Completed candles averaged by the ATR (Wilder smoothing), 0 disables it.
+    pub atr_period: usize,
+    /// Completed closes the Bollinger Bands are calculated over, 0 disables them.
+    pub bollinger_period: usize,
+    /// Standard deviations between the middle and outer Bollinger Bands.
+    pub bollinger_k: f64,
+    /// Whether doji, hammer, and engulfing patterns are detected in completed candles.
+    pub patterns: bool,
+    /// Trailing completed candles averaged for volume spike detection, 0 disables it.
+    pub volume_spike_window: usize,
+    /// Multiple of the trailing mean volume that is reported as a spike.
+    pub volume_spike_multiplier: f64,
+    /// Percent change of a completed close from the previous close, in either direction,
+    /// beyond which the candle is reported as a mover. `None` disables it.
+    pub movers_threshold: Option<f64>,
+    /// Previous completed candles whose high and low a candle must exceed to be reported
+    /// as a new high or low, 0 disables it.
+    pub extremes_period: usize,
+    /// How often completed candles are output, every candle still updates the indicators.
+    pub sampling: Sampling,
+    /// Sampling of specific products, replacing `sampling`.
+    pub product_sampling: HashMap<String, Sampling>,
+    /// Rules that candle updates must satisfy, malformed updates are rejected.
+    pub candle_rules: CandleRules,
+    /// Decimals that the prices and volume of recorded candles are rounded to, `None`
+    /// records them as received. Indicators always use the received values.
+    pub round_decimals: Option<u32>,
+    /// Whether recorded candles are rounded to the quote and base increments of their
+    /// product instead, falling back to `round_decimals` when they are unknown.
+    pub round_to_increment: bool,
+    /// Price thresholds for each product that alert when a completed close crosses them.
+    pub alerts: HashMap<String, Thresholds>,
+    /// Redis server that completed candles are published to, requires the `redis` feature.
+    pub redis_url: Option<String>,
+    /// InfluxDB v2 server that completed candles are written to, requires the `influx`
+    /// feature.
+    pub influx_url: Option<String>,
+    /// Organization owning the InfluxDB bucket.
+    pub influx_org: String,
+    /// InfluxDB bucket the candles are written to.
+    pub influx_bucket: String,
+    /// InfluxDB API token with write access to the bucket.
+    pub influx_token: Option<String>,
+    /// Comma separated Kafka brokers that completed candles are produced to, requires
+    /// the `kafka` feature.
+    pub kafka_brokers: Option<String>,
+    /// Kafka topic that completed candles are produced to.
+    pub kafka_topic: String,
+    /// NATS server that completed candles are published to, requires the `nats` feature.
+    pub nats_url: Option<String>,
+    /// Subjects are `<prefix>.<product_id>`.
+    pub nats_subject_prefix: String,
+    /// JetStream stream created over the subjects for durability, `None` publishes to
+    /// plain subjects.
+    pub nats_stream: Option<String>,
+    /// MQTT broker that completed candles are published to, requires the `mqtt` feature.
+    pub mqtt_host: Option<String>,
+    /// Port of the MQTT broker.
+    pub mqtt_port: u16,
+    /// Client ID of the MQTT connection, unique between watchers on the same broker.
+    pub mqtt_client_id: String,
+    /// Username for the MQTT broker, if it requires one.
+    pub mqtt_username: Option<String>,
+    /// Password for the MQTT broker, used along with the username.
+    pub mqtt_password: Option<String>,
+    /// Topics are `<prefix>/<product_id>`.
+    pub mqtt_topic_prefix: String,
+    /// Quality of service of the published candles: 0, 1, or 2.
+    pub mqtt_qos: u8,
+    /// URL that each completed candle is POSTed to as JSON.
+    pub webhook_url: Option<String>,
+    /// REST API used instead of Coinbase, such as the sandbox or a mock server.
+    pub rest_url: Option<String>,
+    /// WebSocket feed used instead of Coinbase, such as the sandbox or a mock server.
+    pub ws_url: Option<String>,
+    /// Address of a local WebSocket server that re-broadcasts completed candles.
+    pub serve_ws: Option<SocketAddr>,
+    /// Log level used when `RUST_LOG` is not set.
+    pub log_level: String,
+    /// How completed candles are printed to stdout.
+    pub output_format: OutputFormat,
+    /// Fields of each candle written by the CSV and JSON output, in order. Every field
+    /// is written if empty.
+    pub output_fields: Vec<OutputField>,
+    /// Format of the text line printed for each candle, such as
+    /// `"{product_id} ({start}): {close}"`.
+    pub log_template: Option<String>,
+    /// Color the text output by the direction of each close, only when stdout is a
+    /// terminal.
+    pub color: bool,
+    /// IANA timezone, such as `America/New_York`, that days start in for daily bars
+    /// and the VWAP, and that candle starts and log times are shown in. `None` uses
+    /// UTC and shows starts as Unix times.
+    pub timezone: Option<Tz>,
+    /// Port that Prometheus metrics are served on, requires the `metrics` feature.
+    pub metrics_port: Option<u16>,
+    /// Port that the `/healthz` and `/readyz` probes are served on.
+    pub health_port: Option<u16>,
+    /// Unix socket answering `dump` and `stats` commands for debugging, `None` disables it.
+    pub control_socket: Option<PathBuf>,
+    /// Seconds without a newer candle before a product is reported as stale, 0 disables.
+    pub stale_after: u64,
+    /// Seconds after a candles interval ends before it completes without waiting for
+    /// the next candle, `None` waits for the next candle.
+    pub finalize_grace: Option<u64>,
+    /// Seconds without any message, including heartbeats, before reconnecting, 0 disables.
+    pub heartbeat_timeout: u64,
+    /// Whether the HEARTBEATS channel is subscribed to alongside the candles.
+    pub heartbeats: bool,
+    /// How the delay between reconnection attempts is randomized.
+    pub reconnect_jitter: Jitter,
+    /// Whether the in-progress candles are refreshed from the REST API before
+    /// reconnecting, since their updates were missed while disconnected.
+    pub refresh_on_reconnect: bool,
+    /// File in-progress candles and indicators are saved to on shutdown and restored
+    /// from on startup, `None` disables it.
+    pub state_path: Option<PathBuf>,
+    /// File every received WebSocket message is recorded to for later replay, `None`
+    /// disables it.
+    pub record_path: Option<PathBuf>,
+    /// Bytes the recording grows to before it is rotated to `<record_path>.1`, 0
+    /// never rotates.
+    pub record_max_bytes: u64,
+    /// Groups the products are split into, each with its own tracker, sinks and
+    /// WebSocket connection so a slow group does not stall the others.
+    pub partitions: usize,
+    /// Most products subscribed to over a single WebSocket connection, each partition
+    /// opens as many connections as needed. `None` uses one connection.
+    pub shard_size: Option<usize>,
+    /// Most products subscribed to by a single subscribe message on a connection.
+    /// `None` subscribes to every product of the connection in one message.
+    pub subscribe_batch_size: Option<usize>,
+    /// Milliseconds between the subscribe messages of a batched subscription.
+    pub subscribe_batch_delay_ms: u64,
+}
+
+impl Default for WatcherSettings {
+    fn default() -> Self {
+        Self {
+            quote_currencies: vec!["USD".to_string()],
+            product_statuses: vec!["online".to_string()],
+            products_allow: None,
+            products_deny: None,
+            product_page_size: 250,
+            product_fetch_attempts: 5,
+            rest_requests_per_second: 10.0,
+            rest_burst: 10,
+            max_products: None,
+            max_tracked_products: None,
+            on_max_tracked_products: TooManyProducts::Truncate,
+            on_empty_products: EmptyProducts::Error,
+            empty_products_interval: 60,
+            granularity: Granularity::default(),
+            timeframes: vec![],
+            daily_bar: false,
+            warmup_minutes: 0,
+            csv_dir: None,
+            sqlite_path: None,
+            parquet_dir: None,
+            file_sync: SyncPolicy::Flush,
+            file_flush_interval: 0,
+            summary_interval: 30,
+            latency_buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            snapshot_interval: 0,
+            emit_partial: false,
+            partial_interval: 1,
+            sma_period: 0,
+            history_len: 0,
+            dedupe_window: 120,
+            macd: false,
+            rsi_period: 0,
+            vwap: false,
+            emit_derived: false,
+            heikin_ashi: false,
+            atr_period: 0,
+            bollinger_period: 0,
+            bollinger_k: 2.0,
+            patterns: false,
+            volume_spike_window: 0,
+            volume_spike_multiplier: 3.0,
+            movers_threshold: None,
+            extremes_period: 0,
+            sampling: Sampling::default(),
+            product_sampling: HashMap::new(),
+            candle_rules: CandleRules::default(),
+            round_decimals: None,
+            round_to_increment: false,
+            alerts: HashMap::new(),
+            redis_url: None,
+            influx_url: None,
+            influx_org: String::new(),
+            influx_bucket: "candles".to_string(),
+            influx_token: None,
+            kafka_brokers: None,
+            kafka_topic: "candles".to_string(),
+            nats_url: None,
+            nats_subject_prefix: "candles".to_string(),
+            nats_stream: None,
+            mqtt_host: None,
+            mqtt_port: 1883,
+            mqtt_client_id: "candle-watcher".to_string(),
+            mqtt_username: None,
+            mqtt_password: None,
+            mqtt_topic_prefix: "candles".to_string(),
+            mqtt_qos: 1,
+            webhook_url: None,
+            rest_url: None,
+            ws_url: None,
+            serve_ws: None,
+            log_level: "info".to_string(),
+            output_format: OutputFormat::Text,
+            output_fields: vec![],
+            log_template: None,
+            color: true,
+            timezone: None,
+            metrics_port: None,
+            health_port: None,
+            control_socket: None,
+            stale_after: 0,
+            finalize_grace: None,
+            heartbeat_timeout: 15,
+            heartbeats: true,
+            reconnect_jitter: Jitter::default(),
+            refresh_on_reconnect: true,
+            state_path: None,
+            record_path: None,
+            record_max_bytes: 100 * 1024 * 1024,
+            partitions: 1,
+            shard_size: None,
+            subscribe_batch_size: None,
+            subscribe_batch_delay_ms: 250,
+        }
+    }
+}
+
+impl WatcherSettings {
+    /// Overrides settings with the `CW_` prefixed variables in `vars`, each named after
+    /// its setting in uppercase such as `CW_WEBHOOK_URL`. Lists are comma separated,
+    /// tables such as `CW_SAMPLING` are JSON. Returns the names of the variables applied
+    /// and of those ignored for naming no setting, a value that fails to parse is an
+    /// error.
+    pub fn apply_env<I>(&mut self, vars: I) -> Result<EnvOverrides, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut overrides = EnvOverrides::default();
+        for (name, value) in vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) => key.to_lowercase(),
+                None => continue,
+            };
+
+            let known = self
+                .set(&key, &value)
+                .map_err(|err| format!("environment variable {}: {}", name, err))?;
+            match known {
+                true => overrides.applied.push(name),
+                false => overrides.ignored.push(name),
+            }
+        }
+        Ok(overrides)
+    }
+
+    /// Sets a single setting from the text of an environment variable, returning
+    /// whether a setting has that name.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "quote_currencies" => self.quote_currencies = list(value),
+            "product_statuses" => self.product_statuses = list(value),
+            "products_allow" => self.products_allow = Some(list(value)),
+            "products_deny" => self.products_deny = Some(list(value)),
+            "product_page_size" => self.product_page_size = parse(value)?,
+            "product_fetch_attempts" => self.product_fetch_attempts = parse(value)?,
+            "rest_requests_per_second" => self.rest_requests_per_second = parse(value)?,
+            "rest_burst" => self.rest_burst = parse(value)?,
+            "max_products" => self.max_products = Some(parse(value)?),
+            "max_tracked_products" => self.max_tracked_products = Some(parse(value)?),
+            "on_max_tracked_products" => self.on_max_tracked_products = variant(value)?,
+            "on_empty_products" => self.on_empty_products = variant(value)?,
+            "empty_products_interval" => self.empty_products_interval = parse(value)?,
+            "granularity" => self.granularity = variant(value)?,
+            "timeframes" => {
+                self.timeframes = list(value)
+                    .iter()
+                    .map(|timeframe| variant(timeframe))
+                    .collect::<Result<_, _>>()?
+            }
+            "daily_bar" => self.daily_bar = parse(value)?,
+            "warmup_minutes" => self.warmup_minutes = parse(value)?,
+            "csv_dir" => self.csv_dir = Some(PathBuf::from(value)),
+            "sqlite_path" => self.sqlite_path = Some(PathBuf::from(value)),
+            "parquet_dir" => self.parquet_dir = Some(PathBuf::from(value)),
+            "file_sync" => self.file_sync = variant(value)?,
+            "file_flush_interval" => self.file_flush_interval = parse(value)?,
+            "summary_interval" => self.summary_interval = parse(value)?,
+            "latency_buckets" => {
+                self.latency_buckets = list(value)
+                    .iter()
+                    .map(|bound| parse(bound))
+                    .collect::<Result<_, _>>()?
+            }
+            "snapshot_interval" => self.snapshot_interval = parse(value)?,
+            "emit_partial" => self.emit_partial = parse(value)?,
+            "partial_interval" => self.partial_interval = parse(value)?,
+            "sma_period" => self.sma_period = parse(value)?,
+            "history_len" => self.history_len = parse(value)?,
+            "dedupe_window" => self.dedupe_window = parse(value)?,
+            "macd" => self.macd = parse(value)?,
+            "rsi_period" => self.rsi_period = parse(value)?,
+            "vwap" => self.vwap = parse(value)?,
+            "atr_period" => self.atr_period = parse(value)?,
+            "bollinger_period" => self.bollinger_period = parse(value)?,
+            "bollinger_k" => self.bollinger_k = parse(value)?,
+            "patterns" => self.patterns = parse(value)?,
+            "volume_spike_window" => self.volume_spike_window = parse(value)?,
+            "volume_spike_multiplier" => self.volume_spike_multiplier = parse(value)?,
+            "movers_threshold" => self.movers_threshold = Some(parse(value)?),
+            "extremes_period" => self.extremes_period = parse(value)?,
+            "sampling" => self.sampling = json(value)?,
+            "product_sampling" => self.product_sampling = json(value)?,
+            "candle_rules" => self.candle_rules = json(value)?,
+            "alerts" => self.alerts = json(value)?,
+            "redis_url" => self.redis_url = Some(value.to_string()),
+            "influx_url" => self.influx_url = Some(value.to_string()),
+            "influx_org" => self.influx_org = value.to_string(),
+            "influx_bucket" => self.influx_bucket = value.to_string(),
+            "influx_token" => self.influx_token = Some(value.to_string()),
+            "kafka_brokers" => self.kafka_brokers = Some(value.to_string()),
+            "kafka_topic" => self.kafka_topic = value.to_string(),
+            "nats_url" => self.nats_url = Some(value.to_string()),
+            "nats_subject_prefix" => self.nats_subject_prefix = value.to_string(),
+            "nats_stream" => self.nats_stream = Some(value.to_string()),
+            "mqtt_host" => self.mqtt_host = Some(value.to_string()),
+            "mqtt_port" => self.mqtt_port = parse(value)?,
+            "mqtt_client_id" => self.mqtt_client_id = value.to_string(),
+            "mqtt_username" => self.mqtt_username = Some(value.to_string()),
+            "mqtt_password" => self.mqtt_password = Some(value.to_string()),
+            "mqtt_topic_prefix" => self.mqtt_topic_prefix = value.to_string(),
+            "mqtt_qos" => self.mqtt_qos = parse(value)?,
+            "webhook_url" => self.webhook_url = Some(value.to_string()),
+            "rest_url" => self.rest_url = Some(value.to_string()),
+            "ws_url" => self.ws_url = Some(value.to_string()),
+            "serve_ws" => self.serve_ws = Some(parse(value)?),
+            "log_level" => self.log_level = value.to_string(),
+            "output_format" => self.output_format = value.parse()?,
+            "output_fields" => {
+                self.output_fields = list(value)
+                    .iter()
+                    .map(|field| variant(field))
+                    .collect::<Result<_, _>>()?
+            }
+            "log_template" => self.log_template = Some(value.to_string()),
+            "round_decimals" => self.round_decimals = Some(parse(value)?),
+            "round_to_increment" => self.round_to_increment = parse(value)?,
+            "emit_derived" => self.emit_derived = parse(value)?,
+            "heikin_ashi" => self.heikin_ashi = parse(value)?,
+            "color" => self.color = parse(value)?,
+            "timezone" => self.timezone = Some(parse(value)?),
+            "metrics_port" => self.metrics_port = Some(parse(value)?),
+            "health_port" => self.health_port = Some(parse(value)?),
+            "control_socket" => self.control_socket = Some(PathBuf::from(value)),
+            "stale_after" => self.stale_after = parse(value)?,
+            "finalize_grace" => self.finalize_grace = Some(parse(value)?),
+            "heartbeat_timeout" => self.heartbeat_timeout = parse(value)?,
+            "heartbeats" => self.heartbeats = parse(value)?,
+            "reconnect_jitter" => self.reconnect_jitter = variant(value)?,
+            "refresh_on_reconnect" => self.refresh_on_reconnect = parse(value)?,
+            "state_path" => self.state_path = Some(PathBuf::from(value)),
+            "record_path" => self.record_path = Some(PathBuf::from(value)),
+            "record_max_bytes" => self.record_max_bytes = parse(value)?,
+            "partitions" => self.partitions = parse(value)?,
+            "shard_size" => self.shard_size = Some(parse(value)?),
+            "subscribe_batch_size" => self.subscribe_batch_size = Some(parse(value)?),
+            "subscribe_batch_delay_ms" => self.subscribe_batch_delay_ms = parse(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Checks the settings are supported, describing the first that is not.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.granularity.websocket_supported() {
+            let mut err = format!(
+                "granularity '{}' is not provided by the WebSocket, only '{}' is supported",
+                self.granularity,
+                Granularity::FiveMinute
+            );
+            if let Some(timeframe) = self.granularity.timeframe() {
+                err.push_str(&format!(
+                    ", use '{}' with `timeframes = [\"{}\"]` to aggregate it into '{}' candles",
+                    Granularity::FiveMinute,
+                    timeframe,
+                    self.granularity
+                ));
+            }
+            return Err(err);
+        }
+
+        if self.product_page_size == 0 {
+            return Err("product_page_size must be greater than 0".to_string());
+        }
+
+        for (i, field) in self.output_fields.iter().enumerate() {
+            if self.output_fields[..i].contains(field) {
+                return Err(format!("output field '{}' is listed more than once", field));
+            }
+        }
+
+        if self.mqtt_qos > 2 {
+            return Err(format!(
+                "mqtt_qos must be 0, 1, or 2, got {}",
+                self.mqtt_qos
+            ));
+        }
+
+        if let Some(url) = &self.rest_url {
+            validate_url("rest_url", url, &["http", "https"])?;
+        }
+        if let Some(url) = &self.ws_url {
+            validate_url("ws_url", url, &["ws", "wss"])?;
+        }
+        if let Some(url) = &self.influx_url {
+            validate_url("influx_url", url, &["http", "https"])?;
+        }
+
+        if self.on_empty_products == EmptyProducts::Wait && self.empty_products_interval == 0 {
+            return Err("empty_products_interval must be greater than 0".to_string());
+        }
+
+        if !self.rest_requests_per_second.is_finite() || self.rest_requests_per_second < 0.0 {
+            return Err("rest_requests_per_second must be 0 or greater".to_string());
+        }
+
+        if self.volume_spike_window > 0 && self.volume_spike_multiplier <= 0.0 {
+            return Err("volume_spike_multiplier must be greater than 0".to_string());
+        }
+
+        if self
+            .movers_threshold
+            .is_some_and(|threshold| threshold <= 0.0)
+        {
+            return Err("movers_threshold must be greater than 0".to_string());
+        }
+
+        if self
+            .latency_buckets
+            .iter()
+            .any(|bound| !bound.is_finite() || *bound <= 0.0)
+        {
+            return Err("latency_buckets must be greater than 0".to_string());
+        }
+
+        if self.shard_size == Some(0) {
+            return Err("shard_size must be greater than 0".to_string());
+        }
+        if self.subscribe_batch_size == Some(0) {
+            return Err("subscribe_batch_size must be greater than 0".to_string());
+        }
+
+        if self.bollinger_period > 0 && self.bollinger_k <= 0.0 {
+            return Err("bollinger_k must be greater than 0".to_string());
+        }
+
+        if self.partitions == 0 {
+            return Err("partitions must be greater than 0".to_string());
+        }
+
+        if let Some(template) = &self.log_template {
+            template.parse::<Template>()?;
+        }
+
+        let interval = self.granularity.seconds();
+        for timeframe in &self.timeframes {
+            if timeframe.seconds() % interval != 0 {
+                return Err(format!(
+                    "timeframe '{}' is not a multiple of the '{}' granularity",
+                    timeframe, self.granularity
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits a comma separated list, ignoring empty entries.
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses a value, such as a number or address, from text.
+fn parse<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|err| format!("invalid value '{}': {}", value, err))
+}
+
+/// Parses a variant of an enum by the name used in the configuration file.
+fn variant<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    T::deserialize(StrDeserializer::<ValueError>::new(value.trim()))
+        .map_err(|err| format!("invalid value '{}': {}", value, err))
+}
+
+/// Parses a table, such as the sampling of each product, from JSON.
+fn json<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_str(value).map_err(|err| format!("invalid value '{}': {}", value, err))
+}
+
+/// Checks that the endpoint `name` is an absolute URL using one of `schemes`.
+fn validate_url(name: &str, url: &str, schemes: &[&str]) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|err| format!("{} '{}' is invalid: {}", name, url, err))?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(format!(
+            "{} '{}' must use one of: {}",
+            name,
+            url,
+            schemes.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Behavior when product discovery matches no products.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyProducts {
+    /// Exits with an error.
+    #[default]
+    Error,
+    /// Discovers products again on an interval until some match.
+    Wait,
+}
+
+/// Behavior when more products than the cap would be tracked.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TooManyProducts {
+    /// Tracks only the first products up to the cap.
+    #[default]
+    Truncate,
+    /// Exits with an error.
+    Error,
+}
+
+/// Format of the candles printed to stdout.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable line for each candle, logs share stdout.
+    #[default]
+    Text,
+    /// Single line JSON object for each completed candle, logs are written to stderr.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown output format '{}', expected 'text' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_timeframes_for_coarser_granularities() {
+        let settings = WatcherSettings {
+            granularity: Granularity::OneHour,
+            ..Default::default()
+        };
+        let err = settings.validate().unwrap_err();
+        assert!(err.contains("`timeframes = [\"1h\"]`"), "{}", err);
+
+        // Finer candles cannot be aggregated from five minutes.
+        let settings = WatcherSettings {
+            granularity: Granularity::OneMinute,
+            ..Default::default()
+        };
+        let err = settings.validate().unwrap_err();
+        assert!(!err.contains("timeframes"), "{}", err);
+
+        assert_eq!(WatcherSettings::default().validate(), Ok(()));
+    }
+
+    /// Environment variables as `(name, value)` pairs.
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn ignores_variables_that_name_no_setting() {
+        let mut settings = WatcherSettings::default();
+        let overrides = settings
+            .apply_env(vars(&[
+                ("CW_SMA_PERIOD", "20"),
+                ("CW_NO_SUCH_SETTING", "1"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+
+        assert_eq!(overrides.applied, vec!["CW_SMA_PERIOD".to_string()]);
+        assert_eq!(overrides.ignored, vec!["CW_NO_SUCH_SETTING".to_string()]);
+        assert_eq!(settings.sma_period, 20);
+    }
+
+    #[test]
+    fn parses_tables_and_lists_from_variables() {
+        let mut settings = WatcherSettings::default();
+        settings
+            .apply_env(vars(&[
+                ("CW_TIMEFRAMES", "15m, 1h"),
+                ("CW_LATENCY_BUCKETS", "0.5,2"),
+                ("CW_SAMPLING", r#"{"every": 3}"#),
+                ("CW_ALERTS", r#"{"BTC-USD": {"above": 70000.0}}"#),
+            ]))
+            .unwrap();
+
+        assert_eq!(
+            settings.timeframes,
+            vec![Timeframe::FifteenMinutes, Timeframe::OneHour]
+        );
+        assert_eq!(settings.latency_buckets, vec![0.5, 2.0]);
+        assert_eq!(settings.sampling.every, 3);
+        assert_eq!(settings.alerts["BTC-USD"].above, Some(70000.0));
+
+        let err = settings
+            .apply_env(vars(&[("CW_SAMPLING", "every=3")]))
+            .unwrap_err();
+        assert!(
+            err.starts_with("environment variable CW_SAMPLING"),
+            "{}",
+            err
+        );
+    }
+}
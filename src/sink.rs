@@ -0,0 +1,492 @@
+//! Destinations for candles once they have been recorded.
+
+use crate::aggregator::Timeframe;
+use crate::calendar::Calendar;
+use crate::fields::{OutputField, Selected};
+use crate::indicators::{Bands, DerivedPrices, MacdReading, SmaReading};
+use crate::template::{Placeholder, Template};
+
+use cbadv::product::Candle;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tracing::{debug, info, warn};
+
+/// Width that product IDs, including any timeframe, are padded to in text lines.
+const SERIES_WIDTH: usize = 16;
+/// Width that closes are padded to in text lines.
+const CLOSE_WIDTH: usize = 14;
+
+/// Details about a recorded candle.
+#[derive(Debug, Clone, Default)]
+pub struct CandleInfo {
+    /// Total candle updates processed by the tracker when the candle was recorded.
+    pub processed: usize,
+    /// Quote currency of the products market, such as `USD`.
+    pub quote: String,
+    /// Timeframe the candle was aggregated into, `None` for candles from the WebSocket.
+    pub timeframe: Option<Timeframe>,
+    /// Whether this is the Heikin-Ashi candle of a completed candle, a separate series
+    /// of the product.
+    pub heikin_ashi: bool,
+    /// Whether the candle completed, `false` if it was flushed while in-progress.
+    pub complete: bool,
+    /// Whether this is a periodic snapshot of the in-progress candle, which is
+    /// recorded again once it completes.
+    pub snapshot: bool,
+    /// Whether this is an update of the in-progress candle forwarded as it arrived,
+    /// which is recorded again once it completes.
+    pub partial: bool,
+    /// Simple moving average of the closes, `None` if disabled or aggregated.
+    pub sma: Option<SmaReading>,
+    /// MACD of the closes, `None` if disabled or aggregated.
+    pub macd: Option<MacdReading>,
+    /// RSI of the closes, `None` if disabled or aggregated. The inner value is `None`
+    /// until enough candles have completed.
+    pub rsi: Option<Option<f64>>,
+    /// VWAP of the current day, `None` if disabled or aggregated. The inner value
+    /// is `None` until volume has been traded within the day.
+    pub vwap: Option<Option<f64>>,
+    /// Bollinger Bands of the closes, `None` if disabled or aggregated. The inner value
+    /// is `None` until enough candles have completed.
+    pub bollinger: Option<Option<Bands>>,
+    /// ATR of the candles, `None` if disabled or aggregated. The inner value is `None`
+    /// until enough candles have completed.
+    pub atr: Option<Option<f64>>,
+    /// Percent change of the close from the previous completed close, `None` for the
+    /// first candle of a product, in-progress and aggregated candles.
+    pub change: Option<f64>,
+    /// Typical price, median price, and weighted close of completed candles, `None`
+    /// unless enabled.
+    pub derived: Option<DerivedPrices>,
+}
+
+impl CandleInfo {
+    /// Whether the candle belongs to the series received from the WebSocket, rather
+    /// than one built from it.
+    pub fn is_native(&self) -> bool {
+        self.timeframe.is_none() && !self.heikin_ashi
+    }
+}
+
+/// Serializable representation of a candle.
+#[derive(Serialize, Debug, Clone)]
+pub struct CandleRecord {
+    pub product_id: String,
+    pub quote: String,
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    /// Percent change of the close from the previous completed close.
+    pub change: Option<f64>,
+    /// Only written when derived prices are enabled.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub derived: Option<DerivedPrices>,
+}
+
+impl CandleRecord {
+    /// Creates a record of a products candle.
+    pub fn new(product_id: &str, candle: &Candle, info: &CandleInfo) -> Self {
+        Self {
+            product_id: product_id.to_string(),
+            quote: info.quote.clone(),
+            start: candle.start,
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+            volume: candle.volume,
+            change: info.change,
+            derived: info.derived,
+        }
+    }
+}
+
+/// Receives every candle recorded by the tracker.
+pub trait CandleSink {
+    /// Called for each completed candle, and for in-progress candles flushed on shutdown.
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo);
+
+    /// Called on shutdown after the final candles are recorded, buffered candles
+    /// should be written before returning.
+    fn flush(&mut self) {}
+}
+
+impl<S: CandleSink + ?Sized> CandleSink for Box<S> {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        (**self).on_candle(product_id, candle, info);
+    }
+
+    fn flush(&mut self) {
+        (**self).flush();
+    }
+}
+
+/// Passes candles to both sinks, in order.
+impl<A: CandleSink, B: CandleSink> CandleSink for (A, B) {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        self.0.on_candle(product_id, candle, info);
+        self.1.on_candle(product_id, candle, info);
+    }
+
+    fn flush(&mut self) {
+        self.0.flush();
+        self.1.flush();
+    }
+}
+
+/// Colors closes by their direction from the prior completed candle of the series.
+#[derive(Debug, Clone, Default)]
+struct CloseColors {
+    /// Whether closes are colored, otherwise they are returned unchanged.
+    enabled: bool,
+    /// Close of the last completed candle of each series.
+    previous: HashMap<String, f64>,
+}
+
+impl CloseColors {
+    /// Colors `text`, the formatted close of `candle`, green when rising and red when
+    /// falling. Completed candles become the prior candle of the series.
+    fn paint(&mut self, series: &str, candle: &Candle, complete: bool, text: String) -> String {
+        let prior = if complete {
+            self.previous.insert(series.to_string(), candle.close)
+        } else {
+            self.previous.get(series).copied()
+        };
+
+        match prior {
+            Some(prior) if self.enabled && candle.close > prior => text.green().to_string(),
+            Some(prior) if self.enabled && candle.close < prior => text.red().to_string(),
+            _ => text,
+        }
+    }
+}
+
+/// Prints a single line summary for each candle.
+#[derive(Debug, Clone, Default)]
+pub struct StdoutSink {
+    /// Format of each line, the default summary is printed if `None`.
+    template: Option<Template>,
+    /// Colors the close of each line.
+    colors: CloseColors,
+    /// Formats the start of each candle.
+    calendar: Calendar,
+}
+
+impl StdoutSink {
+    /// Creates a sink printing the default summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a sink printing each candle using `template`.
+    pub fn with_template(template: Template) -> Self {
+        Self {
+            template: Some(template),
+            ..Self::default()
+        }
+    }
+
+    /// Colors closes green when rising and red when falling from the prior candle,
+    /// should only be enabled when stdout is a terminal.
+    pub fn set_color(&mut self, color: bool) {
+        self.colors.enabled = color;
+    }
+
+    /// Shows the start of each candle in the timezone of `calendar`, rather than as
+    /// a Unix time.
+    pub fn set_calendar(&mut self, calendar: Calendar) {
+        self.calendar = calendar;
+    }
+}
+
+impl CandleSink for StdoutSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        let status = if info.complete {
+            "finished"
+        } else if info.snapshot {
+            "snapshot"
+        } else if info.partial {
+            "partial"
+        } else {
+            "incomplete"
+        };
+        let series = match info.timeframe {
+            Some(tf) => format!("{} [{}]", product_id, tf),
+            None if info.heikin_ashi => format!("{} [HA]", product_id),
+            None => product_id.to_string(),
+        };
+
+        let mut indicators = String::new();
+        match info.sma {
+            Some(SmaReading {
+                period,
+                value: Some(value),
+            }) => indicators.push_str(&format!(" SMA({}): {:.4}", period, value)),
+            Some(SmaReading {
+                period,
+                value: None,
+            }) => indicators.push_str(&format!(" SMA({}): n/a", period)),
+            None => (),
+        }
+
+        match info.macd {
+            Some(MacdReading {
+                value: Some(value),
+                signal,
+                histogram,
+            }) => indicators.push_str(&format!(
+                " MACD: {:.4}/{}/{}",
+                value,
+                fmt_value(signal),
+                fmt_value(histogram)
+            )),
+            Some(_) => indicators.push_str(" MACD: n/a"),
+            None => (),
+        }
+
+        if let Some(value) = info.rsi {
+            indicators.push_str(&format!(" RSI: {}", fmt_value(value)));
+        }
+
+        if let Some(value) = info.vwap {
+            indicators.push_str(&format!(" VWAP: {}", fmt_value(value)));
+        }
+
+        if let Some(value) = info.atr {
+            indicators.push_str(&format!(" ATR: {}", fmt_value(value)));
+        }
+
+        if let Some(change) = info.change {
+            indicators.push_str(&format!(" CHG: {:+.2}%", change));
+        }
+
+        if let Some(derived) = info.derived {
+            indicators.push_str(&format!(
+                " TP: {:.4} MP: {:.4} WC: {:.4}",
+                derived.typical_price, derived.median_price, derived.weighted_close
+            ));
+        }
+
+        match info.bollinger {
+            Some(Some(bands)) => indicators.push_str(&format!(
+                " BB: {:.4}/{:.4}/{:.4}",
+                bands.lower, bands.middle, bands.upper
+            )),
+            Some(None) => indicators.push_str(" BB: n/a"),
+            None => (),
+        }
+
+        if let Some(template) = &self.template {
+            let close = self
+                .colors
+                .paint(&series, candle, info.complete, candle.close.to_string());
+            let line = template.render(|placeholder| match placeholder {
+                Placeholder::Processed => info.processed.to_string(),
+                Placeholder::ProductId => product_id.to_string(),
+                Placeholder::Quote => info.quote.clone(),
+                Placeholder::Timeframe => {
+                    info.timeframe.map(|tf| tf.to_string()).unwrap_or_default()
+                }
+                Placeholder::Start => self.calendar.format(candle.start),
+                Placeholder::Open => candle.open.to_string(),
+                Placeholder::High => candle.high.to_string(),
+                Placeholder::Low => candle.low.to_string(),
+                Placeholder::Close => close.clone(),
+                Placeholder::Volume => candle.volume.to_string(),
+                Placeholder::Status => status.to_string(),
+                Placeholder::Indicators => indicators.trim_start().to_string(),
+            });
+
+            info!(
+                processed = info.processed,
+                product_id = %series,
+                start = candle.start,
+                close = candle.close,
+                "{}",
+                line
+            );
+            return;
+        }
+
+        // Product_Id | Candle Start | Status | Close | Indicators, padded into columns.
+        let close = format!("{:>width$.4}", candle.close, width = CLOSE_WIDTH);
+        let close = self.colors.paint(&series, candle, info.complete, close);
+        info!(
+            processed = info.processed,
+            product_id = %series,
+            start = candle.start,
+            close = candle.close,
+            "{:<series_width$} {:>10} {:<10} {}{}",
+            series,
+            self.calendar.format(candle.start),
+            status,
+            close,
+            indicators,
+            series_width = SERIES_WIDTH
+        );
+    }
+}
+
+/// Formats an optional indicator value, `n/a` if missing.
+fn fmt_value(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{:.4}", value),
+        None => "n/a".to_string(),
+    }
+}
+
+/// Writes each completed candle to stdout as a single line of JSON, including the
+/// in-progress candles flushed on shutdown marked with `"complete": false`.
+#[derive(Debug, Clone, Default)]
+pub struct JsonSink {
+    /// Fields written for each candle, in order, every field if empty.
+    fields: Vec<OutputField>,
+}
+
+impl JsonSink {
+    /// Creates a sink writing every field of the candles.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a sink writing only `fields` of the candles, in order. Every field is
+    /// written if empty.
+    pub fn with_fields(fields: Vec<OutputField>) -> Self {
+        Self { fields }
+    }
+}
+
+/// Line written by the `JsonSink`.
+#[derive(Serialize)]
+struct JsonLine {
+    processed: usize,
+    /// Only written for candles that had not completed.
+    #[serde(skip_serializing_if = "is_true")]
+    complete: bool,
+    /// Only written for snapshots of in-progress candles.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    snapshot: bool,
+    /// Only written for partial updates of in-progress candles.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+    #[serde(flatten)]
+    candle: CandleRecord,
+}
+
+/// Line written by the `JsonSink` when only some fields are selected.
+#[derive(Serialize)]
+struct SelectedLine<'a> {
+    processed: usize,
+    #[serde(skip_serializing_if = "is_true")]
+    complete: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    snapshot: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+    #[serde(flatten)]
+    candle: Selected<'a>,
+}
+
+/// Whether a flag is set, for skipping flags that are usually set.
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl CandleSink for JsonSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        // Only native candles, aggregated series would interleave.
+        if !info.is_native() {
+            return;
+        }
+
+        let json = if self.fields.is_empty() {
+            serde_json::to_string(&JsonLine {
+                processed: info.processed,
+                complete: info.complete,
+                snapshot: info.snapshot,
+                partial: info.partial,
+                candle: CandleRecord::new(product_id, candle, info),
+            })
+        } else {
+            serde_json::to_string(&SelectedLine {
+                processed: info.processed,
+                complete: info.complete,
+                snapshot: info.snapshot,
+                partial: info.partial,
+                candle: Selected {
+                    fields: &self.fields,
+                    product_id,
+                    candle,
+                },
+            })
+        };
+        let json = match json {
+            Ok(json) => json,
+            Err(err) => {
+                warn!(product_id, "unable to serialize candle: {}", err);
+                return;
+            }
+        };
+
+        // Flush every line so consumers such as `jq` receive candles as they complete.
+        let mut stdout = io::stdout().lock();
+        if let Err(err) = writeln!(stdout, "{}", json).and_then(|_| stdout.flush()) {
+            warn!("unable to write candle to stdout: {}", err);
+        }
+    }
+}
+
+/// Discards every candle, such as for a dry run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl CandleSink for NullSink {
+    fn on_candle(&mut self, _product_id: &str, _candle: &Candle, _info: &CandleInfo) {}
+}
+
+/// Sends each completed candle to a bounded channel. Candles are dropped while the
+/// channel is full, the tracker is never blocked by a slow receiver.
+#[derive(Debug, Clone)]
+pub struct ChannelSink {
+    sender: Sender<(String, Candle)>,
+}
+
+impl ChannelSink {
+    /// Creates a sink and the receiver for its candles, buffering up to `capacity`.
+    pub fn new(capacity: usize) -> (Self, Receiver<(String, Candle)>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (Self { sender }, receiver)
+    }
+}
+
+impl CandleSink for ChannelSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        match self
+            .sender
+            .try_send((product_id.to_string(), candle.clone()))
+        {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    product_id,
+                    start = candle.start,
+                    "Candle channel is full, dropped candle."
+                );
+            }
+            Err(TrySendError::Closed(_)) => {
+                debug!(product_id, "Candle channel is closed, dropped candle.");
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Persists candles to a SQLite database.
+
+use crate::sink::{CandleInfo, CandleSink};
+
+use cbadv::product::Candle;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+/// Maximum candles written within a single transaction.
+const BATCH_SIZE: usize = 100;
+/// Maximum time a candle waits before its batch is written.
+const BATCH_INTERVAL: Duration = Duration::from_secs(5);
+/// Maximum candles kept while writes fail, the oldest are dropped beyond it.
+const MAX_PENDING: usize = 100_000;
+/// Delay before retrying the first failed write, doubled by each failure after it.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Maximum delay between retries of a failing write.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Current version of the schema, stored as the databases `user_version`.
+const SCHEMA_VERSION: i64 = 2;
+
+/// Inserts a candle, replacing the values of an existing candle.
+const UPSERT: &str =
+    "INSERT INTO candles (product_id, start, open, high, low, close, volume, quote)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+    ON CONFLICT (product_id, start) DO UPDATE SET
+        quote = excluded.quote,
+        open = excluded.open,
+        high = excluded.high,
+        low = excluded.low,
+        close = excluded.close,
+        volume = excluded.volume";
+
+/// Work sent to the writer thread.
+enum Command {
+    /// Queues a candle of a product, with its quote currency, to be written.
+    Insert(Pending),
+    /// Writes all queued candles, signaling once complete.
+    Flush(Sender<()>),
+}
+
+/// Candle waiting to be written.
+struct Pending {
+    product_id: String,
+    quote: String,
+    candle: Candle,
+}
+
+/// Upserts candles from the WebSocket into a `candles` table. Writes happen on a
+/// separate thread in batches of up to 100 candles or every 5 seconds.
+pub struct SqliteSink {
+    /// Queues work for the writer, `None` once shutting down.
+    commands: Option<Sender<Command>>,
+    /// Thread writing to the database.
+    writer: Option<JoinHandle<()>>,
+}
+
+impl SqliteSink {
+    /// Opens or creates the database at `path`, migrating the schema if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        migrate(&conn)?;
+
+        let (commands, receiver) = mpsc::channel();
+        let writer = thread::spawn(move || write_loop(conn, receiver));
+        Ok(Self {
+            commands: Some(commands),
+            writer: Some(writer),
+        })
+    }
+
+    /// Sends work to the writer thread.
+    fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            if commands.send(command).is_err() {
+                error!("SQLite writer stopped, candle dropped.");
+            }
+        }
+    }
+}
+
+impl CandleSink for SqliteSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        // Aggregated and Heikin-Ashi candles would collide with the candles they were
+        // built from, and snapshots and partial updates are stored once the candle
+        // completes.
+        if !info.is_native() || info.snapshot || info.partial {
+            return;
+        }
+
+        self.send(Command::Insert(Pending {
+            product_id: product_id.to_string(),
+            quote: info.quote.clone(),
+            candle: candle.clone(),
+        }));
+    }
+
+    fn flush(&mut self) {
+        let (done, wait) = mpsc::channel();
+        self.send(Command::Flush(done));
+        let _ = wait.recv();
+    }
+}
+
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        // Closing the channel writes the remaining candles and stops the writer.
+        self.commands.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Brings the schema up to date within a single transaction, so a failed migration
+/// leaves the previous version in place.
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let version: i64 = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < 1 {
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS candles (
+                product_id TEXT NOT NULL,
+                start INTEGER NOT NULL,
+                open REAL NOT NULL,
+                high REAL NOT NULL,
+                low REAL NOT NULL,
+                close REAL NOT NULL,
+                volume REAL NOT NULL,
+                PRIMARY KEY (product_id, start)
+            );",
+        )?;
+    }
+    // Databases migrated before it ran in a transaction may have the column already.
+    if version < 2 && !has_column(&tx, "candles", "quote")? {
+        // Candles written before markets were tagged have no quote currency.
+        tx.execute_batch("ALTER TABLE candles ADD COLUMN quote TEXT;")?;
+    }
+
+    tx.execute_batch(&format!("PRAGMA user_version = {};", SCHEMA_VERSION))?;
+    tx.commit()
+}
+
+/// Whether `table` has a column named `column`.
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    names.try_fold(false, |found, name| Ok(found || name? == column))
+}
+
+/// Candles waiting to be written, retried with a growing delay while writes fail.
+struct Batch {
+    pending: Vec<Pending>,
+    /// Delay before retrying after the next failed write.
+    retry_delay: Duration,
+    /// Earliest time to write again after a failed write, `None` if the last succeeded.
+    retry_at: Option<Instant>,
+}
+
+impl Batch {
+    /// Queues a candle, dropping the oldest if writes have been failing for too long.
+    fn push(&mut self, candle: Pending) {
+        self.pending.push(candle);
+        if self.pending.len() > MAX_PENDING {
+            let excess = self.pending.len() - MAX_PENDING;
+            self.pending.drain(..excess);
+            warn!(
+                "SQLite writes are failing, dropped the {} oldest candles.",
+                excess
+            );
+        }
+    }
+
+    /// Whether a write may be attempted, not waiting out the delay of a failed write.
+    fn may_write(&self) -> bool {
+        match self.retry_at {
+            Some(at) => Instant::now() >= at,
+            None => true,
+        }
+    }
+
+    /// Writes the pending candles, delaying the next attempt if it fails.
+    fn write(&mut self, conn: &mut Connection) {
+        if commit(conn, &mut self.pending) {
+            self.retry_delay = MIN_RETRY_DELAY;
+            self.retry_at = None;
+        } else {
+            self.retry_at = Some(Instant::now() + self.retry_delay);
+            self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+/// Writes queued candles until the sink is dropped.
+fn write_loop(mut conn: Connection, commands: Receiver<Command>) {
+    let mut batch = Batch {
+        pending: vec![],
+        retry_delay: MIN_RETRY_DELAY,
+        retry_at: None,
+    };
+    let mut deadline = Instant::now() + BATCH_INTERVAL;
+
+    loop {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match commands.recv_timeout(wait) {
+            Ok(Command::Insert(candle)) => {
+                if batch.pending.is_empty() {
+                    deadline = Instant::now() + BATCH_INTERVAL;
+                }
+
+                batch.push(candle);
+                if batch.pending.len() >= BATCH_SIZE && batch.may_write() {
+                    batch.write(&mut conn);
+                }
+            }
+            Ok(Command::Flush(done)) => {
+                // Shutting down, one more attempt regardless of the delay.
+                batch.write(&mut conn);
+                let _ = done.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if batch.may_write() {
+                    batch.write(&mut conn);
+                }
+                deadline = Instant::now() + BATCH_INTERVAL;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                batch.write(&mut conn);
+                return;
+            }
+        }
+    }
+}
+
+/// Writes the pending candles within a single transaction, returning whether they
+/// were written. They are kept to be retried if not.
+fn commit(conn: &mut Connection, pending: &mut Vec<Pending>) -> bool {
+    if pending.is_empty() {
+        return true;
+    }
+
+    let result = conn.transaction().and_then(|tx| {
+        {
+            let mut stmt = tx.prepare_cached(UPSERT)?;
+            for Pending {
+                product_id,
+                quote,
+                candle,
+            } in pending.iter()
+            {
+                stmt.execute(params![
+                    product_id,
+                    candle.start as i64,
+                    candle.open,
+                    candle.high,
+                    candle.low,
+                    candle.close,
+                    candle.volume,
+                    quote
+                ])?;
+            }
+        }
+        tx.commit()
+    });
+
+    match result {
+        Ok(_) => {
+            pending.clear();
+            true
+        }
+        Err(err) => {
+            error!(
+                "Unable to write {} candles to SQLite: {}",
+                pending.len(),
+                err
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Tracker state saved on shutdown and restored on the next start.
+
+use crate::aggregator::Timeframe;
+use crate::indicators::{Atr, Macd, Rsi, Sma, Vwap};
+
+use cbadv::product::Candle;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Version of the state file format, files of other versions are rejected.
+pub(crate) const STATE_VERSION: u32 = 2;
+
+/// Only the version of a state file, read before the rest of the file.
+#[derive(Deserialize, Debug)]
+pub(crate) struct StateVersion {
+    pub version: u32,
+}
+
+/// In-progress candle of a product.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SavedCandle {
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl From<&Candle> for SavedCandle {
+    fn from(candle: &Candle) -> Self {
+        Self {
+            start: candle.start,
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+            volume: candle.volume,
+        }
+    }
+}
+
+impl From<SavedCandle> for Candle {
+    fn from(saved: SavedCandle) -> Self {
+        Candle {
+            start: saved.start,
+            low: saved.low,
+            high: saved.high,
+            open: saved.open,
+            close: saved.close,
+            volume: saved.volume,
+        }
+    }
+}
+
+/// In-progress candles, recent history, and indicator accumulators of each product.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct TrackerState {
+    /// Format of the file, always `STATE_VERSION` when written.
+    pub version: u32,
+    /// Unix time, in seconds, the state was saved.
+    pub saved_at: u64,
+    pub candles: HashMap<String, SavedCandle>,
+    pub sma: HashMap<String, Sma>,
+    pub macd: HashMap<String, Macd>,
+    pub rsi: HashMap<String, Rsi>,
+    pub vwap: HashMap<String, Vwap>,
+    pub atr: HashMap<String, Atr>,
+    /// Most recent completed candles, oldest first.
+    pub history: HashMap<String, Vec<SavedCandle>>,
+    /// Starts of the most recently completed candles, oldest first.
+    pub recent: HashMap<String, Vec<u64>>,
+    /// Partially built candle of each higher timeframe.
+    pub aggregators: HashMap<String, HashMap<Timeframe, SavedCandle>>,
+}
@@ -0,0 +1,416 @@
+//! Counters shared between the tracker and the periodic summary.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time;
+use tracing::info;
+
+/// Default upper bounds, in seconds, of the completion latency buckets.
+pub const DEFAULT_LATENCY_BUCKETS: [f64; 8] = [0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Counters updated by the tracker, cheap to update from the message path.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Total candle updates processed.
+    processed: AtomicUsize,
+    /// Total candles ejected as finished.
+    completed: AtomicUsize,
+    /// Products currently being tracked.
+    products: AtomicUsize,
+    /// Largest delay between a candles start and receiving it since the last summary.
+    max_lag: AtomicU64,
+    /// WebSocket connections currently connected and subscribed.
+    connections: AtomicUsize,
+    /// Total reconnection attempts made after the connection was lost or failed.
+    reconnects: AtomicUsize,
+    /// Total candle updates rejected as malformed.
+    rejected: AtomicUsize,
+    /// Total completed candles skipped for having the start of the last completed one.
+    duplicate_ejections: AtomicUsize,
+    /// Unix time, in seconds, the last message of any kind was received.
+    last_message: AtomicU64,
+    /// Seconds between the end of each candles interval and it completing.
+    latency: Mutex<Histogram>,
+}
+
+/// Counts of observations within buckets of increasing upper bounds, with a final
+/// bucket for anything above the largest bound.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Upper bounds of the buckets, ascending.
+    bounds: Vec<f64>,
+    /// Observations within each bucket, one more than the bounds.
+    counts: Vec<u64>,
+    /// Sum of every observation.
+    sum: f64,
+}
+
+impl Histogram {
+    /// Creates an empty histogram, the bounds are sorted and duplicates removed.
+    pub fn new(mut bounds: Vec<f64>) -> Self {
+        bounds.retain(|bound| bound.is_finite());
+        bounds.sort_by(|a, b| a.total_cmp(b));
+        bounds.dedup();
+        Self {
+            counts: vec![0; bounds.len() + 1],
+            bounds,
+            sum: 0.0,
+        }
+    }
+
+    /// Adds an observation, negative values are counted as 0.
+    pub fn observe(&mut self, value: f64) {
+        let value = value.max(0.0);
+        let index = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[index] += 1;
+        self.sum += value;
+    }
+
+    /// Upper bounds of the buckets, ascending.
+    pub fn bounds(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// Observations at or below each bound.
+    pub fn cumulative(&self) -> Vec<u64> {
+        self.counts[..self.bounds.len()]
+            .iter()
+            .scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            })
+            .collect()
+    }
+
+    /// Total observations.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Sum of every observation.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Upper bound of the bucket holding the `quantile` (0 to 1) observation, infinite
+    /// if above the largest bound. `None` without observations.
+    pub fn percentile(&self, quantile: f64) -> Option<f64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let rank = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut total: u64 = 0;
+        for (i, bucket) in self.counts.iter().enumerate() {
+            total += bucket;
+            if total >= rank {
+                return Some(self.bounds.get(i).copied().unwrap_or(f64::INFINITY));
+            }
+        }
+        Some(f64::INFINITY)
+    }
+
+    /// Adds the observations of `other`, which must share the same bounds.
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.bounds != other.bounds {
+            return;
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.sum += other.sum;
+    }
+
+    /// Observations made since `earlier`, a previous copy of this histogram.
+    pub fn since(&self, earlier: &Histogram) -> Histogram {
+        if self.bounds != earlier.bounds {
+            return self.clone();
+        }
+        Histogram {
+            bounds: self.bounds.clone(),
+            counts: self
+                .counts
+                .iter()
+                .zip(&earlier.counts)
+                .map(|(count, earlier)| count.saturating_sub(*earlier))
+                .collect(),
+            sum: (self.sum - earlier.sum).max(0.0),
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_LATENCY_BUCKETS.to_vec())
+    }
+}
+
+/// Counters kept by the tracker for each product.
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct ProductStats {
+    /// Candles ejected as finished for the product.
+    pub completed: usize,
+    /// Unix time, in seconds, the last update for the product was received.
+    pub last_update: u64,
+    /// Updates that arrived after a newer candle of the product.
+    pub late: usize,
+}
+
+impl Stats {
+    /// Updates the totals after processing a message.
+    pub fn update(&self, processed: usize, completed: usize, products: usize) {
+        self.processed.store(processed, Ordering::Relaxed);
+        self.completed.store(completed, Ordering::Relaxed);
+        self.products.store(products, Ordering::Relaxed);
+    }
+
+    /// Records the delay, in seconds, between a candles start and receiving it.
+    pub fn observe_lag(&self, lag: u64) {
+        self.max_lag.fetch_max(lag, Ordering::Relaxed);
+    }
+
+    /// Total candle updates processed.
+    pub fn processed(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// Total candles ejected as finished.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    /// Products currently being tracked.
+    pub fn products(&self) -> usize {
+        self.products.load(Ordering::Relaxed)
+    }
+
+    /// Records that a WebSocket connection was established and subscribed.
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a WebSocket connection was lost or closed.
+    pub fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// WebSocket connections currently connected and subscribed.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Whether any WebSocket connection is currently connected and subscribed.
+    pub fn connected(&self) -> bool {
+        self.connections() > 0
+    }
+
+    /// Records that a message was received at `now`, in Unix seconds.
+    pub fn record_message(&self, now: u64) {
+        self.last_message.store(now, Ordering::Relaxed);
+    }
+
+    /// Unix time, in seconds, the last message was received.
+    pub fn last_message(&self) -> u64 {
+        self.last_message.load(Ordering::Relaxed)
+    }
+
+    /// Records an attempt to reconnect.
+    pub fn record_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Total reconnection attempts made.
+    pub fn reconnects(&self) -> usize {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Records a candle update rejected as malformed.
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Total candle updates rejected as malformed.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Records a completed candle skipped as a repeat of the last completed one.
+    pub fn record_duplicate_ejection(&self) {
+        self.duplicate_ejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Total completed candles skipped as a repeat of the last completed one.
+    pub fn duplicate_ejections(&self) -> usize {
+        self.duplicate_ejections.load(Ordering::Relaxed)
+    }
+
+    /// Sets the upper bounds, in seconds, of the completion latency buckets, clearing
+    /// the observations.
+    pub fn set_latency_buckets(&self, bounds: Vec<f64>) {
+        *self.latency.lock().unwrap() = Histogram::new(bounds);
+    }
+
+    /// Records the seconds between the end of a candles interval and it completing.
+    pub fn observe_latency(&self, seconds: f64) {
+        self.latency.lock().unwrap().observe(seconds);
+    }
+
+    /// Completion latencies observed so far.
+    pub fn latency(&self) -> Histogram {
+        self.latency.lock().unwrap().clone()
+    }
+
+    /// Obtains the largest lag observed since the last call, resetting it.
+    pub fn take_max_lag(&self) -> u64 {
+        self.max_lag.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Prints a summary of the counters every `interval`, combined across the partitions
+/// in `stats`. Runs until aborted.
+pub async fn report(stats: Vec<Arc<Stats>>, interval: Duration) {
+    let mut ticker = time::interval(interval);
+    // First tick completes immediately.
+    ticker.tick().await;
+
+    let processed_total = || stats.iter().map(|s| s.processed()).sum::<usize>();
+    let latency_total = || {
+        let mut latencies = stats.iter().map(|s| s.latency());
+        let mut total = latencies.next().unwrap_or_default();
+        for latency in latencies {
+            total.merge(&latency);
+        }
+        total
+    };
+    let mut last_processed = processed_total();
+    let mut last_latency = latency_total();
+    let mut last_time = Instant::now();
+
+    loop {
+        ticker.tick().await;
+
+        let processed = processed_total();
+        let elapsed = last_time.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            processed.saturating_sub(last_processed) as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        // Percentiles of the candles completed within this interval.
+        let latency_now = latency_total();
+        let latency = latency_now.since(&last_latency);
+
+        info!(
+            "Summary: {} processed, {} completed, {:.2} candles/sec, {} products tracked, {}s max lag, completion latency p50 {} p90 {} p99 {}.",
+            processed,
+            stats.iter().map(|s| s.completed()).sum::<usize>(),
+            rate,
+            stats.iter().map(|s| s.products()).sum::<usize>(),
+            stats.iter().map(|s| s.take_max_lag()).max().unwrap_or(0),
+            fmt_bound(latency.percentile(0.5)),
+            fmt_bound(latency.percentile(0.9)),
+            fmt_bound(latency.percentile(0.99))
+        );
+
+        last_processed = processed;
+        last_latency = latency_now;
+        last_time = Instant::now();
+    }
+}
+
+/// Totals of a whole session, reported once the watcher stops.
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionSummary {
+    /// Seconds the watcher ran for.
+    pub runtime_secs: u64,
+    /// Reconnections across every connection.
+    pub reconnects: usize,
+    /// Completed candles skipped as a repeat of the last completed one.
+    pub duplicate_ejections: usize,
+    /// Candles completed for each watched product.
+    pub completed: BTreeMap<String, usize>,
+    /// Watched products that never completed a candle.
+    pub silent: Vec<String>,
+}
+
+impl SessionSummary {
+    /// Summarizes the `products` watched for `runtime`, using the counters of every
+    /// partition.
+    pub fn new(
+        products: &[String],
+        product_stats: &HashMap<String, ProductStats>,
+        reconnects: usize,
+        duplicate_ejections: usize,
+        runtime: Duration,
+    ) -> Self {
+        let completed: BTreeMap<String, usize> = products
+            .iter()
+            .map(|product_id| {
+                let count = product_stats.get(product_id).map_or(0, |s| s.completed);
+                (product_id.clone(), count)
+            })
+            .collect();
+        let silent = completed
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(product_id, _)| product_id.clone())
+            .collect();
+
+        Self {
+            runtime_secs: runtime.as_secs(),
+            reconnects,
+            duplicate_ejections,
+            completed,
+            silent,
+        }
+    }
+}
+
+impl fmt::Display for SessionSummary {
+    /// Aligned table of the completed candles of each product.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let runtime = self.runtime_secs;
+        writeln!(
+            f,
+            "Session ran {}h {:02}m {:02}s with {} reconnects and {} duplicate ejections.",
+            runtime / 3_600,
+            runtime % 3_600 / 60,
+            runtime % 60,
+            self.reconnects,
+            self.duplicate_ejections
+        )?;
+
+        let width = self
+            .completed
+            .keys()
+            .map(String::len)
+            .chain(["PRODUCT".len()])
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:<width$}  COMPLETED", "PRODUCT", width = width)?;
+        for (product_id, count) in &self.completed {
+            writeln!(f, "{:<width$}  {:>9}", product_id, count, width = width)?;
+        }
+
+        if self.silent.is_empty() {
+            write!(f, "Every product produced a candle.")
+        } else {
+            write!(f, "Never produced a candle: {}", self.silent.join(", "))
+        }
+    }
+}
+
+/// Formats a percentile bucket bound, `n/a` without observations.
+fn fmt_bound(bound: Option<f64>) -> String {
+    match bound {
+        Some(bound) if bound.is_infinite() => "over the largest bucket".to_string(),
+        Some(bound) => format!("<={}s", bound),
+        None => "n/a".to_string(),
+    }
+}
@@ -0,0 +1,134 @@
+//! Completed candles exposed as an asynchronous stream.
+
+use crate::sink::{CandleInfo, CandleSink};
+use crate::TaskTracker;
+
+use cbadv::product::Candle;
+use cbadv::websocket::{Message, MessageCallback, WebSocketReader};
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracing::error;
+
+/// Stream of completed candles read from a WebSocket connection. Subscriptions are
+/// still made with the client, the stream only consumes the reader. Candles complete
+/// the same way as for the callback of a tracker, so duplicates are skipped and the
+/// counters are kept.
+///
+/// ```ignore
+/// let reader = client.connect().await?;
+/// client.sub(Channel::CANDLES, &products).await?;
+///
+/// let mut stream = CandleStream::new(reader);
+/// while let Some(candle) = stream.next().await {
+///     println!("{}: {}", candle.start, candle.close);
+/// }
+/// ```
+pub struct CandleStream {
+    /// Reader for the WebSocket connection.
+    reader: WebSocketReader,
+    /// Tracks in-progress candles and decides when they are complete.
+    tracker: TaskTracker<QueueSink>,
+}
+
+impl CandleStream {
+    /// Creates a stream of completed candles from a connected reader.
+    pub fn new(reader: WebSocketReader) -> Self {
+        Self {
+            reader,
+            tracker: TaskTracker::with_sink(QueueSink::default()),
+        }
+    }
+
+    /// Tracker deciding when candles complete, such as for its counters.
+    pub fn tracker(&self) -> &TaskTracker<QueueSink> {
+        &self.tracker
+    }
+}
+
+/// Queues the completed candles of a stream until they are yielded.
+#[derive(Debug, Clone, Default)]
+pub struct QueueSink {
+    /// Completed candles waiting to be yielded, oldest first.
+    pending: VecDeque<Candle>,
+}
+
+impl QueueSink {
+    /// Takes the oldest completed candle waiting to be yielded.
+    pub fn pop(&mut self) -> Option<Candle> {
+        self.pending.pop_front()
+    }
+}
+
+impl CandleSink for QueueSink {
+    fn on_candle(&mut self, _product_id: &str, candle: &Candle, info: &CandleInfo) {
+        // Only completed candles received from the WebSocket, like the stream always has.
+        if !info.complete || !info.is_native() {
+            return;
+        }
+        self.pending.push_back(candle.clone());
+    }
+}
+
+impl Stream for CandleStream {
+    type Item = Candle;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(candle) = self.tracker.sink_mut().pop() {
+                return Poll::Ready(Some(candle));
+            }
+
+            let data = match self.reader.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(data))) => data,
+                Poll::Ready(Some(Err(err))) => {
+                    error!("WebSocket error: {}", err);
+                    continue;
+                }
+                // Connection closed.
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            // Skip anything that is not a parsable message, such as pings.
+            let text = match data.to_text() {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            let msg: Message = match serde_json::from_str(text) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+
+            // Completes candles the same way as the callback of a connection.
+            self.tracker.message_callback(Ok(msg));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{candle, candles_message};
+
+    #[test]
+    fn completes_candles_through_the_tracker() {
+        let mut tracker = TaskTracker::with_sink(QueueSink::default());
+        tracker.message_callback(Ok(candles_message(
+            "BTC-USD",
+            &[candle(0, 10.0), candle(300, 11.0)],
+        )));
+        assert_eq!(tracker.sink_mut().pop().map(|c| c.start), Some(0));
+        assert_eq!(tracker.completed(), 1);
+
+        // Resent after a reconnect, the completed candle is not yielded again.
+        tracker.message_callback(Ok(candles_message(
+            "BTC-USD",
+            &[candle(0, 10.0), candle(300, 11.0), candle(600, 12.0)],
+        )));
+        assert_eq!(tracker.sink_mut().pop().map(|c| c.start), Some(300));
+        assert!(tracker.sink_mut().pop().is_none());
+        assert_eq!(tracker.completed(), 2);
+    }
+}
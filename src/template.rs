@@ -0,0 +1,124 @@
+//! User supplied format of the line printed for each candle.
+
+use std::str::FromStr;
+
+/// Values that can be substituted into a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Processed,
+    ProductId,
+    Quote,
+    Timeframe,
+    Start,
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+    Status,
+    Indicators,
+}
+
+impl Placeholder {
+    /// Every placeholder with its name, as written between braces.
+    const ALL: [(&'static str, Placeholder); 12] = [
+        ("processed", Placeholder::Processed),
+        ("product_id", Placeholder::ProductId),
+        ("quote", Placeholder::Quote),
+        ("timeframe", Placeholder::Timeframe),
+        ("start", Placeholder::Start),
+        ("open", Placeholder::Open),
+        ("high", Placeholder::High),
+        ("low", Placeholder::Low),
+        ("close", Placeholder::Close),
+        ("volume", Placeholder::Volume),
+        ("status", Placeholder::Status),
+        ("indicators", Placeholder::Indicators),
+    ];
+}
+
+/// Part of a parsed template.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Text copied as-is.
+    Literal(String),
+    /// Replaced with a value of the candle.
+    Value(Placeholder),
+}
+
+/// Parsed template such as `"{product_id} {start}: {close}"`. Braces are written as
+/// `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Substitutes each placeholder with the value returned by `value`.
+    pub fn render(&self, value: impl Fn(Placeholder) -> String) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => output.push_str(text),
+                Segment::Value(placeholder) => output.push_str(&value(*placeholder)),
+            }
+        }
+        output
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    /// Parses a template, unknown placeholders and unmatched braces are errors.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments: Vec<Segment> = vec![];
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        return Err("unclosed '{' in template, use '{{' for a brace".to_string());
+                    }
+
+                    let placeholder = Placeholder::ALL
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, p)| *p)
+                        .ok_or_else(|| format!("unknown placeholder '{{{}}}' in template", name))?;
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Value(placeholder));
+                }
+                '}' => return Err("unmatched '}' in template, use '}}' for a brace".to_string()),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+}
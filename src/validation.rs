@@ -0,0 +1,226 @@
+//! Rejects malformed candle updates before they reach the series.
+
+use cbadv::product::Candle;
+use serde::Deserialize;
+use std::fmt;
+
+/// Rules a candle update must satisfy to be tracked.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct CandleRules {
+    /// Whether updates are checked at all.
+    pub enabled: bool,
+    /// Whether candles without volume are accepted, quiet products have them.
+    pub allow_zero_volume: bool,
+    /// Seconds a candle may start after the current time, allowing for clock skew.
+    pub max_future: u64,
+}
+
+impl Default for CandleRules {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allow_zero_volume: true,
+            max_future: 300,
+        }
+    }
+}
+
+/// Why a candle update was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// A price or the volume is NaN or infinite.
+    NotFinite,
+    /// A price is zero or negative.
+    NonPositivePrice,
+    /// The volume is negative, or zero when that is not allowed.
+    InvalidVolume(f64),
+    /// The high is below the low.
+    HighBelowLow { high: f64, low: f64 },
+    /// The open or close is outside of the high and low.
+    OutsideRange,
+    /// The candle starts this many seconds after the current time.
+    InFuture(u64),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::NotFinite => write!(f, "price or volume is not a number"),
+            Rejection::NonPositivePrice => write!(f, "price is not positive"),
+            Rejection::InvalidVolume(volume) => write!(f, "volume {} is not allowed", volume),
+            Rejection::HighBelowLow { high, low } => {
+                write!(f, "high {} is below low {}", high, low)
+            }
+            Rejection::OutsideRange => write!(f, "open or close is outside the high and low"),
+            Rejection::InFuture(secs) => write!(f, "starts {}s in the future", secs),
+        }
+    }
+}
+
+impl CandleRules {
+    /// Checks a candle update received at `now`, in Unix seconds.
+    pub fn check(&self, candle: &Candle, now: u64) -> Result<(), Rejection> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let prices = [candle.open, candle.high, candle.low, candle.close];
+        if !prices.iter().chain([&candle.volume]).all(|v| v.is_finite()) {
+            return Err(Rejection::NotFinite);
+        }
+        if prices.iter().any(|price| *price <= 0.0) {
+            return Err(Rejection::NonPositivePrice);
+        }
+        if candle.volume < 0.0 || (candle.volume == 0.0 && !self.allow_zero_volume) {
+            return Err(Rejection::InvalidVolume(candle.volume));
+        }
+        if candle.high < candle.low {
+            return Err(Rejection::HighBelowLow {
+                high: candle.high,
+                low: candle.low,
+            });
+        }
+        let range = candle.low..=candle.high;
+        if !range.contains(&candle.open) || !range.contains(&candle.close) {
+            return Err(Rejection::OutsideRange);
+        }
+
+        let ahead = candle.start.saturating_sub(now);
+        if ahead > self.max_future {
+            return Err(Rejection::InFuture(ahead));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Well formed candle starting at `start`.
+    fn valid(start: u64) -> Candle {
+        Candle {
+            start,
+            open: 10.0,
+            high: 12.0,
+            low: 9.0,
+            close: 11.0,
+            volume: 5.0,
+        }
+    }
+
+    #[test]
+    fn accepts_a_well_formed_candle() {
+        assert_eq!(CandleRules::default().check(&valid(0), 0), Ok(()));
+    }
+
+    #[test]
+    fn rejects_prices_and_volumes_that_are_not_numbers() {
+        let rules = CandleRules::default();
+        let candle = Candle {
+            close: f64::NAN,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::NotFinite));
+
+        let candle = Candle {
+            volume: f64::INFINITY,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::NotFinite));
+    }
+
+    #[test]
+    fn rejects_prices_that_are_not_positive() {
+        let rules = CandleRules::default();
+        let candle = Candle {
+            low: 0.0,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::NonPositivePrice));
+
+        let candle = Candle {
+            open: -1.0,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::NonPositivePrice));
+    }
+
+    #[test]
+    fn rejects_negative_volume_and_zero_volume_when_not_allowed() {
+        let rules = CandleRules::default();
+        let candle = Candle {
+            volume: -1.0,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::InvalidVolume(-1.0)));
+
+        let quiet = Candle {
+            volume: 0.0,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&quiet, 0), Ok(()));
+
+        let rules = CandleRules {
+            allow_zero_volume: false,
+            ..Default::default()
+        };
+        assert_eq!(rules.check(&quiet, 0), Err(Rejection::InvalidVolume(0.0)));
+    }
+
+    #[test]
+    fn rejects_a_high_below_the_low() {
+        let candle = Candle {
+            high: 8.0,
+            ..valid(0)
+        };
+        assert_eq!(
+            CandleRules::default().check(&candle, 0),
+            Err(Rejection::HighBelowLow {
+                high: 8.0,
+                low: 9.0
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_an_open_or_close_outside_the_range() {
+        let rules = CandleRules::default();
+        let candle = Candle {
+            open: 13.0,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::OutsideRange));
+
+        let candle = Candle {
+            close: 8.5,
+            ..valid(0)
+        };
+        assert_eq!(rules.check(&candle, 0), Err(Rejection::OutsideRange));
+    }
+
+    #[test]
+    fn rejects_candles_starting_too_far_in_the_future() {
+        let rules = CandleRules::default();
+        // Clock skew within `max_future` is allowed.
+        assert_eq!(rules.check(&valid(1_300), 1_000), Ok(()));
+        assert_eq!(
+            rules.check(&valid(1_301), 1_000),
+            Err(Rejection::InFuture(301))
+        );
+    }
+
+    #[test]
+    fn accepts_anything_when_disabled() {
+        let rules = CandleRules {
+            enabled: false,
+            ..Default::default()
+        };
+        let candle = Candle {
+            high: f64::NAN,
+            ..valid(u64::MAX)
+        };
+        assert_eq!(rules.check(&candle, 0), Ok(()));
+    }
+}
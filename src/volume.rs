@@ -0,0 +1,101 @@
+//! Detects completed candles with unusually high volume.
+
+use cbadv::product::Candle;
+use std::collections::{HashMap, VecDeque};
+use tracing::info;
+
+/// Volume of a completed candle that exceeded the trailing average.
+#[derive(Debug, Clone)]
+pub struct VolumeSpike {
+    /// Product the candle belongs to.
+    pub product_id: String,
+    /// Start of the candle.
+    pub candle_start: u64,
+    /// Volume of the candle.
+    pub volume: f64,
+    /// Mean volume of the trailing candles.
+    pub mean: f64,
+    /// Standard deviation of the volume of the trailing candles.
+    pub stddev: f64,
+    /// Volume as a multiple of the mean.
+    pub ratio: f64,
+}
+
+/// Receives volume spikes as they are detected.
+pub trait VolumeSpikeSink {
+    /// Called for each completed candle whose volume exceeds the multiplier.
+    fn on_spike(&mut self, spike: &VolumeSpike);
+}
+
+/// Prints each volume spike.
+pub struct LogVolumeSpikeSink;
+
+impl VolumeSpikeSink for LogVolumeSpikeSink {
+    fn on_spike(&mut self, spike: &VolumeSpike) {
+        info!(
+            product_id = %spike.product_id,
+            start = spike.candle_start,
+            volume = spike.volume,
+            mean = spike.mean,
+            stddev = spike.stddev,
+            "Volume spike, {:.2}x the trailing average.",
+            spike.ratio
+        );
+    }
+}
+
+/// Compares the volume of completed candles against a rolling window of the previous
+/// candles of each product.
+pub struct VolumeSpikeDetector {
+    /// Trailing candles averaged, no spikes are reported until this many completed.
+    window: usize,
+    /// Smallest multiple of the mean volume that is reported.
+    multiplier: f64,
+    /// Volumes of the trailing candles for each product, oldest first.
+    volumes: HashMap<String, VecDeque<f64>>,
+    /// Receives detected spikes.
+    sink: Box<dyn VolumeSpikeSink + Send>,
+}
+
+impl VolumeSpikeDetector {
+    /// Creates a detector over `window` candles passing spikes to `sink`.
+    pub fn new(window: usize, multiplier: f64, sink: Box<dyn VolumeSpikeSink + Send>) -> Self {
+        Self {
+            window,
+            multiplier,
+            volumes: HashMap::new(),
+            sink,
+        }
+    }
+
+    /// Checks a completed candle against the trailing window before adding it.
+    pub fn check(&mut self, product_id: &str, candle: &Candle) {
+        let volumes = self.volumes.entry(product_id.to_string()).or_default();
+
+        if volumes.len() >= self.window {
+            let mean = volumes.iter().sum::<f64>() / volumes.len() as f64;
+            if mean > 0.0 && candle.volume >= mean * self.multiplier {
+                let variance =
+                    volumes.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / volumes.len() as f64;
+                self.sink.on_spike(&VolumeSpike {
+                    product_id: product_id.to_string(),
+                    candle_start: candle.start,
+                    volume: candle.volume,
+                    mean,
+                    stddev: variance.sqrt(),
+                    ratio: candle.volume / mean,
+                });
+            }
+        }
+
+        volumes.push_back(candle.volume);
+        if volumes.len() > self.window {
+            volumes.pop_front();
+        }
+    }
+
+    /// Forgets the trailing volumes of a product.
+    pub fn reset(&mut self, product_id: &str) {
+        self.volumes.remove(product_id);
+    }
+}
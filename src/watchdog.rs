@@ -0,0 +1,78 @@
+//! Detects products that stopped receiving candles.
+
+use crate::sink::CandleSink;
+use crate::TrackerHandle;
+
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time;
+use tracing::warn;
+
+/// A product whose most recent candle is older than the staleness threshold.
+#[derive(Debug, Clone)]
+pub struct Stale {
+    /// Product that stopped receiving candles.
+    pub product_id: String,
+    /// Start of the most recent candle received for the product.
+    pub last_start: u64,
+    /// Seconds between `last_start` and the check.
+    pub age: u64,
+}
+
+/// Receives products as they become stale.
+pub trait StaleSink {
+    /// Called once when a product becomes stale, again only after it has recovered.
+    fn on_stale(&mut self, stale: &Stale);
+
+    /// Called when a stale product receives a newer candle.
+    fn on_recovered(&mut self, _product_id: &str) {}
+}
+
+/// Prints each stale product.
+pub struct LogStaleSink;
+
+impl StaleSink for LogStaleSink {
+    fn on_stale(&mut self, stale: &Stale) {
+        warn!(
+            product_id = %stale.product_id,
+            start = stale.last_start,
+            "no candles received for {} minutes, product is stale.",
+            stale.age / 60
+        );
+    }
+}
+
+/// Checks the most recent candle of each product every `interval`, products with a
+/// candle older than `stale_after` seconds are passed to the sink. Runs until aborted.
+pub async fn watch<S: CandleSink + Send + 'static>(
+    tracker: TrackerHandle<S>,
+    stale_after: u64,
+    interval: Duration,
+    mut sink: Box<dyn StaleSink + Send>,
+) {
+    let mut ticker = time::interval(interval);
+    let mut stale: HashSet<String> = HashSet::new();
+
+    loop {
+        ticker.tick().await;
+
+        // Copied so the tracker is only locked briefly.
+        let starts = tracker.latest_starts();
+        let now = tracker.now();
+
+        for (product_id, last_start) in starts {
+            let age = now.saturating_sub(last_start);
+            if age > stale_after {
+                if stale.insert(product_id.clone()) {
+                    sink.on_stale(&Stale {
+                        product_id,
+                        last_start,
+                        age,
+                    });
+                }
+            } else if stale.remove(&product_id) {
+                sink.on_recovered(&product_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! POSTs completed candles to an HTTP endpoint.
+
+use crate::sink::{CandleInfo, CandleRecord, CandleSink};
+
+use cbadv::product::Candle;
+use reqwest::Client;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use tracing::{error, warn};
+
+/// Maximum candles waiting to be sent, the oldest are dropped beyond this.
+const QUEUE_CAPACITY: usize = 1_000;
+/// Attempts made to send a candle before giving up on it.
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled for each following retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Maximum time to wait for the endpoint to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum time to wait for queued candles to be sent when flushing.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Candles waiting to be sent, shared with the sending task.
+struct Queue {
+    /// Pending candles, oldest first.
+    candles: Mutex<VecDeque<CandleRecord>>,
+    /// Wakes the sending task when candles are queued.
+    notify: Notify,
+    /// Whether a candle taken from the queue is still being sent.
+    sending: AtomicBool,
+    /// Signalled each time the sending task finishes with a candle.
+    sent: Condvar,
+}
+
+impl Queue {
+    /// Takes the oldest candle to send, marking it as being sent.
+    fn pop(&self) -> Option<CandleRecord> {
+        let mut candles = self.candles.lock().unwrap();
+        let record = candles.pop_front();
+        self.sending.store(record.is_some(), Ordering::SeqCst);
+        record
+    }
+
+    /// Marks the candle being sent as finished, successful or not.
+    fn done(&self) {
+        let _candles = self.candles.lock().unwrap();
+        self.sending.store(false, Ordering::SeqCst);
+        self.sent.notify_all();
+    }
+}
+
+/// Sends each completed candle as JSON to a webhook. Sending happens on a separate
+/// task so a slow or failing endpoint never blocks candle processing.
+pub struct WebhookSink {
+    queue: Arc<Queue>,
+}
+
+impl WebhookSink {
+    /// Creates the sink and spawns the task that sends to `url`.
+    pub fn new(url: String) -> Self {
+        let queue = Arc::new(Queue {
+            candles: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            sending: AtomicBool::new(false),
+            sent: Condvar::new(),
+        });
+
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        tokio::spawn(send_loop(client, url, Arc::clone(&queue)));
+        Self { queue }
+    }
+}
+
+impl CandleSink for WebhookSink {
+    fn on_candle(&mut self, product_id: &str, candle: &Candle, info: &CandleInfo) {
+        if !info.complete || !info.is_native() {
+            return;
+        }
+
+        let mut candles = self.queue.candles.lock().unwrap();
+        candles.push_back(CandleRecord::new(product_id, candle, info));
+        if candles.len() > QUEUE_CAPACITY {
+            if let Some(dropped) = candles.pop_front() {
+                warn!(
+                    "Webhook queue is full, dropped candle for {} ({}).",
+                    dropped.product_id, dropped.start
+                );
+            }
+        }
+        drop(candles);
+
+        self.queue.notify.notify_one();
+    }
+
+    fn flush(&mut self) {
+        let candles = self.queue.candles.lock().unwrap();
+        let (candles, _) = self
+            .queue
+            .sent
+            .wait_timeout_while(candles, FLUSH_TIMEOUT, |candles| {
+                !candles.is_empty() || self.queue.sending.load(Ordering::SeqCst)
+            })
+            .unwrap();
+
+        if !candles.is_empty() || self.queue.sending.load(Ordering::SeqCst) {
+            error!(
+                "Webhook did not flush in time, {} candles still queued.",
+                candles.len()
+            );
+        }
+    }
+}
+
+/// Sends queued candles one at a time, retrying failures with backoff.
+async fn send_loop(client: Client, url: String, queue: Arc<Queue>) {
+    loop {
+        let next = queue.pop();
+        let record = match next {
+            Some(record) => record,
+            None => {
+                queue.notify.notified().await;
+                continue;
+            }
+        };
+
+        let mut backoff = RETRY_BACKOFF;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let result = client
+                .post(&url)
+                .json(&record)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status());
+
+            match result {
+                Ok(_) => break,
+                Err(err) => {
+                    warn!(
+                        "Webhook failed for {} ({}), attempt {}/{}: {}",
+                        record.product_id, record.start, attempt, MAX_ATTEMPTS, err
+                    );
+                    if attempt < MAX_ATTEMPTS {
+                        sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                }
+            }
+        }
+        queue.done();
+    }
+}
@@ -0,0 +1,74 @@
+//! Publishes candles to the NATS server at `NATS_TEST_URL`, requires the `nats`
+//! feature. Skipped unless the variable is set, such as with
+//! `NATS_TEST_URL=nats://127.0.0.1:4222 cargo test --features nats --test nats`.
+#![cfg(feature = "nats")]
+
+use candle_watcher::nats_sink::NatsSink;
+use candle_watcher::sink::{CandleInfo, CandleSink};
+
+use cbadv::product::Candle;
+use futures::StreamExt;
+use std::env;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// Longest wait for a candle to be published.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Candle starting at `start`.
+fn candle(start: u64, close: f64) -> Candle {
+    Candle {
+        start,
+        open: 1.0,
+        high: 2.0,
+        low: 0.5,
+        close,
+        volume: 10.0,
+    }
+}
+
+#[tokio::test]
+async fn publishes_completed_candles_to_the_product_subject() {
+    let url = match env::var("NATS_TEST_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("NATS_TEST_URL is not set, skipping.");
+            return;
+        }
+    };
+
+    // Subjects of their own so concurrent runs do not see each other's candles.
+    let prefix = format!("candles-test-{}", std::process::id());
+    let client = async_nats::connect(&url).await.expect("connect to NATS");
+    let mut subscriber = client
+        .subscribe(format!("{}.>", prefix))
+        .await
+        .expect("subscribe");
+    client.flush().await.expect("flush the subscription");
+
+    let mut sink = NatsSink::new(url, prefix.clone(), None);
+    let complete = CandleInfo {
+        quote: "USD".to_string(),
+        complete: true,
+        ..Default::default()
+    };
+    // In-progress candles flushed on shutdown are not published.
+    sink.on_candle(
+        "BTC-USD",
+        &candle(1_700_000_400, 1.2),
+        &CandleInfo::default(),
+    );
+    sink.on_candle("BTC-USD", &candle(1_700_000_100, 1.5), &complete);
+
+    let message = timeout(PUBLISH_TIMEOUT, subscriber.next())
+        .await
+        .expect("candle published in time")
+        .expect("subscription open");
+    assert_eq!(message.subject.to_string(), format!("{}.BTC-USD", prefix));
+
+    let json: serde_json::Value = serde_json::from_slice(&message.payload).expect("JSON candle");
+    assert_eq!(json["product_id"], "BTC-USD");
+    assert_eq!(json["quote"], "USD");
+    assert_eq!(json["start"], 1_700_000_100);
+    assert_eq!(json["close"], 1.5);
+}